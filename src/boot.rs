@@ -1,12 +1,78 @@
 //! Instructions used to boot Instrumentality.
 use std::fs::File;
 use std::io::Write;
+use std::net::SocketAddr;
 
+use crate::concepts::user::User;
 use crate::config;
+use crate::database;
+use crate::routes::subjects::import::{self, ImportFormat, ImportMode};
 use crate::server;
 
 pub const CONFIG_FILE_NAME: &str = "Instrumentality.toml";
 pub const EXAMPLE_CONFIG_FILE_NAME: &str = "InstrumentalityExample.toml";
+pub const IMPORT_USAGE: &str =
+    "Usage: instrumentality import <FILE> --user <UUID> \
+    [--format csv|json] [--best-effort]";
+
+/// Imports subjects from a CSV or JSON file for a user, as /subjects/import
+/// does, and prints the report. The format is taken from the file's extension
+/// if it isn't given.
+pub async fn import(args: &[String]) {
+    let mut file = None;
+    let mut user = None;
+    let mut format = None;
+    let mut mode = ImportMode::Transactional;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--user" => user = args.next().cloned(),
+            "--format" => format = args.next().cloned(),
+            "--best-effort" => mode = ImportMode::BestEffort,
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => exit(IMPORT_USAGE),
+        }
+    }
+    let (Some(file), Some(user)) = (file, user) else {
+        exit(IMPORT_USAGE);
+    };
+    let format = match format.as_deref() {
+        Some("csv") => ImportFormat::Csv,
+        Some("json") => ImportFormat::Json,
+        Some(_) => exit(IMPORT_USAGE),
+        None if file.ends_with(".json") => ImportFormat::Json,
+        None => ImportFormat::Csv,
+    };
+
+    let Ok(config) = config::open(CONFIG_FILE_NAME) else {
+        exit(&format!("Couldn't load {CONFIG_FILE_NAME}."));
+    };
+    server::build_tracing(&config.settings.log_level);
+    let text = std::fs::read_to_string(&file)
+        .unwrap_or_else(|e| exit(&format!("Couldn't read {file}: {e}.")));
+    let rows =
+        import::parse(format, &text).unwrap_or_else(|e| exit(e.as_str()));
+
+    let db_pool = database::open(&config).await.unwrap();
+    let mut db = db_pool.handle_with_started_transaction().await;
+    let Some(user) = User::with_uuid(&user, &mut db).await else {
+        exit("User does not exist.");
+    };
+    let report = import::import_rows(rows, mode, &user, &config, &mut db).await;
+    let failed = mode == ImportMode::Transactional && !report.errors.is_empty();
+    if !failed {
+        db.session.commit_transaction().await.unwrap();
+    }
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    if failed {
+        exit("Nothing was imported because some rows have errors.");
+    }
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
+}
 
 pub async fn instrumentality() {
     let config = config::open(CONFIG_FILE_NAME);
@@ -19,7 +85,7 @@ pub async fn instrumentality() {
 
         let server = axum_server::bind_rustls(addr, tls_config)
             .handle(handle)
-            .serve(app.into_make_service());
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
 
         tracing::info!("READY: https://{:?}.", addr);
         server.await.unwrap();
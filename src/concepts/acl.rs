@@ -0,0 +1,263 @@
+//! Sharing of subjects and groups between users.
+//!
+//! Every subject and group has an owner, the user that created it, who may
+//! share it with other users as an editor or a viewer:
+//! - owners can do anything, including deleting and sharing.
+//! - editors can update the subject or group.
+//! - viewers can read the subject or group and its data.
+//!
+//! Sharing a group lets its editors and viewers select subjects through it,
+//! but never widens what they can read: only the subjects in it, and in the
+//! groups nested in it, that they can read themselves are selected.
+//!
+//! The visibility of a subject or group overrides its sharing. A private
+//! subject can only be seen by its owner, even if it was shared, and a public
+//! subject can be seen by every user and without a key through /public/view.
+//! A public group serves the subjects and nested groups in it that are public
+//! or that belong to its owner and aren't private.
+//!
+//! Every route that reads subjects should select them through the filters
+//! here, or through [`visible_subjects`] where asking for a subject that can't
+//! be seen is an error.
+
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::group::Group;
+use crate::concepts::subject::Subject;
+use crate::database::DBHandle;
+
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Private,
+    #[default]
+    Shared,
+    Public,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Editor,
+    Viewer,
+}
+
+impl Role {
+    fn field(&self) -> &'static str {
+        match self {
+            Role::Editor => "editors",
+            Role::Viewer => "viewers",
+        }
+    }
+}
+
+/// Narrows a filter to the subjects or groups the user can read.
+pub fn readable_by(filter: Document, user: &str) -> Document {
+    doc! {"$and": [filter, {"$or": [
+        {"created_by": user},
+        {"visibility": "public"},
+        {"editors": user, "visibility": {"$ne": "private"}},
+        {"viewers": user, "visibility": {"$ne": "private"}}
+    ]}]}
+}
+
+/// Narrows a filter to the subjects or groups the user can update.
+pub fn editable_by(filter: Document, user: &str) -> Document {
+    doc! {"$and": [filter, {"$or": [
+        {"created_by": user},
+        {"editors": user, "visibility": {"$ne": "private"}}
+    ]}]}
+}
+
+/// Narrows a filter to the subjects or groups shared with the user by others.
+pub fn shared_with(filter: Document, user: &str) -> Document {
+    doc! {"$and": [filter, {"visibility": {"$ne": "private"}}, {"$or": [
+        {"editors": user},
+        {"viewers": user}
+    ]}]}
+}
+
+/// The subjects with the given UUIDs and the subjects in the groups with the
+/// given UUIDs, or the UUIDs of the subjects and groups that don't exist or
+/// can't be seen by the user if there are any.
+pub async fn visible_subjects(
+    subjects: &[String],
+    groups: &[String],
+    user: &str,
+    db: &mut DBHandle,
+) -> Result<Vec<Subject>, Vec<String>> {
+    match readable_subjects(subjects, groups, user, db).await {
+        (subjects, invisible) if invisible.is_empty() => Ok(subjects),
+        (_, invisible) => Err(invisible),
+    }
+}
+
+/// The subjects with the given UUIDs and the subjects in the groups with the
+/// given UUIDs that the user can see, and the UUIDs of the subjects and groups
+/// that don't exist or can't be seen.
+pub async fn readable_subjects(
+    subjects: &[String],
+    groups: &[String],
+    user: &str,
+    db: &mut DBHandle,
+) -> (Vec<Subject>, Vec<String>) {
+    let access = readable_by(doc! {}, user);
+    select(subjects, groups, &access, &access, |_| access.clone(), db).await
+}
+
+/// The public subjects with the given UUIDs and the subjects in the public
+/// groups with the given UUIDs, or the UUIDs of the subjects and groups that
+/// don't exist or aren't public if there are any.
+pub async fn public_subjects(
+    subjects: &[String],
+    groups: &[String],
+    db: &mut DBHandle,
+) -> Result<Vec<Subject>, Vec<String>> {
+    let access = doc! {"visibility": "public"};
+    let grouped_access = |group: &Group| {
+        doc! {"$or": [
+            {"visibility": "public"},
+            {"created_by": &group.created_by, "visibility": {"$ne": "private"}}
+        ]}
+    };
+    match select(subjects, groups, &access, &access, grouped_access, db).await {
+        (subjects, invisible) if invisible.is_empty() => Ok(subjects),
+        (_, invisible) => Err(invisible),
+    }
+}
+
+// Selects subjects directly and through groups and the groups nested in them,
+// subject to a filter for each. The filter for the groups nested in a group
+// selected directly, and for the subjects in them, is given by that group.
+// Returns the subjects and the UUIDs of the subjects and groups selected
+// directly that didn't match.
+async fn select(
+    subjects: &[String],
+    groups: &[String],
+    subject_access: &Document,
+    group_access: &Document,
+    grouped_access: impl Fn(&Group) -> Document,
+    db: &mut DBHandle,
+) -> (Vec<Subject>, Vec<String>) {
+    let group_coll: Collection<Group> = db.collection("groups");
+    let mut cursor = group_coll
+        .find_with_session(
+            doc! {"$and": [{"uuid": {"$in": groups}}, group_access]},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    let found_groups: Vec<Group> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+    let mut invisible: Vec<String> = groups
+        .iter()
+        .filter(|uuid| !found_groups.iter().any(|g| &&g.uuid == uuid))
+        .cloned()
+        .collect();
+
+    let mut selections =
+        vec![doc! {"$and": [{"uuid": {"$in": subjects}}, subject_access]}];
+    for group in found_groups {
+        let access = grouped_access(&group);
+        let expanded = Group::expand(vec![group], &access, db).await;
+        let grouped = Group::subjects_of(&expanded);
+        selections.push(doc! {"$and": [{"uuid": {"$in": grouped}}, access]});
+    }
+
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    let mut cursor = subj_coll
+        .find_with_session(doc! {"$or": selections}, None, &mut db.session)
+        .await
+        .unwrap();
+    let found: Vec<Subject> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+    invisible.extend(
+        subjects
+            .iter()
+            .filter(|uuid| !found.iter().any(|s| &&s.uuid == uuid))
+            .cloned(),
+    );
+    (found, invisible)
+}
+
+/// Sets the visibility of a subject or group owned by `owner`. Returns false
+/// if the owner has no such subject or group.
+pub async fn set_visibility(
+    collection: &str,
+    uuid: &str,
+    owner: &str,
+    visibility: Visibility,
+    db: &mut DBHandle,
+) -> bool {
+    let coll: Collection<Document> = db.collection(collection);
+    let result = coll
+        .update_one_with_session(
+            doc! {"uuid": uuid, "created_by": owner},
+            doc! {"$set": {
+                "visibility": mongodb::bson::to_bson(&visibility).unwrap()
+            }},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    result.matched_count == 1
+}
+
+/// Gives a user a role on a subject or group owned by `owner`, replacing any
+/// role they had. Returns false if the owner has no such subject or group.
+pub async fn share(
+    collection: &str,
+    uuid: &str,
+    owner: &str,
+    user: &str,
+    role: Role,
+    db: &mut DBHandle,
+) -> bool {
+    let other = match role {
+        Role::Editor => Role::Viewer,
+        Role::Viewer => Role::Editor,
+    };
+    let coll: Collection<Document> = db.collection(collection);
+    let result = coll
+        .update_one_with_session(
+            doc! {"uuid": uuid, "created_by": owner},
+            doc! {
+                "$addToSet": {role.field(): user},
+                "$pull": {other.field(): user}
+            },
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    result.matched_count == 1
+}
+
+/// Removes a user's role on a subject or group owned by `owner`. Returns false
+/// if the owner has no such subject or group.
+pub async fn unshare(
+    collection: &str,
+    uuid: &str,
+    owner: &str,
+    user: &str,
+    db: &mut DBHandle,
+) -> bool {
+    let coll: Collection<Document> = db.collection(collection);
+    let result = coll
+        .update_one_with_session(
+            doc! {"uuid": uuid, "created_by": owner},
+            doc! {"$pull": {"editors": user, "viewers": user}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    result.matched_count == 1
+}
@@ -0,0 +1,485 @@
+//! Consensus between data providers for queue jobs.
+//!
+//! Instrumentality has no way of checking that data submitted by a provider is
+//! accurate. A lazy provider might post the minimum subset of a profile and a
+//! malicious one might post fabricated data. When consensus is enabled in the
+//! configuration, each queue job is instead given to several different
+//! providers in turn. Their submissions are held back until every provider has
+//! submitted, then compared field by field.
+//!
+//! Items are matched across submissions by what they describe: the profile
+//! for metadata, the content ID for content and the presence type for
+//! presence. An item is only committed if at least a quorum of providers
+//! submitted it, and each of its fields takes the value agreed on by at least
+//! a quorum of those providers. Fields without a quorum are left empty, and
+//! items missing a required field are dropped. The time of retrieval is taken
+//! from the most recent submission rather than compared, since no two
+//! providers retrieve data at the same instant.
+//!
+//! Every value a provider submitted that does not match the committed value is
+//! recorded as a [`Disagreement`] against that provider, as is every item that
+//! a provider submitted without a quorum or failed to submit with one. These
+//! count against the provider's
+//! [`Reputation`](crate::concepts::reputation::Reputation).
+//!
+//! Committed data is tagged as being added by [`CONSENSUS_PROVIDER`].
+//!
+//! While consensus is enabled, only admins may submit data without a queue
+//! job, since nothing else would check it.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::data::Data;
+use crate::concepts::reputation::{self, PendingAssessment};
+use crate::config::ConsensusConfig;
+use crate::database::DBHandle;
+use crate::routes::queue::InternalQueueItem;
+
+/// The `added_by` tag of data committed through consensus.
+pub const CONSENSUS_PROVIDER: &str = "consensus";
+
+// Fields that are expected to differ between providers.
+const IGNORED_FIELDS: [&str; 3] = ["added_by", "added_at", "retrieved_at"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Submission {
+    pub queue_id: String,
+    pub provider: String,
+    pub data: Vec<Data>,
+    pub submitted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Disagreement {
+    pub provider: String,
+    pub queue_id: String,
+    /// The item disagreed on, e.g. "content:twitter:123:tweet:456".
+    pub item: String,
+    /// The field disagreed on. None if the provider disagreed on whether the
+    /// item exists at all.
+    pub field: Option<String>,
+    /// What the provider submitted. None if it did not submit the item.
+    pub submitted: Option<Bson>,
+    /// What was committed. None if the item was not committed.
+    pub committed: Option<Bson>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct Resolution {
+    pub data: Vec<Data>,
+    pub disagreements: Vec<Disagreement>,
+}
+
+/// Submit data for a queue job. The submission is held until the job has been
+/// done by enough providers, at which point the round is resolved and the
+/// agreed data is returned to be committed and the providers' disagreements
+/// are added to `pending`. Otherwise, the provider's lock on the job is
+/// released so it can be given to the next provider.
+pub async fn submit(
+    queue_id: &str,
+    provider: &str,
+    data: Vec<Data>,
+    config: &ConsensusConfig,
+    pending: &mut Vec<PendingAssessment>,
+    db: &mut DBHandle,
+) -> Option<Resolution> {
+    let sub_coll: Collection<Submission> = db.collection("submissions");
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+
+    let submission = Submission {
+        queue_id: queue_id.to_string(),
+        provider: provider.to_string(),
+        data,
+        submitted_at: Utc::now(),
+    };
+    sub_coll
+        .insert_one_with_session(&submission, None, &mut db.session)
+        .await
+        .unwrap();
+
+    let mut cursor = sub_coll
+        .find_with_session(doc! {"queue_id": queue_id}, None, &mut db.session)
+        .await
+        .unwrap();
+    let submissions: Vec<Submission> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+
+    if submissions.len() < config.providers() {
+        q_coll
+            .update_one_with_session(
+                doc! {"queue_id": queue_id, "lock_holder": provider},
+                doc! {
+                    "$set": {"lock_holder": Bson::Null,
+                        "lock_acquired_at": Bson::Null},
+                    "$push": {"round_providers": provider}
+                },
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+        return None;
+    }
+
+    let resolution = resolve(&submissions, config.quorum());
+
+    let items: HashSet<String> = submissions
+        .iter()
+        .flat_map(|s| s.data.iter().map(item_key))
+        .collect();
+    let assessments = reputation::from_disagreements(
+        submissions.iter().map(|s| s.provider.as_str()),
+        items.len() as u64,
+        resolution
+            .disagreements
+            .iter()
+            .map(|d| (d.provider.as_str(), d.item.as_str())),
+    );
+    pending.extend(assessments.into_iter().map(|(provider, assessment)| {
+        PendingAssessment {
+            provider,
+            item: None,
+            assessment,
+        }
+    }));
+
+    if !resolution.disagreements.is_empty() {
+        let dis_coll: Collection<Disagreement> = db.collection("disagreements");
+        dis_coll
+            .insert_many_with_session(
+                &resolution.disagreements,
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+    }
+    sub_coll
+        .delete_many_with_session(
+            doc! {"queue_id": queue_id},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    q_coll
+        .update_one_with_session(
+            doc! {"queue_id": queue_id},
+            doc! {"$set": {"round_providers": []}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+
+    Some(resolution)
+}
+
+/// Compare the submissions of a round, returning the data agreed on by at
+/// least `quorum` providers and every disagreement with it.
+pub fn resolve(submissions: &[Submission], quorum: usize) -> Resolution {
+    let now = Utc::now();
+    let queue_id = submissions
+        .first()
+        .map(|s| s.queue_id.clone())
+        .unwrap_or_default();
+    let disagreement =
+        |provider: &str,
+         item: &str,
+         field: Option<&str>,
+         submitted: Option<Bson>,
+         committed: Option<Bson>| Disagreement {
+            provider: provider.to_string(),
+            queue_id: queue_id.clone(),
+            item: item.to_string(),
+            field: field.map(|f| f.to_string()),
+            submitted,
+            committed,
+            recorded_at: now,
+        };
+
+    // Every item described by any submission, in order of first appearance,
+    // with each provider's version of it.
+    let mut items: Vec<(String, Vec<(&str, &Data)>)> = Vec::new();
+    for submission in submissions {
+        for data in &submission.data {
+            let key = item_key(data);
+            let entry = match items.iter_mut().find(|(k, _)| k == &key) {
+                Some(entry) => entry,
+                None => {
+                    items.push((key, Vec::new()));
+                    items.last_mut().unwrap()
+                }
+            };
+            if entry.1.iter().all(|(p, _)| *p != submission.provider) {
+                entry.1.push((&submission.provider, data));
+            }
+        }
+    }
+
+    let mut data = Vec::new();
+    let mut disagreements = Vec::new();
+    for (key, versions) in items {
+        let docs: Vec<(&str, Document)> = versions
+            .iter()
+            .map(|(p, d)| (*p, bson::to_document(d).unwrap()))
+            .collect();
+
+        if versions.len() < quorum {
+            for (provider, doc) in docs {
+                disagreements.push(disagreement(
+                    provider,
+                    &key,
+                    None,
+                    Some(Bson::Document(doc)),
+                    None,
+                ));
+            }
+            continue;
+        }
+
+        let mut fields: Vec<&str> = Vec::new();
+        for (_, doc) in &docs {
+            for field in doc.keys() {
+                if !IGNORED_FIELDS.contains(&field.as_str())
+                    && !fields.contains(&field.as_str())
+                {
+                    fields.push(field);
+                }
+            }
+        }
+
+        let mut canonical = Document::new();
+        let mut field_disagreements = Vec::new();
+        for field in fields {
+            let values: Vec<(&str, Bson)> = docs
+                .iter()
+                .map(|(p, d)| (*p, d.get(field).cloned().unwrap_or(Bson::Null)))
+                .collect();
+            let agreed = agreed_value(values.iter().map(|(_, v)| v), quorum);
+            for (provider, value) in &values {
+                if Some(value) != agreed.as_ref() {
+                    field_disagreements.push(disagreement(
+                        provider,
+                        &key,
+                        Some(field),
+                        Some(value.clone()),
+                        agreed.clone(),
+                    ));
+                }
+            }
+            canonical.insert(field, agreed.unwrap_or(Bson::Null));
+        }
+
+        let retrieved_at = versions.iter().map(|(_, d)| d.retrieved_at()).max();
+        canonical.insert("retrieved_at", bson::to_bson(&retrieved_at).unwrap());
+
+        match bson::from_document::<Data>(canonical.clone()) {
+            Ok(agreed) => {
+                disagreements.append(&mut field_disagreements);
+                for submission in submissions {
+                    if versions.iter().all(|(p, _)| *p != submission.provider) {
+                        disagreements.push(disagreement(
+                            &submission.provider,
+                            &key,
+                            None,
+                            None,
+                            Some(Bson::Document(canonical.clone())),
+                        ));
+                    }
+                }
+                data.push(agreed.tag(CONSENSUS_PROVIDER));
+            }
+            // A required field had no quorum, so the item can't be committed.
+            Err(_) => {
+                for (provider, doc) in docs {
+                    disagreements.push(disagreement(
+                        provider,
+                        &key,
+                        None,
+                        Some(Bson::Document(doc)),
+                        None,
+                    ));
+                }
+            }
+        }
+    }
+
+    Resolution {
+        data,
+        disagreements,
+    }
+}
+
+// The value given by at least `quorum` of the values, if any.
+fn agreed_value<'a>(
+    values: impl Iterator<Item = &'a Bson>,
+    quorum: usize,
+) -> Option<Bson> {
+    let mut tally: Vec<(&Bson, usize)> = Vec::new();
+    for value in values {
+        match tally.iter_mut().find(|(v, _)| *v == value) {
+            Some((_, count)) => *count += 1,
+            None => tally.push((value, 1)),
+        }
+    }
+    tally
+        .into_iter()
+        .filter(|(_, count)| *count >= quorum)
+        .max_by_key(|(_, count)| *count)
+        .map(|(value, _)| value.clone())
+}
+
+/// What an item describes, used to match it across submissions.
+pub fn item_key(data: &Data) -> String {
+    match data {
+        Data::Meta { platform, id, .. } => format!("meta:{platform}:{id}"),
+        Data::Content {
+            platform,
+            id,
+            content_type,
+            content_id,
+            ..
+        } => format!("content:{platform}:{id}:{content_type}:{content_id}"),
+        Data::Presence {
+            platform,
+            id,
+            presence_type,
+            ..
+        } => format!("presence:{platform}:{id}:{presence_type}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn meta(bio: Option<&str>, private: bool) -> Data {
+        Data::Meta {
+            id: "123".to_string(),
+            platform: "twitter".to_string(),
+            username: "someone".to_string(),
+            private,
+            suspended_or_banned: false,
+            retrieved_at: Utc::now(),
+            display_name: None,
+            profile_picture: None,
+            bio: bio.map(|b| b.to_string()),
+            verified: None,
+            references: None,
+            link: None,
+            added_by: None,
+            added_at: None,
+        }
+    }
+
+    fn content(content_id: &str) -> Data {
+        Data::Content {
+            id: "123".to_string(),
+            platform: "twitter".to_string(),
+            content_type: "tweet".to_string(),
+            retrieved_at: Utc::now(),
+            content_id: content_id.to_string(),
+            deleted: Some(false),
+            retrieved_from: None,
+            created_at: None,
+            body: Some("Hello.".to_string()),
+            media: None,
+            references: None,
+            added_by: None,
+            added_at: None,
+        }
+    }
+
+    fn submission(provider: &str, data: Vec<Data>) -> Submission {
+        Submission {
+            queue_id: "queue".to_string(),
+            provider: provider.to_string(),
+            data: data.into_iter().map(|d| d.tag(provider)).collect(),
+            submitted_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_field_disagreement() {
+        let submissions = vec![
+            submission("a", vec![meta(Some("Hello."), false)]),
+            submission("b", vec![meta(Some("Hello."), false)]),
+            submission("c", vec![meta(Some("Goodbye."), false)]),
+        ];
+
+        let resolution = resolve(&submissions, 2);
+
+        assert_eq!(resolution.data.len(), 1);
+        match &resolution.data[0] {
+            Data::Meta { bio, added_by, .. } => {
+                assert_eq!(bio.as_deref(), Some("Hello."));
+                assert_eq!(added_by.as_deref(), Some(CONSENSUS_PROVIDER));
+            }
+            _ => panic!("Expected Data::Meta."),
+        }
+        assert_eq!(resolution.disagreements.len(), 1);
+        assert_eq!(resolution.disagreements[0].provider, "c");
+        assert_eq!(resolution.disagreements[0].field.as_deref(), Some("bio"));
+    }
+
+    #[test]
+    fn test_resolve_required_field_without_quorum() {
+        let submissions = vec![
+            submission("a", vec![meta(None, false)]),
+            submission("b", vec![meta(None, true)]),
+        ];
+
+        let resolution = resolve(&submissions, 2);
+
+        assert!(resolution.data.is_empty());
+        assert_eq!(resolution.disagreements.len(), 2);
+        assert!(resolution.disagreements.iter().all(|d| d.field.is_none()));
+    }
+
+    #[test]
+    fn test_resolve_items_without_quorum() {
+        let submissions = vec![
+            submission("a", vec![content("1"), content("2")]),
+            submission("b", vec![content("1")]),
+            submission("c", vec![content("1"), content("3")]),
+        ];
+
+        let resolution = resolve(&submissions, 2);
+
+        assert_eq!(resolution.data.len(), 1);
+        assert_eq!(
+            item_key(&resolution.data[0]),
+            "content:twitter:123:tweet:1"
+        );
+        let providers: Vec<&str> = resolution
+            .disagreements
+            .iter()
+            .map(|d| d.provider.as_str())
+            .collect();
+        assert_eq!(providers, vec!["a", "c"]);
+    }
+
+    #[test]
+    fn test_resolve_missing_item() {
+        let submissions = vec![
+            submission("a", vec![content("1")]),
+            submission("b", vec![content("1")]),
+            submission("c", vec![]),
+        ];
+
+        let resolution = resolve(&submissions, 2);
+
+        assert_eq!(resolution.data.len(), 1);
+        assert_eq!(resolution.disagreements.len(), 1);
+        assert_eq!(resolution.disagreements[0].provider, "c");
+        assert!(resolution.disagreements[0].submitted.is_none());
+    }
+}
@@ -9,10 +9,10 @@
 //! ability to determine that all the previous data was not removed by the user
 //! between posts. This also applies to content.
 //!
-//! In the future, profiles with lots of coverage could be used to sniff out
-//! lazy data providers automatically or through a reputation system. However,
-//! at this stage, data providers posting all available data is key to the
-//! utility of the platform.
+//! Profiles with lots of coverage are used to sniff out lazy data providers
+//! through a reputation system, see [`crate::concepts::reputation`]. However,
+//! data providers posting all available data is still key to the utility of
+//! the platform.
 //!
 //! # Content
 //! Content exists to represent any event occurring at a discrete point in time.
@@ -253,6 +253,22 @@ impl Data {
         }
     }
 
+    pub fn retrieved_at(&self) -> DateTime<Utc> {
+        match self {
+            Data::Presence { retrieved_at, .. }
+            | Data::Content { retrieved_at, .. }
+            | Data::Meta { retrieved_at, .. } => *retrieved_at,
+        }
+    }
+
+    pub fn added_by(&self) -> Option<&str> {
+        match self {
+            Data::Presence { added_by, .. }
+            | Data::Content { added_by, .. }
+            | Data::Meta { added_by, .. } => added_by.as_deref(),
+        }
+    }
+
     pub fn verify(&self, config: &IConfig) -> bool {
         match self {
             Data::Presence {
@@ -0,0 +1,242 @@
+//! Edges between profiles for the reference graph.
+//!
+//! Both content and metadata may carry references to other profiles, such as
+//! the profile being replied to, a mentioned profile or a linked account on
+//! another platform. Each reference is a key-value pair:
+//! - the key is the kind of reference, e.g. "mention", "reply", "retweet" or
+//!   "link". Multiple references of the same kind can be distinguished with a
+//!   suffix after a full stop, e.g. "mention.0" and "mention.1".
+//! - the value is the referenced profile, given as "platform:id". A value
+//!   without a platform refers to a profile on the same platform as the data.
+//!
+//! For example,
+//! ```json
+//! {
+//!     "references": {
+//!         "reply": "123456789",
+//!         "mention.0": "987654321",
+//!         "link": "instagram:1122334455"
+//!     }
+//! }
+//! ```
+//!
+//! Edges are directed from the profile the data is about to the referenced
+//! profile. Each piece of content that makes the same reference increments
+//! the count of its edge once, however many times it is submitted. Metadata
+//! counts once for each reference it has ever made.
+
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, doc, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::data::Data;
+use crate::database::DBHandle;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Node {
+    pub platform: String,
+    pub id: String,
+}
+
+impl Node {
+    pub fn new(platform: &str, id: &str) -> Self {
+        Self {
+            platform: platform.to_string(),
+            id: id.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Edge {
+    pub from: Node,
+    pub to: Node,
+    pub kind: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub count: u64,
+}
+
+impl Edge {
+    /// Edges described by the references of a piece of content or metadata.
+    /// Presence carries no references.
+    pub fn from_data(data: &Data) -> Vec<Self> {
+        let (platform, id, references, seen) = match data {
+            Data::Content {
+                platform,
+                id,
+                references,
+                created_at,
+                retrieved_at,
+                ..
+            } => (
+                platform,
+                id,
+                references,
+                created_at.unwrap_or(*retrieved_at),
+            ),
+            Data::Meta {
+                platform,
+                id,
+                references,
+                retrieved_at,
+                ..
+            } => (platform, id, references, *retrieved_at),
+            Data::Presence { .. } => return Vec::new(),
+        };
+
+        let mut edges = Vec::new();
+        if let Some(references) = references {
+            for (key, value) in references {
+                if let Some(to) = parse_reference(platform, value) {
+                    edges.push(Self {
+                        from: Node::new(platform, id),
+                        to,
+                        kind: reference_kind(key).to_string(),
+                        first_seen: seen,
+                        last_seen: seen,
+                        count: 1,
+                    });
+                }
+            }
+        }
+        edges
+    }
+
+    /// Record every edge described by the given data, merging them with
+    /// previous observations of the same edge.
+    pub async fn record(data: &[Data], db: &mut DBHandle) {
+        let edge_coll: Collection<Edge> = db.collection("edges");
+        let source_coll: Collection<Document> = db.collection("edge_sources");
+        let options = UpdateOptions::builder().upsert(true).build();
+        for d in data {
+            let content_id = match d {
+                Data::Content { content_id, .. } => Some(content_id),
+                _ => None,
+            };
+            for edge in Self::from_data(d) {
+                let key = doc! {
+                    "from.platform": &edge.from.platform,
+                    "from.id": &edge.from.id,
+                    "to.platform": &edge.to.platform,
+                    "to.id": &edge.to.id,
+                    "kind": &edge.kind,
+                };
+                let first_seen = bson::to_bson(&edge.first_seen).unwrap();
+                let last_seen = bson::to_bson(&edge.last_seen).unwrap();
+
+                // The data has made this reference before if its source
+                // already exists.
+                let mut source = key.clone();
+                source.insert("content_id", content_id);
+                let new_source = source_coll
+                    .update_one_with_session(
+                        source,
+                        doc! {"$setOnInsert": {"first_seen": &first_seen}},
+                        options.clone(),
+                        &mut db.session,
+                    )
+                    .await
+                    .unwrap()
+                    .upserted_id
+                    .is_some();
+
+                let count: i64 = if new_source { 1 } else { 0 };
+                edge_coll
+                    .update_one_with_session(
+                        key,
+                        doc! {
+                            "$min": {"first_seen": first_seen},
+                            "$max": {"last_seen": last_seen},
+                            "$inc": {"count": count},
+                        },
+                        options.clone(),
+                        &mut db.session,
+                    )
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    /// Edges leaving (outgoing) or arriving at (incoming) any of the given
+    /// nodes, most frequently observed first.
+    pub async fn for_nodes(
+        nodes: &[Node],
+        outgoing: bool,
+        db: &mut DBHandle,
+    ) -> Vec<Self> {
+        if nodes.is_empty() {
+            return Vec::new();
+        }
+
+        let side = if outgoing { "from" } else { "to" };
+        let matches: Vec<Document> = nodes
+            .iter()
+            .map(|n| {
+                doc! {
+                    format!("{side}.platform"): &n.platform,
+                    format!("{side}.id"): &n.id
+                }
+            })
+            .collect();
+
+        let options =
+            FindOptions::builder().sort(doc! {"count": -1_i32}).build();
+        let edge_coll: Collection<Edge> = db.collection("edges");
+        let mut cursor = edge_coll
+            .find_with_session(doc! {"$or": matches}, options, &mut db.session)
+            .await
+            .unwrap();
+        cursor.stream(&mut db.session).try_collect().await.unwrap()
+    }
+}
+
+/// The kind of a reference given its key, i.e. "mention.1" is a "mention".
+pub fn reference_kind(key: &str) -> &str {
+    key.split('.').next().unwrap_or(key)
+}
+
+/// The profile referred to by a reference value. Values without a platform
+/// refer to a profile on the given platform.
+pub fn parse_reference(platform: &str, value: &str) -> Option<Node> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    match value.split_once(':') {
+        Some((p, id)) if !p.is_empty() && !id.is_empty() => {
+            Some(Node::new(p, id))
+        }
+        Some(_) => None,
+        None => Some(Node::new(platform, value)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_reference() {
+        assert_eq!(
+            parse_reference("twitter", "instagram:123"),
+            Some(Node::new("instagram", "123"))
+        );
+        assert_eq!(
+            parse_reference("twitter", "456"),
+            Some(Node::new("twitter", "456"))
+        );
+        assert_eq!(parse_reference("twitter", ":456"), None);
+        assert_eq!(parse_reference("twitter", ""), None);
+    }
+
+    #[test]
+    fn test_reference_kind() {
+        assert_eq!(reference_kind("mention.1"), "mention");
+        assert_eq!(reference_kind("reply"), "reply");
+    }
+}
@@ -0,0 +1,545 @@
+//! Feeds of the content of subjects.
+//!
+//! The data /view assembles about a set of subjects can be rendered as an
+//! Atom, RSS or JSON Feed document so it can be followed in a feed reader.
+//! Every item of content about the subjects' profiles becomes an entry, most
+//! recent first, with its media as enclosures or attachments.
+//!
+//! A user can follow a subject they can see through /feeds/{uuid}.atom or
+//! /feeds/{uuid}.rss with a feed token, since feed readers can't send keys.
+//! These feeds also have an entry for every change to the metadata of the
+//! subject's profiles. A feed stops working if its token is revoked or the
+//! user who created it can no longer see the subject.
+
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::concepts::data::Data;
+use crate::concepts::subject::Subject;
+use crate::database::DBHandle;
+use crate::routes::view::ViewData;
+use crate::utils::random;
+
+// Metadata of each profile compared for changes.
+const MAX_META: i64 = 100;
+
+// The most entries in a feed.
+const MAX_ENTRIES: usize = 100;
+
+// Media are linked to directly and their types aren't recorded.
+const MEDIA_TYPE: &str = "application/octet-stream";
+
+// Atom feeds must name an author, and entries have none of their own.
+const AUTHOR: &str = "Instrumentality";
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+    Json,
+}
+
+impl FeedFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedEntry {
+    pub id: String,
+    pub title: String,
+    pub link: Option<String>,
+    pub body: Option<String>,
+    pub media: Vec<String>,
+    pub updated: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub id: String,
+    pub title: String,
+    pub updated: DateTime<Utc>,
+    pub entries: Vec<FeedEntry>,
+}
+
+impl Feed {
+    /// A feed of the content in the view data, most recent first.
+    pub fn from_view_data(id: &str, title: &str, view_data: &ViewData) -> Self {
+        Self::new(id, title, content_entries(view_data))
+    }
+
+    pub fn new(id: &str, title: &str, mut entries: Vec<FeedEntry>) -> Self {
+        entries.sort_by_key(|e| std::cmp::Reverse(e.updated));
+        entries.truncate(MAX_ENTRIES);
+        Self {
+            id: id.to_string(),
+            title: title.to_string(),
+            updated: entries.first().map_or_else(Utc::now, |e| e.updated),
+            entries,
+        }
+    }
+
+    pub fn render(&self, format: FeedFormat) -> String {
+        match format {
+            FeedFormat::Atom => self.atom(),
+            FeedFormat::Rss => self.rss(),
+            FeedFormat::Json => self.json(),
+        }
+    }
+
+    fn atom(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+            <feed xmlns=\"http://www.w3.org/2005/Atom\">\n",
+        );
+        xml.push_str(&format!(
+            "<id>{}</id>\n<title>{}</title>\n<updated>{}</updated>\n\
+            <author><name>{AUTHOR}</name></author>\n",
+            escape(&self.id),
+            escape(&self.title),
+            self.updated.to_rfc3339()
+        ));
+        for entry in &self.entries {
+            xml.push_str("<entry>\n");
+            xml.push_str(&format!(
+                "<id>{}</id>\n<title>{}</title>\n<updated>{}</updated>\n",
+                escape(&entry.id),
+                escape(&entry.title),
+                entry.updated.to_rfc3339()
+            ));
+            if let Some(link) = &entry.link {
+                xml.push_str(&format!(
+                    "<link rel=\"alternate\" href=\"{}\"/>\n",
+                    escape(link)
+                ));
+            }
+            for media in &entry.media {
+                xml.push_str(&format!(
+                    "<link rel=\"enclosure\" type=\"{MEDIA_TYPE}\" \
+                    href=\"{}\"/>\n",
+                    escape(media)
+                ));
+            }
+            if let Some(body) = &entry.body {
+                xml.push_str(&format!(
+                    "<content type=\"text\">{}</content>\n",
+                    escape(body)
+                ));
+            }
+            xml.push_str("</entry>\n");
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+
+    fn rss(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+            <rss version=\"2.0\">\n<channel>\n",
+        );
+        xml.push_str(&format!(
+            "<title>{}</title>\n<link>{}</link>\n\
+            <description>{}</description>\n<lastBuildDate>{}</lastBuildDate>\n",
+            escape(&self.title),
+            escape(&self.id),
+            escape(&self.title),
+            self.updated.to_rfc2822()
+        ));
+        for entry in &self.entries {
+            xml.push_str("<item>\n");
+            xml.push_str(&format!(
+                "<guid isPermaLink=\"false\">{}</guid>\n<title>{}</title>\n\
+                <pubDate>{}</pubDate>\n",
+                escape(&entry.id),
+                escape(&entry.title),
+                entry.updated.to_rfc2822()
+            ));
+            if let Some(link) = &entry.link {
+                xml.push_str(&format!("<link>{}</link>\n", escape(link)));
+            }
+            if let Some(body) = &entry.body {
+                xml.push_str(&format!(
+                    "<description>{}</description>\n",
+                    escape(body)
+                ));
+            }
+            // RSS only allows one enclosure per item.
+            if let Some(media) = entry.media.first() {
+                xml.push_str(&format!(
+                    "<enclosure url=\"{}\" length=\"0\" type=\"{MEDIA_TYPE}\"/>\n",
+                    escape(media)
+                ));
+            }
+            xml.push_str("</item>\n");
+        }
+        xml.push_str("</channel>\n</rss>\n");
+        xml
+    }
+
+    fn json(&self) -> String {
+        let items: Vec<serde_json::Value> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let attachments: Vec<serde_json::Value> = entry
+                    .media
+                    .iter()
+                    .map(|m| json!({"url": m, "mime_type": MEDIA_TYPE}))
+                    .collect();
+                json!({
+                    "id": entry.id,
+                    "title": entry.title,
+                    "url": entry.link,
+                    "content_text": entry.body.clone().unwrap_or_default(),
+                    "date_published": entry.updated.to_rfc3339(),
+                    "attachments": attachments,
+                })
+            })
+            .collect();
+        json!({
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": self.title,
+            "items": items,
+        })
+        .to_string()
+    }
+}
+
+/// An entry for every item of content in the view data.
+pub fn content_entries(view_data: &ViewData) -> Vec<FeedEntry> {
+    let mut entries = Vec::new();
+    for subject_data in &view_data.subject_data {
+        let profiles = subject_data
+            .platforms
+            .iter()
+            .flat_map(|p| p.profiles.iter());
+        for data in profiles.flat_map(|p| p.content.iter()) {
+            if let Some(entry) =
+                FeedEntry::from_content(&subject_data.subject.name, data)
+            {
+                entries.push(entry);
+            }
+        }
+    }
+    entries
+}
+
+impl FeedEntry {
+    /// An entry for an item of content about one of a subject's profiles.
+    pub fn from_content(subject: &str, data: &Data) -> Option<Self> {
+        let Data::Content {
+            id,
+            platform,
+            content_type,
+            content_id,
+            retrieved_at,
+            retrieved_from,
+            created_at,
+            body,
+            media,
+            ..
+        } = data
+        else {
+            return None;
+        };
+        Some(Self {
+            id: format!("{platform}:{id}:{content_id}"),
+            title: format!("{subject} on {platform} ({content_type})"),
+            link: retrieved_from.clone(),
+            body: body.clone(),
+            media: media.clone().unwrap_or_default(),
+            updated: created_at.unwrap_or(*retrieved_at),
+        })
+    }
+
+    /// An entry for the changes between two consecutive sets of metadata
+    /// about one of a subject's profiles, if there are any.
+    pub fn from_meta_change(
+        subject: &str,
+        previous: &Data,
+        current: &Data,
+    ) -> Option<Self> {
+        let (Some(old_fields), Some(new_fields)) =
+            (meta_fields(previous), meta_fields(current))
+        else {
+            return None;
+        };
+        let changes: Vec<(&str, String)> = old_fields
+            .into_iter()
+            .zip(new_fields.iter())
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((field, old), (_, new))| {
+                let old = old.unwrap_or_else(|| "none".to_string());
+                let new = new.as_deref().unwrap_or("none");
+                (field, format!("{field}: {old} -> {new}"))
+            })
+            .collect();
+        if changes.is_empty() {
+            return None;
+        }
+
+        let Data::Meta {
+            id,
+            platform,
+            retrieved_at,
+            link,
+            profile_picture,
+            ..
+        } = current
+        else {
+            return None;
+        };
+        let fields: Vec<&str> = changes.iter().map(|(f, _)| *f).collect();
+        let lines: Vec<String> = changes.into_iter().map(|(_, l)| l).collect();
+        Some(Self {
+            id: format!("{platform}:{id}:meta:{}", retrieved_at.to_rfc3339()),
+            title: format!(
+                "{subject} on {platform} changed their {}",
+                fields.join(", ")
+            ),
+            link: link.clone(),
+            body: Some(lines.join("\n")),
+            media: profile_picture.iter().cloned().collect(),
+            updated: *retrieved_at,
+        })
+    }
+}
+
+// The fields of metadata that are compared for changes, as text.
+fn meta_fields(data: &Data) -> Option<Vec<(&'static str, Option<String>)>> {
+    let Data::Meta {
+        username,
+        private,
+        suspended_or_banned,
+        display_name,
+        profile_picture,
+        bio,
+        verified,
+        link,
+        ..
+    } = data
+    else {
+        return None;
+    };
+    Some(vec![
+        ("username", Some(username.clone())),
+        ("display name", display_name.clone()),
+        ("bio", bio.clone()),
+        ("profile picture", profile_picture.clone()),
+        ("link", link.clone()),
+        ("verified", verified.map(|v| v.to_string())),
+        ("private", Some(private.to_string())),
+        ("suspended or banned", Some(suspended_or_banned.to_string())),
+    ])
+}
+
+/// An entry for every change to the metadata of the subject's profiles,
+/// ignoring metadata from the hidden providers.
+pub async fn meta_entries(
+    subject: &Subject,
+    hidden: &[String],
+    db: &mut DBHandle,
+) -> Vec<FeedEntry> {
+    let options = FindOptions::builder()
+        .sort(doc! {"retrieved_at": -1_i32})
+        .limit(MAX_META)
+        .build();
+    let data_coll: Collection<Data> = db.collection("data");
+
+    let mut entries = Vec::new();
+    for node in subject.nodes() {
+        let mut cursor = data_coll
+            .find_with_session(
+                doc! {
+                    "id": &node.id,
+                    "platform": &node.platform,
+                    "username": {"$exists": true},
+                    "added_by": {"$nin": hidden}
+                },
+                options.clone(),
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+        let meta: Vec<Data> =
+            cursor.stream(&mut db.session).try_collect().await.unwrap();
+        // Metadata is most recent first, so each is compared to the next.
+        entries.extend(meta.windows(2).filter_map(|pair| {
+            FeedEntry::from_meta_change(&subject.name, &pair[1], &pair[0])
+        }));
+    }
+    entries
+}
+
+/// A token for following a subject's feed without a key.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeedToken {
+    pub hashed_token: String,
+    pub subject: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl FeedToken {
+    /// A new token for the subject and the token itself, which is only
+    /// stored hashed.
+    pub fn new(subject: &str, created_by: &str) -> (Self, String) {
+        let (token, hashed_token) = random::new_feed_token();
+        let feed_token = Self {
+            hashed_token,
+            subject: subject.to_string(),
+            created_by: created_by.to_string(),
+            created_at: Utc::now(),
+        };
+        (feed_token, token)
+    }
+
+    pub async fn with_token(token: &str, db: &mut DBHandle) -> Option<Self> {
+        let t_coll: Collection<Self> = db.collection("feed_tokens");
+        t_coll
+            .find_one_with_session(
+                doc! {"hashed_token": random::hash_string(token)},
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap()
+    }
+}
+
+// Escapes text for use in XML elements and attributes. Characters that can't
+// appear in XML 1.0 even when escaped, such as most control characters, are
+// removed.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn entry(id: &str, updated: DateTime<Utc>) -> FeedEntry {
+        FeedEntry {
+            id: id.to_string(),
+            title: "<title>".to_string(),
+            link: Some("https://example.com/?a=1&b=2".to_string()),
+            body: Some("Body & \"quotes\"".to_string()),
+            media: vec!["https://example.com/1.jpg".to_string()],
+            updated,
+        }
+    }
+
+    #[test]
+    fn test_entries_are_most_recent_first() {
+        let now = Utc::now();
+        let feed = Feed::new(
+            "feed",
+            "Feed",
+            vec![entry("old", now - Duration::hours(1)), entry("new", now)],
+        );
+        assert_eq!(feed.entries[0].id, "new");
+        assert_eq!(feed.updated, now);
+    }
+
+    #[test]
+    fn test_xml_is_escaped() {
+        let feed = Feed::new("feed", "Feed", vec![entry("1", Utc::now())]);
+        for xml in [feed.render(FeedFormat::Atom), feed.render(FeedFormat::Rss)]
+        {
+            assert!(xml.contains("&lt;title&gt;"));
+            assert!(xml.contains("a=1&amp;b=2"));
+            assert!(xml.contains("Body &amp; &quot;quotes&quot;"));
+            assert!(!xml.contains("<title><title>"));
+        }
+    }
+
+    #[test]
+    fn test_invalid_xml_characters_are_removed() {
+        assert_eq!(escape("a\u{0}b\u{1b}c\u{ffff}"), "abc");
+        assert_eq!(escape("line\r\n\tindented"), "line\r\n\tindented");
+    }
+
+    #[test]
+    fn test_atom_feed_has_author() {
+        let feed = Feed::new("feed", "Feed", vec![entry("1", Utc::now())]);
+        let xml = feed.render(FeedFormat::Atom);
+        assert!(xml.contains("<author><name>Instrumentality</name></author>"));
+    }
+
+    fn meta(display_name: &str, bio: Option<&str>) -> Data {
+        Data::Meta {
+            id: "1".to_string(),
+            platform: "PLATFORM_1".to_string(),
+            username: "user".to_string(),
+            private: false,
+            suspended_or_banned: false,
+            retrieved_at: Utc::now(),
+            display_name: Some(display_name.to_string()),
+            profile_picture: None,
+            bio: bio.map(|b| b.to_string()),
+            verified: None,
+            references: None,
+            link: None,
+            added_by: None,
+            added_at: None,
+        }
+    }
+
+    #[test]
+    fn test_meta_changes() {
+        let previous = meta("Old", None);
+        let current = meta("New", Some("Hello"));
+        let entry = FeedEntry::from_meta_change("subject", &previous, &current)
+            .unwrap();
+        assert_eq!(
+            entry.title,
+            "subject on PLATFORM_1 changed their display name, bio"
+        );
+        assert_eq!(
+            entry.body.unwrap(),
+            "display name: Old -> New\nbio: none -> Hello"
+        );
+
+        let unchanged = meta("New", Some("Hello"));
+        assert!(FeedEntry::from_meta_change("subject", &current, &unchanged)
+            .is_none());
+    }
+
+    #[test]
+    fn test_json_feed() {
+        let feed = Feed::new("feed", "Feed", vec![entry("1", Utc::now())]);
+        let json: serde_json::Value =
+            serde_json::from_str(&feed.render(FeedFormat::Json)).unwrap();
+        assert_eq!(json["items"][0]["id"], "1");
+        assert_eq!(
+            json["items"][0]["attachments"][0]["url"],
+            "https://example.com/1.jpg"
+        );
+    }
+}
@@ -1,8 +1,26 @@
 //! Groups for organisitions of subjects.
+//!
+//! Groups can contain other groups as well as subjects, so an organisation
+//! can be a group of its departments. A group contains the subjects of every
+//! group nested in it at any depth. Reading a group doesn't give access to
+//! the groups and subjects nested in it: only those the reader can read
+//! themselves are selected through it. A group can't be nested in itself,
+//! directly or through other groups.
+//!
+//! Like subjects, groups are versioned so that updates made against an older
+//! version can be refused.
+
+use std::collections::HashSet;
 
 use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 
+use crate::concepts::acl::{self, Visibility};
+use crate::database::DBHandle;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Group {
     pub uuid: String,
@@ -11,4 +29,139 @@ pub struct Group {
     pub name: String,
     pub subjects: Vec<String>,
     pub description: Option<String>,
+    // Users the group is shared with. See [`crate::concepts::acl`].
+    #[serde(default)]
+    pub editors: Vec<String>,
+    #[serde(default)]
+    pub viewers: Vec<String>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    // UUIDs of the groups nested in this group.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub version: u64,
+}
+
+impl Group {
+    /// The groups and every group nested in them at any depth that matches
+    /// the access filter, each once. Groups nested in a group that doesn't
+    /// match aren't followed.
+    pub async fn expand(
+        groups: Vec<Group>,
+        access: &Document,
+        db: &mut DBHandle,
+    ) -> Vec<Group> {
+        let mut seen: HashSet<String> =
+            groups.iter().map(|g| g.uuid.clone()).collect();
+        let mut frontier: Vec<String> =
+            groups.iter().flat_map(|g| g.groups.clone()).collect();
+        let mut expanded = groups;
+
+        let group_coll: Collection<Group> = db.collection("groups");
+        while !frontier.is_empty() {
+            frontier.retain(|uuid| seen.insert(uuid.clone()));
+            if frontier.is_empty() {
+                break;
+            }
+            let mut cursor = group_coll
+                .find_with_session(
+                    doc! {"$and": [{"uuid": {"$in": &frontier}}, access]},
+                    None,
+                    &mut db.session,
+                )
+                .await
+                .unwrap();
+            let nested: Vec<Group> =
+                cursor.stream(&mut db.session).try_collect().await.unwrap();
+            frontier = nested.iter().flat_map(|g| g.groups.clone()).collect();
+            expanded.extend(nested);
+        }
+        expanded
+    }
+
+    /// UUIDs of the subjects in the groups, each once.
+    pub fn subjects_of(groups: &[Group]) -> Vec<String> {
+        let mut seen = HashSet::new();
+        groups
+            .iter()
+            .flat_map(|g| g.subjects.iter())
+            .filter(|s| seen.insert(*s))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether every group with the given UUIDs exists and can be read by the
+    /// user.
+    pub async fn all_readable(
+        uuids: &[String],
+        user: &str,
+        db: &mut DBHandle,
+    ) -> bool {
+        let unique: HashSet<&String> = uuids.iter().collect();
+        let group_coll: Collection<Group> = db.collection("groups");
+        let readable = group_coll
+            .count_documents_with_session(
+                acl::readable_by(doc! {"uuid": {"$in": uuids}}, user),
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+        readable == unique.len() as u64
+    }
+
+    /// Whether nesting the groups in the group with the given UUID would
+    /// nest it in itself.
+    pub async fn creates_cycle(
+        uuid: &str,
+        nested: &[String],
+        db: &mut DBHandle,
+    ) -> bool {
+        if nested.iter().any(|g| g == uuid) {
+            return true;
+        }
+        let group_coll: Collection<Group> = db.collection("groups");
+        let mut cursor = group_coll
+            .find_with_session(
+                doc! {"uuid": {"$in": nested}},
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+        let nested: Vec<Group> =
+            cursor.stream(&mut db.session).try_collect().await.unwrap();
+        Self::expand(nested, &doc! {}, db)
+            .await
+            .iter()
+            .any(|g| g.uuid == uuid)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn group(uuid: &str, subjects: &[&str]) -> Group {
+        Group {
+            uuid: uuid.to_string(),
+            created_at: Utc::now(),
+            created_by: "user".to_string(),
+            name: uuid.to_string(),
+            subjects: subjects.iter().map(|s| s.to_string()).collect(),
+            description: None,
+            editors: Vec::new(),
+            viewers: Vec::new(),
+            visibility: Visibility::default(),
+            groups: Vec::new(),
+            version: 0,
+        }
+    }
+
+    #[test]
+    fn test_subjects_of_deduplicates() {
+        let groups = vec![group("a", &["1", "2"]), group("b", &["2", "3"])];
+        assert_eq!(Group::subjects_of(&groups), vec!["1", "2", "3"]);
+    }
 }
@@ -1,6 +1,15 @@
 //! Key concepts for Instrumentality.
 
+pub mod acl;
+pub mod consensus;
 pub mod data;
+pub mod edge;
+pub mod feed;
 pub mod group;
+pub mod reputation;
+pub mod resolution;
+pub mod retention;
 pub mod subject;
+pub mod tag;
+pub mod trash;
 pub mod user;
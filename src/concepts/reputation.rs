@@ -0,0 +1,624 @@
+//! Reputation of data providers.
+//!
+//! A provider's data can't be checked against the platform it came from, but
+//! it can be checked against other providers' data about the same profile.
+//! Whenever data is added, it is compared with the most recent data about the
+//! same profile from every other provider retrieved within a configured window
+//! of it:
+//! - metadata is checked for optional fields that one provider left empty.
+//! - content is checked for items of a content type that one provider did not
+//!   see, despite having retrieved that content type after the item was
+//!   created.
+//!
+//! A provider is only judged on a field or item that at least
+//! [`CORROBORATING_PROVIDERS`] other providers reported, or that was agreed on
+//! through [`crate::concepts::consensus`], so a single provider fabricating
+//! data can't damage anyone else's reputation. Each provider is judged at most
+//! once on each item or field of each snapshot, however often the data is
+//! compared.
+//!
+//! Disagreements recorded by [`crate::concepts::consensus`] also count
+//! against a provider.
+//!
+//! Each check a provider passes or fails is tallied against them and their
+//! score is the proportion of checks passed. Every provider starts with a
+//! number of passed checks so that a few failures don't immediately condemn a
+//! new provider. Data from providers scoring below the configured minimum, once
+//! enough checks have been made, is hidden from /view.
+//!
+//! Assessments are recorded once the data they were made against has been
+//! committed, outside of any transaction, so that concurrent submissions don't
+//! conflict over a provider's tallies.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::consensus::CONSENSUS_PROVIDER;
+use crate::concepts::data::Data;
+use crate::database::DBHandle;
+
+// Passed checks every provider starts with.
+const PRIOR_CHECKS: f64 = 10.0;
+// Checks needed before a provider's data can be hidden.
+pub const MIN_CHECKS: u64 = 20;
+/// Other providers that must report a field or item before a provider is
+/// judged on it.
+pub const CORROBORATING_PROVIDERS: usize = 2;
+// Metadata fields that a thorough provider is expected to fill in if present.
+const OPTIONAL_META_FIELDS: [&str; 6] = [
+    "display_name",
+    "profile_picture",
+    "bio",
+    "verified",
+    "references",
+    "link",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Reputation {
+    pub user: String,
+    pub checks: u64,
+    pub omitted_fields: u64,
+    pub missed_content: u64,
+    pub disagreements: u64,
+    // Computed when read rather than stored.
+    #[serde(default)]
+    pub score: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The outcome of comparing some of a provider's data with another's.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Assessment {
+    pub checks: u64,
+    pub omitted_fields: u64,
+    pub missed_content: u64,
+    pub disagreements: u64,
+}
+
+/// An assessment of a provider waiting to be recorded. Assessments with an
+/// item are only recorded the first time the provider is judged on that item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingAssessment {
+    pub provider: String,
+    pub item: Option<String>,
+    pub assessment: Assessment,
+}
+
+impl Reputation {
+    #[cfg(test)]
+    fn new(user: &str) -> Self {
+        Self {
+            user: user.to_string(),
+            checks: 0,
+            omitted_fields: 0,
+            missed_content: 0,
+            disagreements: 0,
+            score: 1.0,
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.omitted_fields + self.missed_content + self.disagreements
+    }
+
+    fn rescore(&mut self) {
+        let passed = self.checks.saturating_sub(self.failures()) as f64;
+        self.score =
+            (passed + PRIOR_CHECKS) / (self.checks as f64 + PRIOR_CHECKS);
+    }
+
+    /// Whether this provider's data should be hidden.
+    pub fn is_low(&self, min_score: f64) -> bool {
+        self.checks >= MIN_CHECKS && self.score < min_score
+    }
+
+    /// Add assessments to providers' reputations, skipping any item a provider
+    /// has already been judged on. This is done outside of the request's
+    /// transaction, so it must only be called once the data assessed has been
+    /// committed.
+    pub async fn record(pending: Vec<PendingAssessment>, db: &DBHandle) {
+        let rep_coll: Collection<Document> = db.collection("reputations");
+        let check_coll: Collection<Document> =
+            db.collection("reputation_checks");
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        for p in pending {
+            if p.assessment == Assessment::default() {
+                continue;
+            }
+            let now = bson::to_bson(&Utc::now()).unwrap();
+
+            if let Some(item) = &p.item {
+                let result = check_coll
+                    .update_one(
+                        doc! {"provider": &p.provider, "item": item},
+                        doc! {"$setOnInsert": {"checked_at": &now}},
+                        options.clone(),
+                    )
+                    .await
+                    .unwrap();
+                if result.upserted_id.is_none() {
+                    continue;
+                }
+            }
+
+            let a = p.assessment;
+            rep_coll
+                .update_one(
+                    doc! {"user": &p.provider},
+                    doc! {
+                        "$inc": {
+                            "checks": a.checks as i64,
+                            "omitted_fields": a.omitted_fields as i64,
+                            "missed_content": a.missed_content as i64,
+                            "disagreements": a.disagreements as i64,
+                        },
+                        "$set": {"updated_at": &now},
+                    },
+                    options.clone(),
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Every reputation, lowest score first.
+    pub async fn all(db: &mut DBHandle) -> Vec<Self> {
+        let rep_coll: Collection<Reputation> = db.collection("reputations");
+        let mut cursor = rep_coll
+            .find_with_session(None, None, &mut db.session)
+            .await
+            .unwrap();
+        let mut reputations: Vec<Self> =
+            cursor.stream(&mut db.session).try_collect().await.unwrap();
+        reputations.iter_mut().for_each(Self::rescore);
+        reputations.sort_by(|a, b| a.score.total_cmp(&b.score));
+        reputations
+    }
+
+    /// Providers whose data should be hidden.
+    pub async fn low_providers(
+        min_score: f64,
+        db: &mut DBHandle,
+    ) -> Vec<String> {
+        // The same score as rescore, computed by the database.
+        let failures = doc! {
+            "$add": ["$omitted_fields", "$missed_content", "$disagreements"]
+        };
+        let passed =
+            doc! {"$max": [0_i64, {"$subtract": ["$checks", failures]}]};
+        let score = doc! {
+            "$divide": [
+                {"$add": [passed, PRIOR_CHECKS]},
+                {"$add": ["$checks", PRIOR_CHECKS]}
+            ]
+        };
+        let filter = doc! {
+            "checks": {"$gte": MIN_CHECKS as i64},
+            "$expr": {"$lt": [score, min_score]},
+        };
+        let options = FindOptions::builder()
+            .projection(doc! {"user": 1_i32, "_id": 0_i32})
+            .build();
+
+        let rep_coll: Collection<Document> = db.collection("reputations");
+        let mut cursor = rep_coll
+            .find_with_session(filter, options, &mut db.session)
+            .await
+            .unwrap();
+        let low: Vec<Document> =
+            cursor.stream(&mut db.session).try_collect().await.unwrap();
+        low.iter()
+            .filter_map(|d| d.get_str("user").ok())
+            .map(|u| u.to_string())
+            .collect()
+    }
+
+    /// Compare newly added data from a provider with other providers' data
+    /// about the same profiles, returning the assessments of every provider
+    /// involved to be recorded once the data is committed.
+    pub async fn assess(
+        data: &[Data],
+        user: &str,
+        window: Duration,
+        db: &mut DBHandle,
+    ) -> Vec<PendingAssessment> {
+        let mut pending = Vec::new();
+        let data_coll: Collection<Data> = db.collection("data");
+
+        for meta in data {
+            let Data::Meta { platform, id, .. } = meta else {
+                continue;
+            };
+            let t = meta.retrieved_at();
+            let options = FindOptions::builder()
+                .sort(doc! {"retrieved_at": -1_i32})
+                .build();
+            let mut cursor = data_coll
+                .find_with_session(
+                    doc! {
+                        "platform": platform,
+                        "id": id,
+                        "username": {"$exists": true},
+                        "added_by": {"$ne": user},
+                        "retrieved_at": between(t - window, t + window),
+                    },
+                    options,
+                    &mut db.session,
+                )
+                .await
+                .unwrap();
+            let theirs: Vec<Data> =
+                cursor.stream(&mut db.session).try_collect().await.unwrap();
+
+            // The most recent snapshot from each provider.
+            let mut providers = HashSet::from([user]);
+            let mut snapshots = vec![meta];
+            for other in &theirs {
+                if other.added_by().is_some_and(|p| providers.insert(p)) {
+                    snapshots.push(other);
+                }
+            }
+            pending.extend(omitted_fields(&snapshots));
+        }
+
+        let mut content: HashMap<(&str, &str, &str), Vec<&Data>> =
+            HashMap::new();
+        for d in data {
+            if let Data::Content {
+                platform,
+                id,
+                content_type,
+                ..
+            } = d
+            {
+                content
+                    .entry((platform, id, content_type))
+                    .or_default()
+                    .push(d);
+            }
+        }
+
+        for ((platform, id, content_type), ours) in content {
+            let earliest = ours.iter().map(|d| d.retrieved_at()).min().unwrap();
+            let latest = ours.iter().map(|d| d.retrieved_at()).max().unwrap();
+            let mut cursor = data_coll
+                .find_with_session(
+                    doc! {
+                        "platform": platform,
+                        "id": id,
+                        "content_type": content_type,
+                        "added_by": {"$ne": user},
+                        "retrieved_at":
+                            between(earliest - window, latest + window),
+                    },
+                    None,
+                    &mut db.session,
+                )
+                .await
+                .unwrap();
+            let theirs: Vec<Data> =
+                cursor.stream(&mut db.session).try_collect().await.unwrap();
+
+            let mut all = ours;
+            all.extend(theirs.iter());
+            pending.extend(missed_content(&all));
+        }
+
+        pending
+    }
+}
+
+fn between(from: DateTime<Utc>, to: DateTime<Utc>) -> Bson {
+    let from = bson::to_bson(&from).unwrap();
+    let to = bson::to_bson(&to).unwrap();
+    Bson::Document(doc! {"$gte": from, "$lte": to})
+}
+
+// Whether a field or item reported by these providers is established well
+// enough to judge another provider on.
+fn corroborated(reporters: &HashSet<&str>, provider: &str) -> bool {
+    reporters.contains(CONSENSUS_PROVIDER)
+        || reporters.iter().filter(|r| **r != provider).count()
+            >= CORROBORATING_PROVIDERS
+}
+
+/// Checks each provider's metadata snapshot for optional fields filled in by
+/// other providers. Expects at most one snapshot of a profile per provider.
+pub fn omitted_fields(snapshots: &[&Data]) -> Vec<PendingAssessment> {
+    let filled = |d: &Document, f: &str| {
+        d.get(f).is_some_and(|v| !matches!(v, Bson::Null))
+    };
+    let docs: Vec<(&str, &Data, Document)> = snapshots
+        .iter()
+        .filter_map(|d| {
+            Some((d.added_by()?, *d, bson::to_document(d).unwrap()))
+        })
+        .collect();
+
+    let mut pending = Vec::new();
+    for (provider, snapshot, ours) in &docs {
+        if *provider == CONSENSUS_PROVIDER {
+            continue;
+        }
+        let Data::Meta {
+            platform,
+            id,
+            retrieved_at,
+            ..
+        } = snapshot
+        else {
+            continue;
+        };
+        let retrieved_at = retrieved_at.timestamp_millis();
+
+        for field in OPTIONAL_META_FIELDS {
+            let reporters: HashSet<&str> = docs
+                .iter()
+                .filter(|(_, _, d)| filled(d, field))
+                .map(|(p, _, _)| *p)
+                .collect();
+            if !corroborated(&reporters, provider) {
+                continue;
+            }
+            let omitted = !filled(ours, field);
+            pending.push(PendingAssessment {
+                provider: provider.to_string(),
+                item: Some(format!(
+                    "meta:{platform}:{id}:{retrieved_at}:{field}"
+                )),
+                assessment: Assessment {
+                    checks: 1,
+                    omitted_fields: omitted as u64,
+                    ..Default::default()
+                },
+            });
+        }
+    }
+    pending
+}
+
+/// Checks each provider's content for items reported by other providers that
+/// were created before the provider last retrieved that content.
+pub fn missed_content(content: &[&Data]) -> Vec<PendingAssessment> {
+    let mut reporters: HashMap<&str, HashSet<&str>> = HashMap::new();
+    let mut created: HashMap<&str, DateTime<Utc>> = HashMap::new();
+    let mut latest: HashMap<&str, DateTime<Utc>> = HashMap::new();
+    let mut profile = None;
+
+    for d in content {
+        let Data::Content {
+            platform,
+            id,
+            content_id,
+            created_at,
+            retrieved_at,
+            ..
+        } = d
+        else {
+            continue;
+        };
+        let Some(provider) = d.added_by() else {
+            continue;
+        };
+        profile = Some((platform, id));
+        reporters.entry(content_id).or_default().insert(provider);
+        let created_at = created_at.unwrap_or(*retrieved_at);
+        created
+            .entry(content_id)
+            .and_modify(|t| *t = (*t).min(created_at))
+            .or_insert(created_at);
+        latest
+            .entry(provider)
+            .and_modify(|t| *t = (*t).max(*retrieved_at))
+            .or_insert(*retrieved_at);
+    }
+    let Some((platform, id)) = profile else {
+        return Vec::new();
+    };
+
+    let mut pending = Vec::new();
+    for (provider, retrieved_at) in &latest {
+        if *provider == CONSENSUS_PROVIDER {
+            continue;
+        }
+        for (content_id, reported_by) in &reporters {
+            if created[content_id] > *retrieved_at
+                || !corroborated(reported_by, provider)
+            {
+                continue;
+            }
+            let missed = !reported_by.contains(provider);
+            pending.push(PendingAssessment {
+                provider: provider.to_string(),
+                item: Some(format!("content:{platform}:{id}:{content_id}")),
+                assessment: Assessment {
+                    checks: 1,
+                    missed_content: missed as u64,
+                    ..Default::default()
+                },
+            });
+        }
+    }
+    pending
+}
+
+/// Count each provider's disagreements in a consensus round of the given
+/// number of items.
+pub fn from_disagreements<'a>(
+    providers: impl Iterator<Item = &'a str>,
+    items: u64,
+    disagreeing: impl Iterator<Item = (&'a str, &'a str)>,
+) -> HashMap<String, Assessment> {
+    let mut assessments: HashMap<String, Assessment> = providers
+        .map(|p| {
+            let assessment = Assessment {
+                checks: items,
+                ..Default::default()
+            };
+            (p.to_string(), assessment)
+        })
+        .collect();
+
+    // Only one disagreement is counted per item.
+    let distinct: HashSet<(&str, &str)> = disagreeing.collect();
+    for (provider, _) in distinct {
+        assessments
+            .entry(provider.to_string())
+            .or_default()
+            .disagreements += 1;
+    }
+    assessments
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn meta(provider: &str, bio: Option<&str>) -> Data {
+        Data::Meta {
+            id: "123".to_string(),
+            platform: "twitter".to_string(),
+            username: "someone".to_string(),
+            private: false,
+            suspended_or_banned: false,
+            retrieved_at: Utc::now(),
+            display_name: Some("Someone".to_string()),
+            profile_picture: None,
+            bio: bio.map(|b| b.to_string()),
+            verified: None,
+            references: None,
+            link: None,
+            added_by: Some(provider.to_string()),
+            added_at: None,
+        }
+    }
+
+    fn content(
+        provider: &str,
+        content_id: &str,
+        created_at: DateTime<Utc>,
+    ) -> Data {
+        Data::Content {
+            id: "123".to_string(),
+            platform: "twitter".to_string(),
+            content_type: "tweet".to_string(),
+            retrieved_at: Utc::now(),
+            content_id: content_id.to_string(),
+            deleted: Some(false),
+            retrieved_from: None,
+            created_at: Some(created_at),
+            body: None,
+            media: None,
+            references: None,
+            added_by: Some(provider.to_string()),
+            added_at: None,
+        }
+    }
+
+    fn total(pending: &[PendingAssessment], provider: &str) -> Assessment {
+        let mut total = Assessment::default();
+        for p in pending.iter().filter(|p| p.provider == provider) {
+            total.checks += p.assessment.checks;
+            total.omitted_fields += p.assessment.omitted_fields;
+            total.missed_content += p.assessment.missed_content;
+        }
+        total
+    }
+
+    #[test]
+    fn test_omitted_fields() {
+        let (a, b) = (meta("a", Some("Hello.")), meta("b", Some("Hello.")));
+        let lazy = meta("lazy", None);
+
+        // A single provider filling in a field can't penalise another.
+        let pending = omitted_fields(&[&lazy, &a]);
+        assert_eq!(total(&pending, "lazy").omitted_fields, 0);
+
+        let pending = omitted_fields(&[&lazy, &a, &b]);
+        let assessment = total(&pending, "lazy");
+        assert_eq!(assessment.checks, 2);
+        assert_eq!(assessment.omitted_fields, 1);
+        // Only b corroborates a's bio, so a is only judged on the name.
+        let assessment = total(&pending, "a");
+        assert_eq!(assessment.checks, 1);
+        assert_eq!(assessment.omitted_fields, 0);
+
+        // Each field of each snapshot is judged once per provider.
+        let items: HashSet<_> =
+            pending.iter().map(|p| (&p.provider, &p.item)).collect();
+        assert_eq!(items.len(), pending.len());
+        let mut later = meta("lazy", None);
+        if let Data::Meta { retrieved_at, .. } = &mut later {
+            *retrieved_at += Duration::seconds(1);
+        }
+        let later = omitted_fields(&[&later, &a, &b]);
+        assert_ne!(later[0].item, pending[0].item);
+
+        let consensus = meta(CONSENSUS_PROVIDER, Some("Hello."));
+        let pending = omitted_fields(&[&lazy, &consensus]);
+        assert_eq!(total(&pending, "lazy").omitted_fields, 1);
+        assert_eq!(total(&pending, CONSENSUS_PROVIDER).checks, 0);
+    }
+
+    #[test]
+    fn test_missed_content() {
+        let old = Utc::now() - Duration::hours(1);
+        let new = Utc::now() + Duration::hours(1);
+        let ours = content("lazy", "1", old);
+        let (a1, a2, a3) = (
+            content("a", "1", old),
+            content("a", "2", old),
+            content("a", "3", new),
+        );
+        let (b2, b3) = (content("b", "2", old), content("b", "3", new));
+
+        let pending = missed_content(&[&ours, &a1, &a2]);
+        assert_eq!(total(&pending, "lazy").missed_content, 0);
+
+        let pending = missed_content(&[&ours, &a1, &a2, &a3, &b2, &b3]);
+        let assessment = total(&pending, "lazy");
+        assert_eq!(assessment.checks, 1);
+        assert_eq!(assessment.missed_content, 1);
+        // Only a corroborates b's item, so b is only judged on the one it
+        // missed.
+        let assessment = total(&pending, "b");
+        assert_eq!(assessment.checks, 1);
+        assert_eq!(assessment.missed_content, 1);
+    }
+
+    #[test]
+    fn test_score() {
+        let mut reputation = Reputation::new("user");
+        reputation.checks = 30;
+        reputation.omitted_fields = 25;
+        reputation.rescore();
+        assert!(reputation.score < 0.5);
+        assert!(reputation.is_low(0.5));
+
+        reputation.checks = 10;
+        reputation.omitted_fields = 10;
+        reputation.rescore();
+        assert!(!reputation.is_low(0.5));
+    }
+
+    #[test]
+    fn test_from_disagreements() {
+        let assessments = from_disagreements(
+            ["a", "b"].into_iter(),
+            2,
+            [("a", "item1"), ("a", "item1"), ("a", "item2")].into_iter(),
+        );
+        assert_eq!(assessments["a"].checks, 2);
+        assert_eq!(assessments["a"].disagreements, 2);
+        assert_eq!(assessments["b"].disagreements, 0);
+    }
+}
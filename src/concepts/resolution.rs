@@ -0,0 +1,300 @@
+//! Resolution of usernames to platform IDs.
+//!
+//! Subjects are often created with a username for a profile because the
+//! platform ID isn't known. The queue item for such a profile is unconfirmed
+//! until a data provider reports which platform ID the username belongs to,
+//! either explicitly through /queue/resolve with evidence for the mapping or by
+//! submitting metadata for the profile through /add.
+//!
+//! When a mapping is applied, every subject and queue item referring to the
+//! username is rewritten to refer to the platform ID in the same transaction,
+//! and a record of the mapping and the subjects it changed is kept. A mapping
+//! that contradicts one already applied for the same username is not applied
+//! but recorded as a conflict for an administrator to review. An administrator
+//! clears a conflict by choosing the platform ID the username belongs to,
+//! which is then applied in place of the earlier mapping. Subjects rewritten by
+//! the earlier mapping are not changed back.
+
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, doc};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::subject::Subject;
+use crate::database::DBHandle;
+use crate::routes::queue::InternalQueueItem;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MappingStatus {
+    Applied,
+    Conflict,
+    // Superseded by an administrator clearing a conflict.
+    Cleared,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsernameMapping {
+    pub platform: String,
+    pub username: String,
+    pub platform_id: String,
+    pub reported_by: String,
+    pub evidence: String,
+    pub reported_at: DateTime<Utc>,
+    pub status: MappingStatus,
+    // The subjects rewritten when the mapping was applied.
+    pub subjects: Vec<String>,
+}
+
+impl UsernameMapping {
+    /// Every mapping with the given status, most recent first.
+    pub async fn all(
+        status: Option<MappingStatus>,
+        db: &mut DBHandle,
+    ) -> Vec<Self> {
+        let filter = match status {
+            Some(status) => doc! {"status": bson::to_bson(&status).unwrap()},
+            None => doc! {},
+        };
+        let options = FindOptions::builder()
+            .sort(doc! {"reported_at": -1_i32})
+            .build();
+        let m_coll: Collection<Self> = db.collection("username_mappings");
+        let mut cursor = m_coll
+            .find_with_session(filter, options, &mut db.session)
+            .await
+            .unwrap();
+        cursor.stream(&mut db.session).try_collect().await.unwrap()
+    }
+}
+
+/// Map a username on a platform to a platform ID, rewriting every subject and
+/// queue item that refers to the username, unless the username has already
+/// been mapped to a different ID.
+pub async fn resolve(
+    platform: &str,
+    username: &str,
+    platform_id: &str,
+    reported_by: &str,
+    evidence: &str,
+    db: &mut DBHandle,
+) -> MappingStatus {
+    let m_coll: Collection<UsernameMapping> =
+        db.collection("username_mappings");
+    let previous = m_coll
+        .find_one_with_session(
+            doc! {
+                "platform": platform,
+                "username": username,
+                "status": "applied",
+                "platform_id": {"$ne": platform_id}
+            },
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+
+    let (status, subjects) = if previous.is_some() {
+        (MappingStatus::Conflict, Vec::new())
+    } else if username == platform_id {
+        // The username is already the platform ID, so nothing refers to the
+        // username under another name.
+        confirm_queue(platform, platform_id, db).await;
+        (MappingStatus::Applied, Vec::new())
+    } else {
+        let subjects =
+            rewrite_subjects(platform, username, platform_id, db).await;
+        rewrite_queue(platform, username, platform_id, db).await;
+        (MappingStatus::Applied, subjects)
+    };
+
+    let mapping = UsernameMapping {
+        platform: platform.to_string(),
+        username: username.to_string(),
+        platform_id: platform_id.to_string(),
+        reported_by: reported_by.to_string(),
+        evidence: evidence.to_string(),
+        reported_at: Utc::now(),
+        status,
+        subjects,
+    };
+    m_coll
+        .insert_one_with_session(mapping, None, &mut db.session)
+        .await
+        .unwrap();
+    status
+}
+
+/// Clear every conflict over a username on a platform by mapping it to the
+/// given platform ID. Conflicting mappings and earlier mappings to other IDs
+/// are marked as cleared before the mapping is applied. Returns None if there
+/// is no conflict over the username.
+pub async fn clear(
+    platform: &str,
+    username: &str,
+    platform_id: &str,
+    cleared_by: &str,
+    db: &mut DBHandle,
+) -> Option<MappingStatus> {
+    let m_coll: Collection<UsernameMapping> =
+        db.collection("username_mappings");
+    let conflict = m_coll
+        .find_one_with_session(
+            doc! {
+                "platform": platform,
+                "username": username,
+                "status": "conflict"
+            },
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    conflict.as_ref()?;
+
+    m_coll
+        .update_many_with_session(
+            doc! {
+                "platform": platform,
+                "username": username,
+                "$or": [
+                    {"status": "conflict"},
+                    {"status": "applied", "platform_id": {"$ne": platform_id}}
+                ]
+            },
+            doc! {"$set": {"status": "cleared"}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+
+    let status = resolve(
+        platform,
+        username,
+        platform_id,
+        cleared_by,
+        "Conflict cleared by an administrator.",
+        db,
+    )
+    .await;
+    Some(status)
+}
+
+// Replaces the username with the platform ID in every subject's profiles,
+// returning the UUIDs of the subjects changed.
+async fn rewrite_subjects(
+    platform: &str,
+    username: &str,
+    platform_id: &str,
+    db: &mut DBHandle,
+) -> Vec<String> {
+    let key = format!("profiles.{platform}");
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    let mut cursor = subj_coll
+        .find_with_session(doc! {&key: username}, None, &mut db.session)
+        .await
+        .unwrap();
+    let subjects: Vec<Subject> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+
+    // Subjects that already have the platform ID only lose the username.
+    subj_coll
+        .update_many_with_session(
+            doc! {&key: {"$all": [username, platform_id]}},
+            doc! {"$pull": {&key: username}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    subj_coll
+        .update_many_with_session(
+            doc! {&key: username},
+            doc! {"$set": {format!("{key}.$"): platform_id}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+
+    subjects.into_iter().map(|s| s.uuid).collect()
+}
+
+// Confirms the platform ID of the queue item for a profile.
+async fn confirm_queue(platform: &str, platform_id: &str, db: &mut DBHandle) {
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    q_coll
+        .update_one_with_session(
+            doc! {"platform": platform, "platform_id": platform_id},
+            doc! {"$set": {"confirmed_id": true}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+}
+
+// Merges the username's queue item into the platform ID's queue item, creating
+// it if need be.
+async fn rewrite_queue(
+    platform: &str,
+    username: &str,
+    platform_id: &str,
+    db: &mut DBHandle,
+) {
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let unconfirmed = q_coll
+        .find_one_and_delete_with_session(
+            doc! {
+                "platform": platform,
+                "platform_id": username,
+                "confirmed_id": false
+            },
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    let Some(unconfirmed) = unconfirmed else {
+        return;
+    };
+
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    let references = subj_coll
+        .count_documents_with_session(
+            doc! {format!("profiles.{platform}"): platform_id},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+
+    let result = q_coll
+        .update_one_with_session(
+            doc! {"platform": platform, "platform_id": platform_id},
+            doc! {"$set": {
+                "references": references as i64,
+                "confirmed_id": true
+            }},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    if result.matched_count == 0 {
+        let mut queue_item = unconfirmed;
+        queue_item.platform_id = platform_id.to_string();
+        queue_item.references = references;
+        queue_item.confirmed_id = true;
+        queue_item.lock_holder = None;
+        queue_item.lock_acquired_at = None;
+        q_coll
+            .insert_one_with_session(queue_item, None, &mut db.session)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,457 @@
+//! Retention of data.
+//!
+//! Left alone, the data collection only grows, with presence making up the
+//! bulk of it. Retention rules in the config say how long presence and content
+//! of a given type on a given platform are kept. A background worker enforces
+//! the rules periodically, and administrators can review what the rules would
+//! remove without removing anything through /admin/retention.
+//!
+//! Raw presence is rarely needed once it is old. Rules can have expired
+//! presence aggregated into intervals of continuous presence, such as "live
+//! from 20:00 to 23:30", before it is deleted. Observations of the same
+//! presence type for the same profile are part of one interval unless they are
+//! further apart than the rule's gap, and intervals are extended across runs.
+//! Expired presence is read one profile at a time in order of retrieval and
+//! aggregated and deleted in batches, each in its own transaction, so an
+//! interrupted run never counts an observation twice.
+//!
+//! Data about the profiles of exempt subjects is never removed.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::options::{FindOneOptions, FindOptions};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::data::Data;
+use crate::concepts::subject::Subject;
+use crate::config::{RetentionConfig, RetentionRule};
+use crate::database::DBHandle;
+
+// The most observations aggregated and deleted in one transaction.
+const BATCH_SIZE: usize = 1000;
+
+/// A period of continuous presence made from aggregated observations.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PresenceInterval {
+    pub id: String,
+    pub platform: String,
+    pub presence_type: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub observations: u64,
+}
+
+/// What a rule removed, or would remove in a dry run.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RuleReport {
+    pub rule: RetentionRule,
+    pub expired: u64,
+    pub intervals: u64,
+}
+
+/// Enforce every retention rule, or only report what they would remove if
+/// `dry_run` is set. Presence is aggregated in a transaction per batch, so
+/// unless it is a dry run the handle must not have a transaction started.
+pub async fn enforce(
+    config: &RetentionConfig,
+    dry_run: bool,
+    db: &mut DBHandle,
+) -> Vec<RuleReport> {
+    let exempt = exempt_profiles(&config.exempt_subjects, db).await;
+
+    let mut reports = Vec::new();
+    for rule in &config.rules {
+        let filter = expired_filter(rule, &exempt);
+        let data_coll: Collection<Data> = db.collection("data");
+        let expired = data_coll
+            .count_documents_with_session(filter.clone(), None, &mut db.session)
+            .await
+            .unwrap();
+
+        let mut intervals = 0;
+        let mut filter = filter;
+        if rule.aggregate && rule.content_type.is_none() {
+            intervals = aggregate(rule, &filter, dry_run, db).await;
+            // Presence is deleted as it is aggregated.
+            filter.insert("presence_type", doc! {"$exists": false});
+        }
+        if !dry_run {
+            data_coll
+                .delete_many_with_session(filter, None, &mut db.session)
+                .await
+                .unwrap();
+        }
+
+        reports.push(RuleReport {
+            rule: rule.clone(),
+            expired,
+            intervals,
+        });
+    }
+    reports
+}
+
+// The IDs of profiles on each platform belonging to the exempt subjects.
+async fn exempt_profiles(
+    subjects: &[String],
+    db: &mut DBHandle,
+) -> HashMap<String, HashSet<String>> {
+    let mut exempt: HashMap<String, HashSet<String>> = HashMap::new();
+    if subjects.is_empty() {
+        return exempt;
+    }
+
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    let mut cursor = subj_coll
+        .find_with_session(
+            doc! {"uuid": {"$in": subjects}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    let subjects: Vec<Subject> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+    for node in subjects.iter().flat_map(|s| s.nodes()) {
+        exempt.entry(node.platform).or_default().insert(node.id);
+    }
+    exempt
+}
+
+fn expired_filter(
+    rule: &RetentionRule,
+    exempt: &HashMap<String, HashSet<String>>,
+) -> Document {
+    let cutoff = Utc::now() - Duration::days(rule.keep_days);
+    let mut filter = doc! {
+        "platform": &rule.platform,
+        "retrieved_at": {"$lt": bson::to_bson(&cutoff).unwrap()},
+    };
+    match (&rule.presence_type, &rule.content_type) {
+        (Some(presence_type), _) => {
+            filter.insert("presence_type", presence_type);
+        }
+        (None, Some(content_type)) => {
+            filter.insert("content_type", content_type);
+        }
+        (None, None) => {
+            filter.insert(
+                "$or",
+                vec![
+                    doc! {"presence_type": {"$exists": true}},
+                    doc! {"content_type": {"$exists": true}},
+                ],
+            );
+        }
+    }
+    if let Some(ids) = exempt.get(&rule.platform) {
+        let ids: Vec<&String> = ids.iter().collect();
+        filter.insert("id", doc! {"$nin": ids});
+    }
+    filter
+}
+
+// An expired observation of presence.
+#[derive(Debug, Deserialize)]
+struct Observation {
+    _id: Bson,
+    id: String,
+    presence_type: String,
+    retrieved_at: DateTime<Utc>,
+}
+
+// Where aggregation of a profile's presence has got to.
+struct Progress {
+    id: String,
+    presence_type: String,
+    // The end of the most recent interval, stored or from this run.
+    last_end: Option<DateTime<Utc>>,
+    // Whether that interval has been counted in this run.
+    counted: bool,
+}
+
+impl Progress {
+    // Moves on past a batch's intervals, returning whether the first continues
+    // the last interval and how many of them haven't been counted yet.
+    fn advance(
+        &mut self,
+        intervals: &[(DateTime<Utc>, DateTime<Utc>, u64)],
+        gap: Duration,
+    ) -> (bool, u64) {
+        let Some(&(start, end, _)) = intervals.first() else {
+            return (false, 0);
+        };
+        let continues = self.last_end.is_some_and(|last| start - last <= gap);
+        let mut count = intervals.len() as u64;
+        if continues && self.counted {
+            count -= 1;
+        }
+        let end = intervals.last().map_or(end, |i| i.1);
+        self.last_end = Some(end).max(self.last_end);
+        self.counted = true;
+        (continues, count)
+    }
+}
+
+// Collapses the presence matching the filter into intervals, returning how
+// many intervals were created or extended. Unless it is a dry run, the
+// presence is deleted as it is aggregated.
+async fn aggregate(
+    rule: &RetentionRule,
+    filter: &Document,
+    dry_run: bool,
+    db: &mut DBHandle,
+) -> u64 {
+    let mut filter = filter.clone();
+    filter.insert("presence_type", doc! {"$exists": true});
+    let options = FindOptions::builder()
+        .sort(doc! {"id": 1_i32, "presence_type": 1_i32, "retrieved_at": 1_i32})
+        .projection(doc! {
+            "_id": 1_i32,
+            "id": 1_i32,
+            "presence_type": 1_i32,
+            "retrieved_at": 1_i32
+        })
+        .build();
+    // Read outside of the session, which is used for each batch's
+    // transaction.
+    let o_coll: Collection<Observation> = db.collection("data");
+    let mut cursor = o_coll.find(filter, options).await.unwrap();
+
+    let mut count = 0;
+    let mut progress: Option<Progress> = None;
+    let mut batch: Vec<Observation> = Vec::new();
+    while let Some(observation) = cursor.try_next().await.unwrap() {
+        let same_profile = progress.as_ref().is_some_and(|p| {
+            p.id == observation.id
+                && p.presence_type == observation.presence_type
+        });
+        if !same_profile || batch.len() >= BATCH_SIZE {
+            if let Some(p) = progress.as_mut() {
+                count += flush(rule, p, &batch, dry_run, db).await;
+            }
+            batch.clear();
+        }
+        if !same_profile {
+            let last_end = last_interval(
+                rule,
+                &observation.id,
+                &observation.presence_type,
+                db,
+            )
+            .await
+            .map(|i| i.end);
+            progress = Some(Progress {
+                id: observation.id.clone(),
+                presence_type: observation.presence_type.clone(),
+                last_end,
+                counted: false,
+            });
+        }
+        batch.push(observation);
+    }
+    if let Some(p) = progress.as_mut() {
+        count += flush(rule, p, &batch, dry_run, db).await;
+    }
+    count
+}
+
+// The most recent interval stored for a profile's presence type.
+async fn last_interval(
+    rule: &RetentionRule,
+    id: &str,
+    presence_type: &str,
+    db: &mut DBHandle,
+) -> Option<PresenceInterval> {
+    let i_coll: Collection<PresenceInterval> =
+        db.collection("presence_intervals");
+    let options = FindOneOptions::builder().sort(doc! {"end": -1_i32}).build();
+    i_coll
+        .find_one_with_session(
+            doc! {
+                "id": id,
+                "platform": &rule.platform,
+                "presence_type": presence_type
+            },
+            options,
+            &mut db.session,
+        )
+        .await
+        .unwrap()
+}
+
+// Aggregates a batch of one profile's observations into intervals and, unless
+// it is a dry run, deletes them in the same transaction. Returns how many
+// intervals were created, or extended for the first time in this run.
+async fn flush(
+    rule: &RetentionRule,
+    progress: &mut Progress,
+    batch: &[Observation],
+    dry_run: bool,
+    db: &mut DBHandle,
+) -> u64 {
+    if batch.is_empty() {
+        return 0;
+    }
+    let mut times: Vec<DateTime<Utc>> =
+        batch.iter().map(|o| o.retrieved_at).collect();
+    times.sort_unstable();
+    let gap = Duration::seconds(rule.interval_gap_secs);
+    let mut intervals = merge_intervals(&times, gap);
+    let (continues, count) = progress.advance(&intervals, gap);
+    if dry_run {
+        return count;
+    }
+
+    db.session.start_transaction(None).await.unwrap();
+    let i_coll: Collection<PresenceInterval> =
+        db.collection("presence_intervals");
+    if continues {
+        let (_, end, observations) = intervals.remove(0);
+        let last =
+            last_interval(rule, &progress.id, &progress.presence_type, db)
+                .await
+                .unwrap();
+        i_coll
+            .update_one_with_session(
+                doc! {
+                    "id": &progress.id,
+                    "platform": &rule.platform,
+                    "presence_type": &progress.presence_type,
+                    "end": bson::to_bson(&last.end).unwrap()
+                },
+                doc! {
+                    "$set": {"end": bson::to_bson(&end.max(last.end)).unwrap()},
+                    "$inc": {"observations": observations as i64}
+                },
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+    }
+
+    let intervals: Vec<PresenceInterval> = intervals
+        .into_iter()
+        .map(|(start, end, observations)| PresenceInterval {
+            id: progress.id.clone(),
+            platform: rule.platform.clone(),
+            presence_type: progress.presence_type.clone(),
+            start,
+            end,
+            observations,
+        })
+        .collect();
+    if !intervals.is_empty() {
+        i_coll
+            .insert_many_with_session(intervals, None, &mut db.session)
+            .await
+            .unwrap();
+    }
+
+    let ids: Vec<&Bson> = batch.iter().map(|o| &o._id).collect();
+    let data_coll: Collection<Data> = db.collection("data");
+    data_coll
+        .delete_many_with_session(
+            doc! {"_id": {"$in": ids}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    db.session.commit_transaction().await.unwrap();
+    count
+}
+
+/// Every interval of presence for a profile, most recent first.
+pub async fn intervals(
+    id: &str,
+    platform: &str,
+    db: &mut DBHandle,
+) -> Vec<PresenceInterval> {
+    let options = FindOptions::builder()
+        .sort(doc! {"start": -1_i32})
+        .limit(100)
+        .build();
+    let i_coll: Collection<PresenceInterval> =
+        db.collection("presence_intervals");
+    let mut cursor = i_coll
+        .find_with_session(
+            doc! {"id": id, "platform": platform},
+            options,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    cursor.stream(&mut db.session).try_collect().await.unwrap()
+}
+
+// Merges sorted observation times into (start, end, observations) intervals,
+// starting a new interval whenever two observations are more than `gap` apart.
+fn merge_intervals(
+    times: &[DateTime<Utc>],
+    gap: Duration,
+) -> Vec<(DateTime<Utc>, DateTime<Utc>, u64)> {
+    let mut intervals: Vec<(DateTime<Utc>, DateTime<Utc>, u64)> = Vec::new();
+    for &time in times {
+        match intervals.last_mut() {
+            Some((_, end, observations)) if time - *end <= gap => {
+                *end = time;
+                *observations += 1;
+            }
+            _ => intervals.push((time, time, 1)),
+        }
+    }
+    intervals
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_merge_intervals_splits_on_gaps() {
+        let start = Utc::now();
+        let at = |mins| start + Duration::minutes(mins);
+        let times = vec![at(0), at(5), at(10), at(30), at(31), at(60)];
+
+        let intervals = merge_intervals(&times, Duration::minutes(10));
+        assert_eq!(
+            intervals,
+            vec![(at(0), at(10), 3), (at(30), at(31), 2), (at(60), at(60), 1)]
+        );
+    }
+
+    #[test]
+    fn test_progress_counts_intervals_once() {
+        let start = Utc::now();
+        let at = |mins| start + Duration::minutes(mins);
+        let gap = Duration::minutes(10);
+        let mut progress = Progress {
+            id: "123".to_string(),
+            presence_type: "live".to_string(),
+            last_end: Some(at(0)),
+            counted: false,
+        };
+
+        // Extending the stored interval counts it once.
+        let intervals = merge_intervals(&[at(5), at(30)], gap);
+        assert_eq!(progress.advance(&intervals, gap), (true, 2));
+        // A later batch continuing an interval from this run doesn't count it
+        // again.
+        let intervals = merge_intervals(&[at(35), at(60)], gap);
+        assert_eq!(progress.advance(&intervals, gap), (true, 1));
+        let intervals = merge_intervals(&[at(90)], gap);
+        assert_eq!(progress.advance(&intervals, gap), (false, 1));
+        assert_eq!(progress.last_end, Some(at(90)));
+    }
+
+    #[test]
+    fn test_merge_intervals_empty() {
+        assert!(merge_intervals(&[], Duration::minutes(10)).is_empty());
+    }
+}
@@ -1,10 +1,20 @@
 //! Subjects for organisation of profiles.
+//!
+//! Every change to a subject's name, profiles or description increments its
+//! version, so an update made against an older version can be refused rather
+//! than overwriting changes it never saw.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 
+use crate::concepts::acl::{self, Visibility};
+use crate::concepts::edge::Node;
+use crate::database::DBHandle;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Subject {
     pub uuid: String,
@@ -13,4 +23,96 @@ pub struct Subject {
     pub name: String,
     pub profiles: HashMap<String, Vec<String>>,
     pub description: Option<String>,
+    // Users the subject is shared with. See [`crate::concepts::acl`].
+    #[serde(default)]
+    pub editors: Vec<String>,
+    #[serde(default)]
+    pub viewers: Vec<String>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    // See [`crate::concepts::tag`].
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub version: u64,
+}
+
+impl Subject {
+    /// Every profile of the subject as a node of the reference graph.
+    pub fn nodes(&self) -> Vec<Node> {
+        nodes_of(&self.profiles)
+    }
+
+    /// Whether every subject with the given UUIDs exists and can be read by
+    /// the user.
+    pub async fn all_readable(
+        uuids: &[String],
+        user: &str,
+        db: &mut DBHandle,
+    ) -> bool {
+        let unique: HashSet<&String> = uuids.iter().collect();
+        let subj_coll: Collection<Subject> = db.collection("subjects");
+        let readable = subj_coll
+            .count_documents_with_session(
+                acl::readable_by(doc! {"uuid": {"$in": uuids}}, user),
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+        readable == unique.len() as u64
+    }
+}
+
+fn nodes_of(profiles: &HashMap<String, Vec<String>>) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    for (platform, ids) in profiles {
+        for id in ids {
+            nodes.push(Node::new(platform, id));
+        }
+    }
+    nodes
+}
+
+/// Narrows a filter to the subject or group at the version. Documents from
+/// before versioning have no version and are at version 0.
+pub fn at_version(filter: Document, version: u64) -> Document {
+    let version = version as i64;
+    if version == 0 {
+        doc! {"$and": [filter, {"version": {"$in": [0_i64, Bson::Null]}}]}
+    } else {
+        doc! {"$and": [filter, {"version": version}]}
+    }
+}
+
+/// The profiles in `new` but not in `old` and the profiles in `old` but not
+/// in `new`.
+pub fn profile_changes(
+    old: &HashMap<String, Vec<String>>,
+    new: &HashMap<String, Vec<String>>,
+) -> (Vec<Node>, Vec<Node>) {
+    let (old, new) = (nodes_of(old), nodes_of(new));
+    let added = new.iter().filter(|p| !old.contains(p)).cloned().collect();
+    let removed = old.iter().filter(|p| !new.contains(p)).cloned().collect();
+    (added, removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_profile_changes_are_one_sided() {
+        let old = HashMap::from([(
+            "PLATFORM_1".to_string(),
+            vec!["a".to_string(), "b".to_string()],
+        )]);
+        let new = HashMap::from([
+            ("PLATFORM_1".to_string(), vec!["b".to_string()]),
+            ("PLATFORM_2".to_string(), vec!["c".to_string()]),
+        ]);
+        let (added, removed) = profile_changes(&old, &new);
+        assert_eq!(added, vec![Node::new("PLATFORM_2", "c")]);
+        assert_eq!(removed, vec![Node::new("PLATFORM_1", "a")]);
+    }
 }
@@ -0,0 +1,268 @@
+//! Tags for annotating subjects and content.
+//!
+//! Users can tag subjects, such as "election" or "verified-source", and
+//! individual items of content by their content ID, such as "needs review".
+//! Tags are trimmed and lowercased so the same tag is always written the same
+//! way.
+//!
+//! Tags on a subject are stored on the subject, so they can be seen by anyone
+//! who can see the subject and changed by anyone who can edit it. Tags on
+//! content are stored in the "content_tags" collection along with the profile
+//! the content is about and who added them. Content can be tagged by anyone
+//! who can see a subject with its profile, and its tags can be seen by anyone
+//! who can see the content. Tags on content can be removed by whoever added
+//! them and by the editors of a subject with its profile.
+//!
+//! /view and /search can be narrowed to subjects with any of a set of tags,
+//! and to content with any of a set of tags.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::options::UpdateOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::subject::Subject;
+use crate::database::DBHandle;
+
+// The longest a tag can be.
+const MAX_TAG_LEN: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContentTag {
+    pub platform: String,
+    // The ID of the profile the content is about.
+    #[serde(default)]
+    pub id: String,
+    pub content_id: String,
+    pub tag: String,
+    pub tagged_by: String,
+    pub tagged_at: DateTime<Utc>,
+}
+
+/// How many of the subjects a user can see and how many items of content
+/// about them have a tag.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TagUsage {
+    pub tag: String,
+    pub subjects: u64,
+    pub content: u64,
+}
+
+/// The tags trimmed, lowercased and deduplicated, or None if any of them are
+/// empty or too long.
+pub fn normalise(tags: &[String]) -> Option<Vec<String>> {
+    let mut normalised: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() || tag.chars().count() > MAX_TAG_LEN {
+            return None;
+        }
+        if !normalised.contains(&tag) {
+            normalised.push(tag);
+        }
+    }
+    Some(normalised)
+}
+
+/// Adds the tags to an item of content about the profile.
+pub async fn tag_content(
+    platform: &str,
+    id: &str,
+    content_id: &str,
+    tags: &[String],
+    user: &str,
+    db: &mut DBHandle,
+) {
+    let t_coll: Collection<ContentTag> = db.collection("content_tags");
+    let options = UpdateOptions::builder().upsert(true).build();
+    for tag in tags {
+        t_coll
+            .update_one_with_session(
+                doc! {"platform": platform, "content_id": content_id, "tag": tag},
+                doc! {"$setOnInsert": {
+                    "id": id,
+                    "tagged_by": user,
+                    "tagged_at": mongodb::bson::to_bson(&Utc::now()).unwrap()
+                }},
+                options.clone(),
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+    }
+}
+
+/// Removes the tags from an item of content, or only those added by
+/// `tagged_by` if given.
+pub async fn untag_content(
+    platform: &str,
+    content_id: &str,
+    tags: &[String],
+    tagged_by: Option<&str>,
+    db: &mut DBHandle,
+) {
+    let mut filter = doc! {
+        "platform": platform,
+        "content_id": content_id,
+        "tag": {"$in": tags}
+    };
+    if let Some(user) = tagged_by {
+        filter.insert("tagged_by", user);
+    }
+    let t_coll: Collection<ContentTag> = db.collection("content_tags");
+    t_coll
+        .delete_many_with_session(filter, None, &mut db.session)
+        .await
+        .unwrap();
+}
+
+/// The tags on each of the items of content on a platform, by content ID.
+pub async fn content_tags(
+    platform: &str,
+    content_ids: &[String],
+    db: &mut DBHandle,
+) -> HashMap<String, Vec<String>> {
+    let filter =
+        doc! {"platform": platform, "content_id": {"$in": content_ids}};
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    for t in find(filter, db).await {
+        tags.entry(t.content_id).or_default().push(t.tag);
+    }
+    tags
+}
+
+/// The IDs of the items of content with any of the tags, by platform.
+pub async fn tagged_content(
+    tags: &[String],
+    db: &mut DBHandle,
+) -> HashMap<String, Vec<String>> {
+    let mut content: HashMap<String, Vec<String>> = HashMap::new();
+    for t in find(doc! {"tag": {"$in": tags}}, db).await {
+        content.entry(t.platform).or_default().push(t.content_id);
+    }
+    content
+}
+
+/// How often each tag is used on the subjects and on content about their
+/// profiles.
+pub async fn usage(subjects: &[Subject], db: &mut DBHandle) -> Vec<TagUsage> {
+    let profiles: Vec<Document> = subjects
+        .iter()
+        .flat_map(|s| s.profiles.iter())
+        .map(|(platform, ids)| doc! {"platform": platform, "id": {"$in": ids}})
+        .collect();
+    if profiles.is_empty() {
+        return count_usage(subjects, &[]);
+    }
+    let t_coll: Collection<ContentTag> = db.collection("content_tags");
+    let mut cursor = t_coll
+        .aggregate_with_session(
+            [
+                doc! {"$match": {"$or": profiles}},
+                doc! {"$group": {"_id": "$tag", "count": {"$sum": 1_i32}}},
+            ],
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    let counts: Vec<Document> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+    let content: Vec<(String, u64)> = counts
+        .into_iter()
+        .filter_map(|d| {
+            let tag = d.get_str("_id").ok()?.to_string();
+            let count = d.get_i32("count").ok()? as u64;
+            Some((tag, count))
+        })
+        .collect();
+    count_usage(subjects, &content)
+}
+
+// Combines the tags on the subjects with the counts of tags on content.
+fn count_usage(
+    subjects: &[Subject],
+    content: &[(String, u64)],
+) -> Vec<TagUsage> {
+    let mut usage: BTreeMap<&str, TagUsage> = BTreeMap::new();
+    for tag in subjects.iter().flat_map(|s| s.tags.iter()) {
+        usage
+            .entry(tag)
+            .or_insert_with(|| TagUsage {
+                tag: tag.clone(),
+                subjects: 0,
+                content: 0,
+            })
+            .subjects += 1;
+    }
+    for (tag, count) in content {
+        usage
+            .entry(tag)
+            .or_insert_with(|| TagUsage {
+                tag: tag.clone(),
+                subjects: 0,
+                content: 0,
+            })
+            .content += count;
+    }
+    usage.into_values().collect()
+}
+
+async fn find(filter: Document, db: &mut DBHandle) -> Vec<ContentTag> {
+    let t_coll: Collection<ContentTag> = db.collection("content_tags");
+    let mut cursor = t_coll
+        .find_with_session(filter, None, &mut db.session)
+        .await
+        .unwrap();
+    cursor.stream(&mut db.session).try_collect().await.unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalise_tags() {
+        let tags = vec![
+            " Election ".to_string(),
+            "election".to_string(),
+            "needs review".to_string(),
+        ];
+        assert_eq!(
+            normalise(&tags),
+            Some(vec!["election".to_string(), "needs review".to_string()])
+        );
+        assert_eq!(normalise(&[" ".to_string()]), None);
+        assert_eq!(normalise(&["a".repeat(MAX_TAG_LEN + 1)]), None);
+    }
+
+    #[test]
+    fn test_count_usage_combines_subjects_and_content() {
+        let subject = |tags: &[&str]| Subject {
+            uuid: String::new(),
+            created_at: Utc::now(),
+            created_by: String::new(),
+            name: String::new(),
+            profiles: HashMap::new(),
+            description: None,
+            editors: Vec::new(),
+            viewers: Vec::new(),
+            visibility: Default::default(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            version: 0,
+        };
+        let subjects = vec![subject(&["a", "b"]), subject(&["a"])];
+        let content = vec![("b".to_string(), 3), ("c".to_string(), 1)];
+
+        let usage = count_usage(&subjects, &content);
+        let counts: Vec<(&str, u64, u64)> = usage
+            .iter()
+            .map(|u| (u.tag.as_str(), u.subjects, u.content))
+            .collect();
+        assert_eq!(counts, vec![("a", 2, 0), ("b", 1, 3), ("c", 0, 1)]);
+    }
+}
@@ -0,0 +1,182 @@
+//! The trash bin for deleted subjects and groups.
+//!
+//! Deleting a subject or group moves it out of its collection and into the
+//! "trash" collection along with the groups it was removed from, so nothing
+//! else has to know about deleted subjects and groups. Its owner can restore
+//! it until it has been in the trash for longer than the purge window, after
+//! which a background worker removes it for good.
+//!
+//! Queue items for the profiles of a deleted subject are released when it is
+//! deleted and registered again when it is restored.
+
+use chrono::{DateTime, Duration, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::config::TrashConfig;
+use crate::database::DBHandle;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashKind {
+    Subject,
+    Group,
+}
+
+impl TrashKind {
+    /// The collection items of this kind are restored to.
+    pub fn collection(&self) -> &'static str {
+        match self {
+            Self::Subject => "subjects",
+            Self::Group => "groups",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrashItem {
+    pub kind: TrashKind,
+    pub uuid: String,
+    pub name: String,
+    pub created_by: String,
+    pub deleted_at: DateTime<Utc>,
+    // UUIDs of the groups the item was removed from when it was deleted.
+    pub groups: Vec<String>,
+    // The subject or group as it was stored.
+    pub document: Document,
+}
+
+/// A trashed subject or group as shown to its owner.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrashEntry {
+    pub kind: TrashKind,
+    pub uuid: String,
+    pub name: String,
+    pub deleted_at: DateTime<Utc>,
+    pub purge_at: DateTime<Utc>,
+}
+
+impl TrashItem {
+    pub fn new<T: Serialize>(
+        kind: TrashKind,
+        uuid: &str,
+        name: &str,
+        created_by: &str,
+        groups: Vec<String>,
+        item: &T,
+    ) -> Self {
+        Self {
+            kind,
+            uuid: uuid.to_string(),
+            name: name.to_string(),
+            created_by: created_by.to_string(),
+            deleted_at: Utc::now(),
+            groups,
+            document: bson::to_document(item).unwrap(),
+        }
+    }
+
+    pub fn entry(&self, config: &TrashConfig) -> TrashEntry {
+        TrashEntry {
+            kind: self.kind,
+            uuid: self.uuid.clone(),
+            name: self.name.clone(),
+            deleted_at: self.deleted_at,
+            purge_at: self.deleted_at + Duration::days(config.purge_after_days),
+        }
+    }
+
+    pub async fn insert(&self, db: &mut DBHandle) {
+        let t_coll: Collection<TrashItem> = db.collection("trash");
+        t_coll
+            .insert_one_with_session(self, None, &mut db.session)
+            .await
+            .unwrap();
+    }
+
+    /// The item of the kind with the UUID trashed by the owner, if any and it
+    /// hasn't outlived the purge window.
+    pub async fn find(
+        kind: TrashKind,
+        uuid: &str,
+        owner: &str,
+        config: &TrashConfig,
+        db: &mut DBHandle,
+    ) -> Option<Self> {
+        let t_coll: Collection<TrashItem> = db.collection("trash");
+        t_coll
+            .find_one_with_session(
+                doc! {
+                    "kind": bson::to_bson(&kind).unwrap(),
+                    "uuid": uuid,
+                    "created_by": owner,
+                    "deleted_at": {"$gte": purge_cutoff(config)}
+                },
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap()
+    }
+
+    /// Removes the item from the trash.
+    pub async fn remove(&self, db: &mut DBHandle) {
+        let t_coll: Collection<TrashItem> = db.collection("trash");
+        t_coll
+            .delete_one_with_session(
+                doc! {"uuid": &self.uuid},
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+    }
+}
+
+/// Everything the owner has in the trash that hasn't outlived the purge
+/// window, most recently deleted first.
+pub async fn list(
+    owner: &str,
+    config: &TrashConfig,
+    db: &mut DBHandle,
+) -> Vec<TrashItem> {
+    let t_coll: Collection<TrashItem> = db.collection("trash");
+    let options = FindOptions::builder().sort(doc! {"deleted_at": -1}).build();
+    let mut cursor = t_coll
+        .find_with_session(
+            doc! {
+                "created_by": owner,
+                "deleted_at": {"$gte": purge_cutoff(config)}
+            },
+            options,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    cursor.stream(&mut db.session).try_collect().await.unwrap()
+}
+
+/// Removes everything that has been in the trash for longer than the purge
+/// window, returning how many items were removed.
+pub async fn purge(config: &TrashConfig, db: &mut DBHandle) -> u64 {
+    let t_coll: Collection<TrashItem> = db.collection("trash");
+    t_coll
+        .delete_many_with_session(
+            doc! {"deleted_at": {"$lt": purge_cutoff(config)}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap()
+        .deleted_count
+}
+
+// Items deleted before this have outlived the purge window, whether or not
+// the worker has removed them yet.
+fn purge_cutoff(config: &TrashConfig) -> Bson {
+    let cutoff = Utc::now() - Duration::days(config.purge_after_days);
+    bson::to_bson(&cutoff).unwrap()
+}
@@ -8,6 +8,7 @@ use serde::{Deserialize, Serialize};
 use tokio_stream::StreamExt;
 use uuid::Uuid;
 
+use crate::concepts::acl;
 use crate::concepts::group::Group;
 use crate::concepts::subject::Subject;
 use crate::database::DBHandle;
@@ -86,6 +87,55 @@ impl User {
         }
     }
 
+    /// Subjects owned by other users and shared with this user.
+    pub async fn shared_subjects(&self, db: &mut DBHandle) -> Vec<Subject> {
+        let subj_coll: Collection<Subject> = db.collection("subjects");
+        let mut cursor = subj_coll
+            .find_with_session(
+                acl::shared_with(doc! {}, &self.uuid),
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+        cursor.stream(&mut db.session).try_collect().await.unwrap()
+    }
+
+    /// Groups owned by other users and shared with this user.
+    pub async fn shared_groups(&self, db: &mut DBHandle) -> Vec<Group> {
+        let group_coll: Collection<Group> = db.collection("groups");
+        let mut cursor = group_coll
+            .find_with_session(
+                acl::shared_with(doc! {}, &self.uuid),
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+        cursor.stream(&mut db.session).try_collect().await.unwrap()
+    }
+
+    /// Subjects selected directly by UUID or through a group by UUID,
+    /// including the groups nested in it. Only subjects and groups the user
+    /// can read are considered. If nothing is selected then all of the user's
+    /// own and shared subjects are returned.
+    pub async fn selected_subjects(
+        &self,
+        subjects: &[String],
+        groups: &[String],
+        db: &mut DBHandle,
+    ) -> Vec<Subject> {
+        if subjects.is_empty() && groups.is_empty() {
+            let mut all = self.subjects(db).await.unwrap_or_default();
+            all.extend(self.shared_subjects(db).await);
+            return all;
+        }
+
+        acl::readable_subjects(subjects, groups, &self.uuid, db)
+            .await
+            .0
+    }
+
     pub async fn with_key(key: &str, db: &mut DBHandle) -> Option<Self> {
         let users_coll: Collection<User> = db.collection("users");
         users_coll
@@ -97,6 +147,14 @@ impl User {
             .await
             .unwrap()
     }
+
+    pub async fn with_uuid(uuid: &str, db: &mut DBHandle) -> Option<Self> {
+        let users_coll: Collection<User> = db.collection("users");
+        users_coll
+            .find_one_with_session(doc! {"uuid": uuid}, None, &mut db.session)
+            .await
+            .unwrap()
+    }
 }
 
 #[cfg(test)]
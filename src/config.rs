@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use std::time::Duration;
 
 use mongodb::options::{ClientOptions, Credential, ServerAddress};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Deserialize)]
 pub struct IConfig {
@@ -13,6 +13,18 @@ pub struct IConfig {
     pub presence_types: HashMap<String, Vec<String>>,
     #[serde(default = "Settings::default")]
     pub settings: Settings,
+    #[serde(default = "QueueConfig::default")]
+    pub queue: QueueConfig,
+    #[serde(default = "ConsensusConfig::default")]
+    pub consensus: ConsensusConfig,
+    #[serde(default = "ReputationConfig::default")]
+    pub reputation: ReputationConfig,
+    #[serde(default = "RetentionConfig::default")]
+    pub retention: RetentionConfig,
+    #[serde(default = "PublicConfig::default")]
+    pub public: PublicConfig,
+    #[serde(default = "TrashConfig::default")]
+    pub trash: TrashConfig,
     pub network: NetworkConfig,
     pub tls: TLSConfig,
 }
@@ -50,6 +62,12 @@ pub struct Settings {
     pub log_level: String,
     #[serde(default = "Settings::default_queue_timeout_secs")]
     pub queue_timeout_secs: i64,
+    #[serde(default = "Settings::default_queue_backoff_after")]
+    pub queue_backoff_after: u64,
+    #[serde(default = "Settings::default_queue_backoff_secs")]
+    pub queue_backoff_secs: i64,
+    #[serde(default = "Settings::default_queue_max_backoff_secs")]
+    pub queue_max_backoff_secs: i64,
 }
 
 impl Default for Settings {
@@ -57,6 +75,9 @@ impl Default for Settings {
         Self {
             log_level: Self::default_log_level(),
             queue_timeout_secs: Self::default_queue_timeout_secs(),
+            queue_backoff_after: Self::default_queue_backoff_after(),
+            queue_backoff_secs: Self::default_queue_backoff_secs(),
+            queue_max_backoff_secs: Self::default_queue_max_backoff_secs(),
         }
     }
 }
@@ -69,6 +90,324 @@ impl Settings {
     pub fn default_queue_timeout_secs() -> i64 {
         30
     }
+
+    pub fn default_queue_backoff_after() -> u64 {
+        3
+    }
+
+    pub fn default_queue_backoff_secs() -> i64 {
+        60
+    }
+
+    pub fn default_queue_max_backoff_secs() -> i64 {
+        86400
+    }
+
+    /// How long a queue item is held back after failing the given number of
+    /// times in a row. Items are not held back until they have failed
+    /// `queue_backoff_after` times, then for a period that doubles with every
+    /// further failure up to the maximum.
+    pub fn queue_backoff(&self, failures: u64) -> chrono::Duration {
+        if failures < self.queue_backoff_after {
+            return chrono::Duration::zero();
+        }
+        let doublings = (failures - self.queue_backoff_after).min(32) as u32;
+        let secs = self
+            .queue_backoff_secs
+            .saturating_mul(2_i64.saturating_pow(doublings))
+            .min(self.queue_max_backoff_secs);
+        chrono::Duration::seconds(secs)
+    }
+}
+
+/// Fairness settings and refresh policies for the queue, the latter keyed by
+/// platform.
+///
+/// For example,
+/// ```toml
+/// [queue]
+/// max_leases_per_provider = 50
+/// round_robin = true
+/// avoid_repeat_provider = true
+///
+/// [queue.platforms.PLATFORM_1]
+/// min_interval_secs = 600
+/// max_interval_secs = 86400
+/// weight = 2
+/// max_leases = 10
+/// ```
+#[derive(Clone, Deserialize)]
+pub struct QueueConfig {
+    #[serde(default)]
+    pub platforms: HashMap<String, PlatformPolicy>,
+    /// The most jobs a single provider may hold at once.
+    #[serde(default)]
+    pub max_leases_per_provider: Option<u64>,
+    /// Providers asking for jobs on the same platform take turns.
+    #[serde(default)]
+    pub round_robin: bool,
+    /// How recently a provider must have asked for a job on a platform to
+    /// have a turn.
+    #[serde(default = "QueueConfig::default_round_robin_secs")]
+    pub round_robin_secs: i64,
+    /// Providers are given profiles they didn't do last where possible.
+    #[serde(default)]
+    pub avoid_repeat_provider: bool,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            platforms: HashMap::new(),
+            max_leases_per_provider: None,
+            round_robin: false,
+            round_robin_secs: Self::default_round_robin_secs(),
+            avoid_repeat_provider: false,
+        }
+    }
+}
+
+impl QueueConfig {
+    pub fn default_round_robin_secs() -> i64 {
+        60
+    }
+
+    /// The policy for a platform, or the default policy if it has none.
+    pub fn policy(&self, platform: &str) -> PlatformPolicy {
+        self.platforms.get(platform).cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct PlatformPolicy {
+    /// Profiles are not refreshed more often than this.
+    #[serde(default)]
+    pub min_interval_secs: i64,
+    /// Profiles that haven't been refreshed for this long are handed out
+    /// before any others.
+    #[serde(default)]
+    pub max_interval_secs: Option<i64>,
+    /// How likely this platform is to be chosen relative to the others a
+    /// provider can do jobs for.
+    #[serde(default = "PlatformPolicy::default_weight")]
+    pub weight: u64,
+    /// The most jobs for this platform that may be leased at once.
+    #[serde(default)]
+    pub max_leases: Option<u64>,
+}
+
+impl Default for PlatformPolicy {
+    fn default() -> Self {
+        Self {
+            min_interval_secs: 0,
+            max_interval_secs: None,
+            weight: Self::default_weight(),
+            max_leases: None,
+        }
+    }
+}
+
+impl PlatformPolicy {
+    pub fn default_weight() -> u64 {
+        1
+    }
+}
+
+/// Consensus between data providers for queue jobs.
+///
+/// When enabled, each queue job is given to `providers` different data
+/// providers in turn and only values agreed on by at least `quorum` of them
+/// are committed. See [`crate::concepts::consensus`].
+#[derive(Clone, Deserialize)]
+pub struct ConsensusConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "ConsensusConfig::default_providers")]
+    pub providers: usize,
+    #[serde(default = "ConsensusConfig::default_quorum")]
+    pub quorum: usize,
+}
+
+impl Default for ConsensusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            providers: Self::default_providers(),
+            quorum: Self::default_quorum(),
+        }
+    }
+}
+
+impl ConsensusConfig {
+    pub fn default_providers() -> usize {
+        3
+    }
+
+    pub fn default_quorum() -> usize {
+        2
+    }
+
+    /// The number of providers each job is given to, at least one.
+    pub fn providers(&self) -> usize {
+        self.providers.max(1)
+    }
+
+    /// The number of providers that must agree on a value, between one and
+    /// the number of providers.
+    pub fn quorum(&self) -> usize {
+        self.quorum.clamp(1, self.providers())
+    }
+}
+
+/// Reputation of data providers. See [`crate::concepts::reputation`].
+#[derive(Clone, Deserialize)]
+pub struct ReputationConfig {
+    /// Data from providers with a lower score is hidden from /view.
+    #[serde(default = "ReputationConfig::default_min_score")]
+    pub min_score: f64,
+    /// How far apart two submissions about the same profile may be retrieved
+    /// and still be compared.
+    #[serde(default = "ReputationConfig::default_window_secs")]
+    pub window_secs: i64,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        Self {
+            min_score: Self::default_min_score(),
+            window_secs: Self::default_window_secs(),
+        }
+    }
+}
+
+impl ReputationConfig {
+    pub fn default_min_score() -> f64 {
+        0.5
+    }
+
+    pub fn default_window_secs() -> i64 {
+        600
+    }
+}
+
+/// Retention rules for data. See [`crate::concepts::retention`].
+///
+/// For example,
+/// ```toml
+/// [retention]
+/// interval_secs = 3600
+/// exempt_subjects = ["..."]
+///
+/// [[retention.rules]]
+/// platform = "PLATFORM_2"
+/// presence_type = "listening_now"
+/// keep_days = 30
+/// aggregate = true
+/// ```
+#[derive(Clone, Deserialize)]
+pub struct RetentionConfig {
+    #[serde(default)]
+    pub rules: Vec<RetentionRule>,
+    /// How often the rules are enforced.
+    #[serde(default = "RetentionConfig::default_interval_secs")]
+    pub interval_secs: u64,
+    /// UUIDs of subjects whose profiles' data is kept regardless of the rules.
+    #[serde(default)]
+    pub exempt_subjects: Vec<String>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            interval_secs: Self::default_interval_secs(),
+            exempt_subjects: Vec::new(),
+        }
+    }
+}
+
+impl RetentionConfig {
+    pub fn default_interval_secs() -> u64 {
+        3600
+    }
+}
+
+/// How long data of a type on a platform is kept. A rule with neither a
+/// presence type nor a content type applies to all presence and content on
+/// the platform. Metadata is always kept.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RetentionRule {
+    pub platform: String,
+    #[serde(default)]
+    pub presence_type: Option<String>,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    pub keep_days: i64,
+    /// Expired presence is collapsed into intervals of continuous presence
+    /// rather than simply deleted.
+    #[serde(default)]
+    pub aggregate: bool,
+    /// Observations of presence further apart than this start a new interval.
+    #[serde(default = "RetentionRule::default_interval_gap_secs")]
+    pub interval_gap_secs: i64,
+}
+
+impl RetentionRule {
+    pub fn default_interval_gap_secs() -> i64 {
+        600
+    }
+}
+
+/// Unauthenticated access to public subjects and groups through /public.
+#[derive(Clone, Deserialize)]
+pub struct PublicConfig {
+    /// Requests each client may make to /public every minute.
+    #[serde(default = "PublicConfig::default_requests_per_min")]
+    pub requests_per_min: u32,
+}
+
+impl Default for PublicConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_min: Self::default_requests_per_min(),
+        }
+    }
+}
+
+impl PublicConfig {
+    pub fn default_requests_per_min() -> u32 {
+        60
+    }
+}
+
+/// Deleted subjects and groups. See [`crate::concepts::trash`].
+#[derive(Clone, Deserialize)]
+pub struct TrashConfig {
+    /// Days a deleted subject or group can be restored for.
+    #[serde(default = "TrashConfig::default_purge_after_days")]
+    pub purge_after_days: i64,
+    /// How often expired subjects and groups are purged.
+    #[serde(default = "TrashConfig::default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            purge_after_days: Self::default_purge_after_days(),
+            interval_secs: Self::default_interval_secs(),
+        }
+    }
+}
+
+impl TrashConfig {
+    pub fn default_purge_after_days() -> i64 {
+        30
+    }
+
+    pub fn default_interval_secs() -> u64 {
+        3600
+    }
 }
 
 #[derive(Clone, Deserialize)]
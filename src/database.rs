@@ -5,6 +5,7 @@ use axum::extract::FromRequestParts;
 use axum::http::request::Parts;
 use axum::response::Response;
 use mongodb::bson::Document;
+use mongodb::error::{Error, ErrorKind, TRANSIENT_TRANSACTION_ERROR};
 use mongodb::options::IndexOptions;
 use mongodb::results::CreateIndexResult;
 use mongodb::ClientSession;
@@ -50,11 +51,33 @@ impl DBHandle {
         self.db.collection::<T>(name)
     }
 
-    pub async fn drop(&self) -> Result<(), mongodb::error::Error> {
+    pub async fn drop(&self) -> Result<(), Error> {
         self.db.drop(None).await
     }
 }
 
+// The server's error code for a write to a document another transaction has
+// written since this one started.
+const WRITE_CONFLICT: i32 = 112;
+
+/// The result of an operation in a transaction, or None if it conflicted with
+/// another transaction writing the same documents. Any other error panics.
+pub fn unless_conflict<T>(result: Result<T, Error>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) if is_conflict(&e) => None,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+fn is_conflict(error: &Error) -> bool {
+    error.contains_label(TRANSIENT_TRANSACTION_ERROR)
+        || matches!(
+            *error.kind,
+            ErrorKind::Command(ref e) if e.code == WRITE_CONFLICT
+        )
+}
+
 pub async fn open(
     config: &IConfig,
 ) -> Result<DBPool, Box<dyn std::error::Error>> {
@@ -83,6 +106,7 @@ pub async fn open(
         create_indexes(&database).await;
         tracing::info!("Created MongoDB indices.")
     }
+    create_required_indexes(&database).await;
 
     Ok(DBPool {
         client: mongo_client,
@@ -165,11 +189,214 @@ async fn create_indexes(database: &Database) {
     )
     .await
     .unwrap();
+    create_index(
+        "Edges From Index",
+        "edges",
+        doc! {"from.platform" : 1_u32, "from.id" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Edges To Index",
+        "edges",
+        doc! {"to.platform" : 1_u32, "to.id" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Submissions Queue ID Index",
+        "submissions",
+        doc! {"queue_id" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Disagreements Provider Index",
+        "disagreements",
+        doc! {"provider" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_unique_index(
+        "Reputations User Index",
+        "reputations",
+        doc! {"user" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Queue Providers Index",
+        "queue_providers",
+        doc! {"platform" : 1_u32, "provider" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Username Mappings Index",
+        "username_mappings",
+        doc! {"platform" : 1_u32, "username" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Presence Intervals Index",
+        "presence_intervals",
+        doc! {"id" : 1_u32, "platform" : 1_u32, "presence_type" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Subjects Editors Index",
+        "subjects",
+        doc! {"editors" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Subjects Viewers Index",
+        "subjects",
+        doc! {"viewers" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Groups Editors Index",
+        "groups",
+        doc! {"editors" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Groups Viewers Index",
+        "groups",
+        doc! {"viewers" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Feed Tokens Index",
+        "feed_tokens",
+        doc! {"hashed_token" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Content Tags Index",
+        "content_tags",
+        doc! {"platform" : 1_u32, "content_id" : 1_u32, "tag" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Content Tags Profile Index",
+        "content_tags",
+        doc! {"platform" : 1_u32, "id" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Content Tags Tag Index",
+        "content_tags",
+        doc! {"tag" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Subjects Tags Index",
+        "subjects",
+        doc! {"tags" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Trash Index",
+        "trash",
+        doc! {"created_by" : 1_u32, "uuid" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+    create_index(
+        "Trash Deleted At Index",
+        "trash",
+        doc! {"deleted_at" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
+}
+
+// Indexes that queries or upserts rely on to be correct. These are created on
+// every start so that databases from before they were added get them too.
+// Creating an index that already exists does nothing.
+async fn create_required_indexes(database: &Database) {
+    create_index(
+        "Data Text Index",
+        "data",
+        doc! {"body" : "text", "bio" : "text", "display_name" : "text"},
+        database,
+    )
+    .await
+    .unwrap();
+    create_unique_index(
+        "Unique Edge",
+        "edges",
+        doc! {
+            "from.platform" : 1_u32,
+            "from.id" : 1_u32,
+            "to.platform" : 1_u32,
+            "to.id" : 1_u32,
+            "kind" : 1_u32
+        },
+        database,
+    )
+    .await
+    .unwrap();
+    create_unique_index(
+        "Unique Edge Source",
+        "edge_sources",
+        doc! {
+            "from.platform" : 1_u32,
+            "from.id" : 1_u32,
+            "to.platform" : 1_u32,
+            "to.id" : 1_u32,
+            "kind" : 1_u32,
+            "content_id" : 1_u32
+        },
+        database,
+    )
+    .await
+    .unwrap();
+    create_unique_index(
+        "Unique Reputation Check",
+        "reputation_checks",
+        doc! {"provider" : 1_u32, "item" : 1_u32},
+        database,
+    )
+    .await
+    .unwrap();
 }
 
 async fn unique_subject_name_index(
     database: &Database,
-) -> Result<CreateIndexResult, mongodb::error::Error> {
+) -> Result<CreateIndexResult, Error> {
     let idx_options = IndexOptions::builder()
         .name(String::from("Unique Subject Name"))
         .unique(true)
@@ -191,7 +418,7 @@ async fn create_index(
     collection_name: &str,
     keys: Document,
     database: &Database,
-) -> Result<CreateIndexResult, mongodb::error::Error> {
+) -> Result<CreateIndexResult, Error> {
     let idx_options =
         IndexOptions::builder().name(index_name.to_string()).build();
 
@@ -206,6 +433,28 @@ async fn create_index(
         .await
 }
 
+async fn create_unique_index(
+    index_name: &str,
+    collection_name: &str,
+    keys: Document,
+    database: &Database,
+) -> Result<CreateIndexResult, Error> {
+    let idx_options = IndexOptions::builder()
+        .name(index_name.to_string())
+        .unique(true)
+        .build();
+
+    let idx_model = IndexModel::builder()
+        .keys(keys)
+        .options(idx_options)
+        .build();
+
+    database
+        .collection::<Document>(collection_name)
+        .create_index(idx_model, None)
+        .await
+}
+
 pub async fn drop_database(database: &DBHandle) {
     database.drop().await.unwrap();
 }
@@ -9,5 +9,9 @@ pub mod utils;
 
 #[tokio::main]
 async fn main() {
-    boot::instrumentality().await;
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("import") => boot::import(&args[2..]).await,
+        _ => boot::instrumentality().await,
+    }
 }
@@ -7,44 +7,121 @@
 //!
 //! See [`Data`] for examples of valid data objects.
 
+use std::collections::HashSet;
+
 use axum::Extension;
 use axum::{http::StatusCode, response::IntoResponse, Json};
+use chrono::Duration;
 use mongodb::bson::doc;
 use mongodb::Collection;
+use serde::{Deserialize, Serialize};
 
+use crate::concepts::consensus;
 use crate::concepts::data::{Data, Datas};
+use crate::concepts::edge::Edge;
+use crate::concepts::reputation::{PendingAssessment, Reputation};
 use crate::concepts::user::User;
 use crate::config::IConfig;
 use crate::database::DBHandle;
+use crate::routes::live::{LiveEvent, LiveFeed};
 use crate::routes::queue;
 use crate::routes::queue::InternalQueueItem;
 use crate::routes::response::{ErrorResponse, OkResponse};
 
+// The most jobs that can be submitted in one request.
+const MAX_JOBS: usize = 100;
+
+/// Data for a single job, or for several jobs leased together through
+/// /queue?count=N. Every job in a batch is added in one transaction, so if any
+/// of them is invalid none of them are added.
+///
+/// For example,
+/// ```json
+/// {
+///     "jobs": [
+///         {"queue_id": "...", "data": [...]},
+///         {"queue_id": "...", "data": [...]}
+///     ]
+/// }
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum AddRequest {
+    Batch { jobs: Vec<Datas> },
+    Single(Datas),
+}
+
+impl AddRequest {
+    fn into_jobs(self) -> Vec<Datas> {
+        match self {
+            AddRequest::Batch { jobs } => jobs,
+            AddRequest::Single(datas) => vec![datas],
+        }
+    }
+}
+
 pub async fn add(
     user: User,
     mut db: DBHandle,
     Extension(config): Extension<IConfig>,
-    Json(datas): Json<Datas>,
+    Extension(feed): Extension<LiveFeed>,
+    Json(req): Json<AddRequest>,
 ) -> impl IntoResponse {
-    if datas.data.is_empty() {
+    let jobs = req.into_jobs();
+    if jobs.is_empty() || jobs.iter().any(|d| d.data.is_empty()) {
         return error!(BAD_REQUEST, "No data was submitted.");
     }
+    if jobs.len() > MAX_JOBS {
+        return error!(
+            BAD_REQUEST,
+            &format!("You may submit at most {MAX_JOBS} jobs at once.")
+        );
+    }
 
-    if let Some(queue_id) = datas.queue_id.as_ref() {
+    // Consensus only holds back data submitted for queue jobs, so while it is
+    // enabled only admins may submit data without one.
+    if config.consensus.enabled
+        && !user.admin
+        && jobs.iter().any(|d| d.queue_id.is_none())
+    {
+        return error!(
+            FORBIDDEN,
+            "Data must be submitted for a queue job while consensus is enabled."
+        );
+    }
+
+    let mut queue_ids = HashSet::new();
+    for queue_id in jobs.iter().filter_map(|d| d.queue_id.as_ref()) {
+        if !queue_ids.insert(queue_id) {
+            return error!(BAD_REQUEST, "Each job may only be submitted once.");
+        }
         if get_queue_item(queue_id, &user, &mut db).await.is_none() {
             return error!(BAD_REQUEST, "Invalid queue ID.");
         }
     }
 
-    match process(datas, &config, &user, &mut db).await {
-        true => ok!(CREATED),
-        false => error!(
-            BAD_REQUEST,
-            "No valid data was submitted. Ensure the given platforms and 
+    let mut events = Vec::new();
+    let mut assessments = Vec::new();
+    for datas in jobs {
+        match process(datas, &config, &user, &mut assessments, &mut db).await {
+            Some(mut job_events) => events.append(&mut job_events),
+            None => {
+                return error!(
+                    BAD_REQUEST,
+                    "No valid data was submitted. Ensure the given platforms and 
                 content/presence types are supported by this server. Ensure all 
                 data was correctly labeled for queue jobs."
-        ),
+                );
+            }
+        }
+    }
+
+    if db.session.commit_transaction().await.is_err() {
+        return error!(INTERNAL_SERVER_ERROR, "Failed to add data.");
     }
+    Reputation::record(assessments, &db).await;
+    feed.publish(events);
+    ok!(CREATED)
 }
 
 async fn get_queue_item(
@@ -69,33 +146,42 @@ async fn get_queue_item(
 // - If there is a valid queue ID, if there is a valid queue item for that queue
 //   ID, is there any data remaining after verifying against the queue item?
 // - Does the data succeed in verifying against the queue?
-// And then it adds the data if all the answers are yes.
+// And then it adds the data if all the answers are yes, returning the added
+// data for live subscribers and collecting assessments of providers to be
+// recorded once the transaction is committed.
 async fn process(
     datas: Datas,
     config: &IConfig,
     user: &User,
+    assessments: &mut Vec<PendingAssessment>,
     db: &mut DBHandle,
-) -> bool {
-    let data_coll: Collection<Data> = db.collection("data");
-
+) -> Option<Vec<LiveEvent>> {
     let datas = datas.tag(&user.uuid).verify_for_config(config);
 
     if datas.data.is_empty() {
-        return false;
+        return None;
     }
 
     if let Some(queue_id) = &datas.queue_id.clone() {
-        let queue_item = get_queue_item(queue_id, user, db).await;
-
-        if queue_item.is_none() {
-            return false;
-        }
-
-        let queue_item = queue_item.unwrap();
+        let queue_item = get_queue_item(queue_id, user, db).await?;
+        let profile =
+            (queue_item.platform_id.clone(), queue_item.platform.clone());
         let datas = datas.verify_for_queue(queue_item);
 
         if datas.data.is_empty() {
-            return false;
+            return None;
+        }
+
+        if config.consensus.enabled {
+            return process_consensus(
+                datas,
+                profile,
+                config,
+                user,
+                assessments,
+                db,
+            )
+            .await;
         }
 
         let (platform_id, platform, added_by, username) = datas.info();
@@ -111,19 +197,98 @@ async fn process(
         .await;
 
         if !process_success {
-            false
+            None
         } else {
-            data_coll
-                .insert_many_with_session(datas.data, None, &mut db.session)
-                .await
-                .unwrap();
-            db.session.commit_transaction().await.is_ok()
+            assessments.extend(assess(&datas.data, config, user, db).await);
+            insert(datas.data, db).await
         }
     } else {
-        data_coll
-            .insert_many_with_session(datas.data, None, &mut db.session)
-            .await
-            .unwrap();
-        db.session.commit_transaction().await.is_ok()
+        assessments.extend(assess(&datas.data, config, user, db).await);
+        insert(datas.data, db).await
+    }
+}
+
+// Holds the data back until the job has been done by enough providers, then
+// adds only the data they agree on.
+async fn process_consensus(
+    datas: Datas,
+    (platform_id, platform): (String, String),
+    config: &IConfig,
+    user: &User,
+    assessments: &mut Vec<PendingAssessment>,
+    db: &mut DBHandle,
+) -> Option<Vec<LiveEvent>> {
+    let queue_id = datas.queue_id.clone()?;
+    let resolution = consensus::submit(
+        &queue_id,
+        &user.uuid,
+        datas.data,
+        &config.consensus,
+        assessments,
+        db,
+    )
+    .await;
+
+    match resolution {
+        None => Some(Vec::new()),
+        Some(resolution) if resolution.data.is_empty() => {
+            queue::process(
+                &queue_id,
+                &platform_id,
+                &platform,
+                &user.uuid,
+                None,
+                db,
+            )
+            .await;
+            Some(Vec::new())
+        }
+        Some(resolution) => {
+            let datas = Datas {
+                queue_id: Some(queue_id.clone()),
+                data: resolution.data,
+            };
+            let (platform_id, platform, _, username) = datas.info();
+            queue::process(
+                &queue_id,
+                &platform_id,
+                &platform,
+                &user.uuid,
+                username,
+                db,
+            )
+            .await;
+            insert(datas.data, db).await
+        }
     }
 }
+
+async fn assess(
+    data: &[Data],
+    config: &IConfig,
+    user: &User,
+    db: &mut DBHandle,
+) -> Vec<PendingAssessment> {
+    let window = Duration::seconds(config.reputation.window_secs);
+    Reputation::assess(data, &user.uuid, window, db).await
+}
+
+async fn insert(data: Vec<Data>, db: &mut DBHandle) -> Option<Vec<LiveEvent>> {
+    let data_coll: Collection<Data> = db.collection("data");
+
+    Edge::record(&data, db).await;
+    let result = data_coll
+        .insert_many_with_session(&data, None, &mut db.session)
+        .await
+        .unwrap();
+
+    let events = data
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, data)| {
+            let id = result.inserted_ids.get(&i)?.as_object_id()?;
+            Some(LiveEvent { id, data })
+        })
+        .collect();
+    Some(events)
+}
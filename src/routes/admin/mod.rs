@@ -0,0 +1,5 @@
+//! Routes for administrators.
+
+pub mod queue;
+pub mod resolutions;
+pub mod retention;
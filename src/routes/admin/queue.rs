@@ -0,0 +1,325 @@
+//! Routes for inspecting and managing the queue.
+//!
+//! The /admin/queue routes are implemented here.
+//!
+//! See endpoint documentation at
+//! <https://docs.berserksystems.com/endpoints/admin/queue/>.
+//!
+//! Only administrators may use these routes. They allow stuck jobs to be found
+//! and freed without opening a database shell: items can be listed and
+//! filtered, staleness can be reviewed per platform, and individual items can
+//! be unlocked, requeued or prioritised. Items that no subject refers to any
+//! more can be purged.
+
+use std::collections::{HashMap, HashSet};
+
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use chrono::{Duration, TimeZone, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::edge::Node;
+use crate::concepts::subject::Subject;
+use crate::concepts::user::User;
+use crate::database::DBHandle;
+use crate::routes::queue::{timestamp, InternalQueueItem};
+use crate::routes::response::{
+    ErrorResponse, OkResponse, PurgeResponse, QueueItemsResponse,
+    StalenessResponse,
+};
+use crate::utils::deserialise_array::deserialise_array;
+
+// The most queue items that can be listed at once.
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct QueueItemsQuery {
+    #[serde(default, deserialize_with = "deserialise_array")]
+    platforms: Vec<String>,
+    locked: Option<bool>,
+    confirmed: Option<bool>,
+    // Only list items that haven't been processed for this long.
+    stale_secs: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// List queue items, highest priority and stalest first.
+pub async fn items(
+    user: User,
+    mut db: DBHandle,
+    Query(query): Query<QueueItemsQuery>,
+) -> impl IntoResponse {
+    if !user.admin {
+        return error!(UNAUTHORIZED, "Unauthorised.");
+    }
+    let limit = query.limit.unwrap_or(100);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return error!(
+            BAD_REQUEST,
+            &format!("You may list between 1 and {MAX_LIMIT} items.")
+        );
+    }
+
+    let mut filter = Document::new();
+    if !query.platforms.is_empty() {
+        filter.insert("platform", doc! {"$in": &query.platforms});
+    }
+    match query.locked {
+        Some(true) => {
+            filter.insert("lock_holder", doc! {"$ne": Bson::Null});
+        }
+        Some(false) => {
+            filter.insert("lock_holder", Bson::Null);
+        }
+        None => (),
+    }
+    if let Some(confirmed) = query.confirmed {
+        filter.insert("confirmed_id", confirmed);
+    }
+    if let Some(stale_secs) = query.stale_secs {
+        let since = Utc::now() - Duration::seconds(stale_secs);
+        filter.insert("last_processed", doc! {"$lte": timestamp(since)});
+    }
+
+    let options = FindOptions::builder()
+        .sort(doc! {"priority": -1_i32, "last_processed": 1_i32})
+        .limit(limit)
+        .build();
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let mut cursor = q_coll
+        .find_with_session(filter, options, &mut db.session)
+        .await
+        .unwrap();
+    let items: Vec<InternalQueueItem> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+
+    db.session.commit_transaction().await.unwrap();
+    ok!(OK, QueueItemsResponse::new(items))
+}
+
+/// How long ago the items on a platform were last processed, in seconds.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlatformStaleness {
+    pub platform: String,
+    pub items: u64,
+    pub locked: u64,
+    pub unconfirmed: u64,
+    pub p50: i64,
+    pub p90: i64,
+    pub p99: i64,
+    pub max: i64,
+}
+
+impl PlatformStaleness {
+    fn new(platform: String, items: &[InternalQueueItem]) -> Self {
+        let now = Utc::now();
+        let mut staleness: Vec<i64> = items
+            .iter()
+            .map(|i| (now - i.last_processed).num_seconds())
+            .collect();
+        staleness.sort_unstable();
+
+        Self {
+            platform,
+            items: items.len() as u64,
+            locked: items.iter().filter(|i| i.lock_holder.is_some()).count()
+                as u64,
+            unconfirmed: items.iter().filter(|i| !i.confirmed_id).count()
+                as u64,
+            p50: percentile(&staleness, 50),
+            p90: percentile(&staleness, 90),
+            p99: percentile(&staleness, 99),
+            max: staleness.last().copied().unwrap_or(0),
+        }
+    }
+}
+
+/// Staleness percentiles for each platform in the queue.
+pub async fn staleness(user: User, mut db: DBHandle) -> impl IntoResponse {
+    if !user.admin {
+        return error!(UNAUTHORIZED, "Unauthorised.");
+    }
+
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let mut cursor = q_coll
+        .find_with_session(doc! {}, None, &mut db.session)
+        .await
+        .unwrap();
+    let items: Vec<InternalQueueItem> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+    db.session.commit_transaction().await.unwrap();
+
+    let mut by_platform: HashMap<String, Vec<InternalQueueItem>> =
+        HashMap::new();
+    for item in items {
+        by_platform
+            .entry(item.platform.clone())
+            .or_default()
+            .push(item);
+    }
+    let mut platforms: Vec<PlatformStaleness> = by_platform
+        .into_iter()
+        .map(|(platform, items)| PlatformStaleness::new(platform, &items))
+        .collect();
+    platforms.sort_by(|a, b| a.platform.cmp(&b.platform));
+
+    ok!(OK, StalenessResponse::new(platforms))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QueueItemRequest {
+    pub queue_id: String,
+}
+
+/// Release the lock on a queue item, whoever holds it.
+pub async fn unlock(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<QueueItemRequest>,
+) -> impl IntoResponse {
+    let update = doc! {"$set": {
+        "lock_holder": Bson::Null,
+        "lock_acquired_at": Bson::Null
+    }};
+    update_item(&user, &req.queue_id, update, &mut db).await
+}
+
+/// Unlock a queue item and clear its history so that it is handed out as
+/// though it had never been processed.
+pub async fn requeue(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<QueueItemRequest>,
+) -> impl IntoResponse {
+    let update = doc! {"$set": {
+        "lock_holder": Bson::Null,
+        "lock_acquired_at": Bson::Null,
+        "last_processed": timestamp(Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap()),
+        "failures": 0_i64,
+        "last_failure": Bson::Null,
+        "backoff_until": Bson::Null,
+        "round_providers": []
+    }};
+    update_item(&user, &req.queue_id, update, &mut db).await
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PriorityRequest {
+    pub queue_id: String,
+    // Items with a higher priority are handed out first.
+    pub priority: i64,
+}
+
+/// Set the priority of a queue item.
+pub async fn priority(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<PriorityRequest>,
+) -> impl IntoResponse {
+    let update = doc! {"$set": {"priority": req.priority}};
+    update_item(&user, &req.queue_id, update, &mut db).await
+}
+
+async fn update_item(
+    user: &User,
+    queue_id: &str,
+    update: Document,
+    db: &mut DBHandle,
+) -> Result<(StatusCode, Json<OkResponse>), (StatusCode, Json<ErrorResponse>)> {
+    if !user.admin {
+        return error!(UNAUTHORIZED, "Unauthorised.");
+    }
+
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let result = q_coll
+        .update_one_with_session(
+            doc! {"queue_id": queue_id},
+            update,
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    if result.matched_count == 0 {
+        return error!(BAD_REQUEST, "Invalid queue ID.");
+    }
+
+    db.session.commit_transaction().await.unwrap();
+    ok!()
+}
+
+/// Delete unlocked queue items that no subject refers to any more.
+pub async fn purge(user: User, mut db: DBHandle) -> impl IntoResponse {
+    if !user.admin {
+        return error!(UNAUTHORIZED, "Unauthorised.");
+    }
+
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    let mut cursor = subj_coll
+        .find_with_session(doc! {}, None, &mut db.session)
+        .await
+        .unwrap();
+    let subjects: Vec<Subject> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+    let referenced: HashSet<Node> =
+        subjects.iter().flat_map(|s| s.nodes()).collect();
+
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let mut cursor = q_coll
+        .find_with_session(
+            doc! {"lock_holder": Bson::Null},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    let items: Vec<InternalQueueItem> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+    let orphans: Vec<String> = items
+        .into_iter()
+        .filter(|i| {
+            i.references == 0
+                || !referenced.contains(&Node::new(&i.platform, &i.platform_id))
+        })
+        .map(|i| i.queue_id)
+        .collect();
+
+    let result = q_coll
+        .delete_many_with_session(
+            doc! {"queue_id": {"$in": &orphans}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+
+    db.session.commit_transaction().await.unwrap();
+    ok!(OK, PurgeResponse::new(result.deleted_count))
+}
+
+// The nearest-rank percentile of sorted values.
+fn percentile(sorted: &[i64], p: usize) -> i64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let values: Vec<i64> = (1..=10).collect();
+        assert_eq!(percentile(&values, 50), 5);
+        assert_eq!(percentile(&values, 90), 9);
+        assert_eq!(percentile(&values, 99), 10);
+        assert_eq!(percentile(&[7], 50), 7);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+}
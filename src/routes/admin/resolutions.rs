@@ -0,0 +1,78 @@
+//! Route for reviewing username resolutions.
+//!
+//! The /admin/resolutions route is implemented here.
+//!
+//! See endpoint documentation at
+//! <https://docs.berserksystems.com/endpoints/admin/resolutions/>.
+//!
+//! Only administrators may review resolutions. Filtering by
+//! `?status=conflict` lists the mappings that contradicted an earlier one and
+//! were not applied, and /admin/resolutions/clear settles a conflict. See
+//! [`crate::concepts::resolution`].
+
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::resolution::{self, MappingStatus, UsernameMapping};
+use crate::concepts::user::User;
+use crate::database::DBHandle;
+use crate::routes::response::{
+    ErrorResponse, ResolutionResponse, UsernameMappingsResponse,
+};
+
+#[derive(Deserialize)]
+pub struct ResolutionsQuery {
+    status: Option<MappingStatus>,
+}
+
+pub async fn resolutions(
+    user: User,
+    mut db: DBHandle,
+    Query(query): Query<ResolutionsQuery>,
+) -> impl IntoResponse {
+    if !user.admin {
+        return error!(UNAUTHORIZED, "Unauthorised.");
+    }
+
+    let mappings = UsernameMapping::all(query.status, &mut db).await;
+    db.session.commit_transaction().await.unwrap();
+    ok!(OK, UsernameMappingsResponse::new(mappings))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ClearConflictRequest {
+    pub platform: String,
+    pub username: String,
+    // The platform ID that the username really belongs to.
+    pub platform_id: String,
+}
+
+/// Clear a conflict over a username by choosing the platform ID it belongs
+/// to.
+pub async fn clear(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<ClearConflictRequest>,
+) -> impl IntoResponse {
+    if !user.admin {
+        return error!(UNAUTHORIZED, "Unauthorised.");
+    }
+    if req.platform_id.is_empty() {
+        return error!(BAD_REQUEST, "You must provide a platform ID.");
+    }
+
+    let status = resolution::clear(
+        &req.platform,
+        &req.username,
+        &req.platform_id,
+        &user.uuid,
+        &mut db,
+    )
+    .await;
+    let Some(status) = status else {
+        return error!(BAD_REQUEST, "There is no conflict for this username.");
+    };
+
+    db.session.commit_transaction().await.unwrap();
+    ok!(OK, ResolutionResponse::new(status))
+}
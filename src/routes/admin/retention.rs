@@ -0,0 +1,33 @@
+//! Route for reviewing retention rules.
+//!
+//! The /admin/retention route is implemented here.
+//!
+//! See endpoint documentation at
+//! <https://docs.berserksystems.com/endpoints/admin/retention/>.
+//!
+//! Only administrators may review retention. The rules are run as a dry run,
+//! reporting how much data each would remove right now without removing any.
+//! See [`crate::concepts::retention`].
+
+use axum::Extension;
+use axum::{http::StatusCode, response::IntoResponse, Json};
+
+use crate::concepts::retention;
+use crate::concepts::user::User;
+use crate::config::IConfig;
+use crate::database::DBHandle;
+use crate::routes::response::{ErrorResponse, RetentionResponse};
+
+pub async fn retention(
+    user: User,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+) -> impl IntoResponse {
+    if !user.admin {
+        return error!(UNAUTHORIZED, "Unauthorised.");
+    }
+
+    let reports = retention::enforce(&config.retention, true, &mut db).await;
+    db.session.commit_transaction().await.unwrap();
+    ok!(OK, RetentionResponse::new(reports))
+}
@@ -0,0 +1,641 @@
+//! Route for computing activity statistics about subjects and groups.
+//!
+//! The /analytics route is implemented here.
+//!
+//! See endpoint documentation at
+//! <https://docs.berserksystems.com/endpoints/analytics/>.
+//!
+//! Statistics are computed per profile, per subject (across all of the
+//! subject's profiles) and per group (across all of the group's subjects).
+//! Counts are placed into contiguous time buckets so that they can be charted
+//! directly, with empty buckets included as zeroes.
+//!
+//! Content is placed by its `created_at` time when available, falling back to
+//! the time it was retrieved. Presence and metadata are placed by the time
+//! they were retrieved. The `from` and `to` range applies to the same times.
+//! Without `from`, only data from the most recent `MAX_BUCKETS` buckets is
+//! read. All times are in UTC.
+
+use std::collections::BTreeMap;
+
+use axum::{extract::Query, http::StatusCode, Json};
+use chrono::{DateTime, Duration, TimeZone, Timelike, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, doc};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::acl;
+use crate::concepts::data::Data;
+use crate::concepts::group::Group;
+use crate::concepts::subject::Subject;
+use crate::concepts::user::User;
+use crate::database::DBHandle;
+use crate::routes::response::{AnalyticsResponse, ErrorResponse};
+use crate::utils::deserialise_array::deserialise_array;
+
+// Upper bound on the number of buckets in a single series. This stops a
+// request for hourly buckets over several years from producing an enormous
+// response.
+const MAX_BUCKETS: i64 = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    Hour,
+    #[default]
+    Day,
+    Week,
+}
+
+impl Bucket {
+    fn duration(&self) -> Duration {
+        match self {
+            Bucket::Hour => Duration::hours(1),
+            Bucket::Day => Duration::days(1),
+            Bucket::Week => Duration::weeks(1),
+        }
+    }
+
+    /// The start of the bucket containing the given time. Weeks start on
+    /// Monday.
+    pub fn start(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let secs = time.timestamp();
+        let length = self.duration().num_seconds();
+        // The Unix epoch was a Thursday, three days after the start of its
+        // week.
+        let offset = match self {
+            Bucket::Week => Duration::days(3).num_seconds(),
+            _ => 0,
+        };
+        let start = secs - (secs + offset).rem_euclid(length);
+        Utc.timestamp_opt(start, 0).unwrap()
+    }
+
+    /// The buckets from the one containing `start` to the one containing
+    /// `end`. If there would be more than [`MAX_BUCKETS`], only the latest are
+    /// returned.
+    fn range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<DateTime<Utc>> {
+        let (first, last) = (self.start(start), self.start(end));
+        if last < first {
+            return Vec::new();
+        }
+        let count = (self.index(first, last) + 1).min(MAX_BUCKETS);
+        let first = last - self.duration() * (count - 1) as i32;
+        (0..count)
+            .map(|i| first + self.duration() * i as i32)
+            .collect()
+    }
+
+    /// The number of buckets between the bucket starting at `first` and the
+    /// one containing the given time.
+    fn index(&self, first: DateTime<Utc>, time: DateTime<Utc>) -> i64 {
+        (self.start(time) - first).num_seconds() / self.duration().num_seconds()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Point {
+    pub bucket: DateTime<Utc>,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Series {
+    pub label: String,
+    pub points: Vec<Point>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetaChange {
+    pub at: DateTime<Utc>,
+    pub field: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Analytics {
+    /// Content counts per bucket, one series per content type.
+    pub content: Vec<Series>,
+    /// Presence observation counts per bucket, one series per presence type.
+    pub presence: Vec<Series>,
+    /// The fraction of buckets containing at least one presence observation.
+    pub presence_coverage: f64,
+    /// Content counts by hour of the day, from 00:00 to 23:00 UTC.
+    pub posting_hours: Vec<u64>,
+    /// The mean time between consecutive pieces of content in seconds.
+    pub average_post_gap_secs: Option<f64>,
+    /// Changes between consecutive metadata snapshots of the same profile.
+    pub meta_changes: Vec<MetaChange>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProfileAnalytics {
+    pub platform: String,
+    pub id: String,
+    pub analytics: Analytics,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SubjectAnalytics {
+    pub uuid: String,
+    pub name: String,
+    pub analytics: Analytics,
+    pub profiles: Vec<ProfileAnalytics>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GroupAnalytics {
+    pub uuid: String,
+    pub name: String,
+    pub analytics: Analytics,
+}
+
+#[derive(Deserialize)]
+pub struct AnalyticsQuery {
+    #[serde(default, deserialize_with = "deserialise_array")]
+    subjects: Vec<String>,
+    #[serde(default, deserialize_with = "deserialise_array")]
+    groups: Vec<String>,
+    #[serde(default)]
+    bucket: Bucket,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+pub async fn analytics(
+    analytics_query: Option<Query<AnalyticsQuery>>,
+    mut db: DBHandle,
+    user: User,
+) -> Result<
+    (StatusCode, Json<AnalyticsResponse>),
+    (StatusCode, Json<ErrorResponse>),
+> {
+    if analytics_query.is_none() {
+        return error!(
+            BAD_REQUEST,
+            "You must provide a list of subjects or groups."
+        );
+    }
+    let Query(query) = analytics_query.unwrap();
+    if query.subjects.is_empty() && query.groups.is_empty() {
+        return error!(
+            BAD_REQUEST,
+            "You must provide a list of subjects or groups."
+        );
+    }
+    let to = query.to.unwrap_or_else(Utc::now);
+    let bucket_secs = query.bucket.duration().num_seconds();
+    let since = query
+        .from
+        .unwrap_or_else(|| to - Duration::seconds(bucket_secs * MAX_BUCKETS));
+    if (to - since).num_seconds() / bucket_secs > MAX_BUCKETS {
+        return error!(
+            BAD_REQUEST,
+            "Too many buckets. Use a larger bucket or a shorter range."
+        );
+    }
+
+    let subjects =
+        acl::visible_subjects(&query.subjects, &[], &user.uuid, &mut db).await;
+    let group_coll: Collection<Group> = db.collection("groups");
+    let mut group_cursor = group_coll
+        .find_with_session(
+            acl::readable_by(doc! {"uuid": {"$in": &query.groups}}, &user.uuid),
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    let groups: Vec<Group> = group_cursor
+        .stream(&mut db.session)
+        .try_collect()
+        .await
+        .unwrap();
+    let mut invisible = subjects.as_ref().err().cloned().unwrap_or_default();
+    invisible.extend(
+        query
+            .groups
+            .iter()
+            .filter(|uuid| !groups.iter().any(|g| &&g.uuid == uuid))
+            .cloned(),
+    );
+    if !invisible.is_empty() {
+        return error!(
+            NOT_FOUND,
+            &format!(
+                "Subjects or groups do not exist or are not visible to you: \
+                {}.",
+                invisible.join(", ")
+            )
+        );
+    }
+
+    let mut subject_analytics = Vec::new();
+    for subject in subjects.unwrap() {
+        let (all_data, profiles) =
+            subject_data(&subject, since, query.to, &mut db).await;
+        let profiles = profiles
+            .into_iter()
+            .map(|(platform, id, data)| ProfileAnalytics {
+                analytics: Analytics::from_data(
+                    &data,
+                    query.bucket,
+                    query.from,
+                    query.to,
+                ),
+                platform,
+                id,
+            })
+            .collect();
+        subject_analytics.push(SubjectAnalytics {
+            uuid: subject.uuid,
+            name: subject.name,
+            analytics: Analytics::from_data(
+                &all_data,
+                query.bucket,
+                query.from,
+                query.to,
+            ),
+            profiles,
+        });
+    }
+
+    let mut group_analytics = Vec::new();
+    for group in groups {
+        let mut group_data = Vec::new();
+        for subject in user
+            .selected_subjects(&[], std::slice::from_ref(&group.uuid), &mut db)
+            .await
+        {
+            let (all_data, _) =
+                subject_data(&subject, since, query.to, &mut db).await;
+            group_data.extend(all_data);
+        }
+        group_analytics.push(GroupAnalytics {
+            uuid: group.uuid,
+            name: group.name,
+            analytics: Analytics::from_data(
+                &group_data,
+                query.bucket,
+                query.from,
+                query.to,
+            ),
+        });
+    }
+
+    db.session.commit_transaction().await.unwrap();
+    ok!(
+        OK,
+        AnalyticsResponse::new(subject_analytics, group_analytics)
+    )
+}
+
+type ProfileDataList = Vec<(String, String, Vec<Data>)>;
+
+// The data about the subject placed between `from` and `to`.
+async fn subject_data(
+    subject: &Subject,
+    from: DateTime<Utc>,
+    to: Option<DateTime<Utc>>,
+    db: &mut DBHandle,
+) -> (Vec<Data>, ProfileDataList) {
+    let data_coll: Collection<Data> = db.collection("data");
+    let options = FindOptions::builder()
+        .sort(doc! {"retrieved_at": 1_i32})
+        .build();
+
+    let mut all_data = Vec::new();
+    let mut profiles = Vec::new();
+    for (platform, ids) in &subject.profiles {
+        for id in ids {
+            let mut filter = doc! {"id": id, "platform": platform};
+            let mut range = doc! {"$gte": bson::to_bson(&from).unwrap()};
+            if let Some(to) = &to {
+                range.insert("$lte", bson::to_bson(to).unwrap());
+            }
+            // Data is selected by the time it is placed at: content by when
+            // it was created if known, and everything else by when it was
+            // retrieved.
+            filter.insert(
+                "$or",
+                vec![
+                    doc! {"created_at": &range},
+                    doc! {"created_at": null, "retrieved_at": range},
+                ],
+            );
+
+            let mut cursor = data_coll
+                .find_with_session(filter, options.clone(), &mut db.session)
+                .await
+                .unwrap();
+            let data: Vec<Data> =
+                cursor.stream(&mut db.session).try_collect().await.unwrap();
+            all_data.extend(data.iter().cloned());
+            profiles.push((platform.clone(), id.clone(), data));
+        }
+    }
+    (all_data, profiles)
+}
+
+impl Analytics {
+    pub fn from_data(
+        data: &[Data],
+        bucket: Bucket,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Self {
+        let mut content: BTreeMap<String, Vec<DateTime<Utc>>> = BTreeMap::new();
+        let mut presence: BTreeMap<String, Vec<DateTime<Utc>>> =
+            BTreeMap::new();
+        let mut metas: Vec<&Data> = Vec::new();
+        let mut times: Vec<DateTime<Utc>> = Vec::new();
+
+        for d in data {
+            match d {
+                Data::Content {
+                    content_type,
+                    created_at,
+                    retrieved_at,
+                    ..
+                } => {
+                    let time = created_at.unwrap_or(*retrieved_at);
+                    content.entry(content_type.clone()).or_default().push(time);
+                    times.push(time);
+                }
+                Data::Presence {
+                    presence_type,
+                    retrieved_at,
+                    ..
+                } => {
+                    presence
+                        .entry(presence_type.clone())
+                        .or_default()
+                        .push(*retrieved_at);
+                    times.push(*retrieved_at);
+                }
+                Data::Meta { retrieved_at, .. } => {
+                    metas.push(d);
+                    times.push(*retrieved_at);
+                }
+            }
+        }
+
+        let start = from.or_else(|| times.iter().min().copied());
+        let end = to.or_else(|| times.iter().max().copied());
+        let buckets = match (start, end) {
+            (Some(start), Some(end)) => bucket.range(start, end),
+            _ => Vec::new(),
+        };
+
+        let mut post_times: Vec<DateTime<Utc>> =
+            content.values().flatten().copied().collect();
+        post_times.sort();
+        let mut posting_hours = vec![0; 24];
+        for time in &post_times {
+            posting_hours[time.hour() as usize] += 1;
+        }
+        let average_post_gap_secs = if post_times.len() < 2 {
+            None
+        } else {
+            let total = *post_times.last().unwrap() - post_times[0];
+            Some(total.num_seconds() as f64 / (post_times.len() - 1) as f64)
+        };
+
+        let presence_coverage = if buckets.is_empty() {
+            0.0
+        } else {
+            let mut present = vec![false; buckets.len()];
+            for time in presence.values().flatten() {
+                if let Some(i) = bucket_index(*time, &buckets, bucket) {
+                    present[i] = true;
+                }
+            }
+            present.iter().filter(|p| **p).count() as f64 / buckets.len() as f64
+        };
+
+        Self {
+            content: series(content, &buckets, bucket),
+            presence: series(presence, &buckets, bucket),
+            presence_coverage,
+            posting_hours,
+            average_post_gap_secs,
+            meta_changes: meta_changes(&metas),
+        }
+    }
+}
+
+fn series(
+    times: BTreeMap<String, Vec<DateTime<Utc>>>,
+    buckets: &[DateTime<Utc>],
+    bucket: Bucket,
+) -> Vec<Series> {
+    times
+        .into_iter()
+        .map(|(label, times)| {
+            let mut counts = vec![0; buckets.len()];
+            for time in times {
+                if let Some(i) = bucket_index(time, buckets, bucket) {
+                    counts[i] += 1;
+                }
+            }
+            let points = buckets
+                .iter()
+                .zip(counts)
+                .map(|(b, count)| Point { bucket: *b, count })
+                .collect();
+            Series { label, points }
+        })
+        .collect()
+}
+
+// The position of the bucket containing the time in the buckets, if it is in
+// one of them.
+fn bucket_index(
+    time: DateTime<Utc>,
+    buckets: &[DateTime<Utc>],
+    bucket: Bucket,
+) -> Option<usize> {
+    let i = bucket.index(*buckets.first()?, time);
+    usize::try_from(i).ok().filter(|i| *i < buckets.len())
+}
+
+/// Changes between consecutive metadata snapshots. Snapshots are grouped by
+/// profile and ordered by the time they were retrieved.
+pub fn meta_changes(metas: &[&Data]) -> Vec<MetaChange> {
+    let mut by_profile: BTreeMap<(String, String), Vec<&Data>> =
+        BTreeMap::new();
+    for meta in metas {
+        if let Data::Meta { id, platform, .. } = meta {
+            by_profile
+                .entry((platform.clone(), id.clone()))
+                .or_default()
+                .push(meta);
+        }
+    }
+
+    let mut changes = Vec::new();
+    for snapshots in by_profile.values_mut() {
+        snapshots.sort_by_key(|m| match m {
+            Data::Meta { retrieved_at, .. } => *retrieved_at,
+            _ => unreachable!(),
+        });
+        for pair in snapshots.windows(2) {
+            let (before, after) = (meta_fields(pair[0]), meta_fields(pair[1]));
+            let at = match pair[1] {
+                Data::Meta { retrieved_at, .. } => *retrieved_at,
+                _ => unreachable!(),
+            };
+            for ((field, from), (_, to)) in before.into_iter().zip(after) {
+                if from != to {
+                    changes.push(MetaChange {
+                        at,
+                        field: field.to_string(),
+                        from,
+                        to,
+                    });
+                }
+            }
+        }
+    }
+    changes.sort_by_key(|c| c.at);
+    changes
+}
+
+fn meta_fields(meta: &Data) -> Vec<(&'static str, Option<String>)> {
+    match meta {
+        Data::Meta {
+            username,
+            private,
+            suspended_or_banned,
+            display_name,
+            profile_picture,
+            bio,
+            verified,
+            link,
+            ..
+        } => vec![
+            ("username", Some(username.clone())),
+            ("private", Some(private.to_string())),
+            ("suspended_or_banned", Some(suspended_or_banned.to_string())),
+            ("display_name", display_name.clone()),
+            ("profile_picture", profile_picture.clone()),
+            ("bio", bio.clone()),
+            ("verified", verified.map(|v| v.to_string())),
+            ("link", link.clone()),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn content(content_type: &str, created_at: DateTime<Utc>) -> Data {
+        Data::Content {
+            id: "1".to_string(),
+            platform: "twitter".to_string(),
+            content_type: content_type.to_string(),
+            retrieved_at: created_at,
+            content_id: created_at.to_string(),
+            deleted: None,
+            retrieved_from: None,
+            created_at: Some(created_at),
+            body: None,
+            media: None,
+            references: None,
+            added_by: None,
+            added_at: None,
+        }
+    }
+
+    fn meta(username: &str, retrieved_at: DateTime<Utc>) -> Data {
+        Data::Meta {
+            id: "1".to_string(),
+            platform: "twitter".to_string(),
+            username: username.to_string(),
+            private: false,
+            suspended_or_banned: false,
+            retrieved_at,
+            display_name: None,
+            profile_picture: None,
+            bio: None,
+            verified: None,
+            references: None,
+            link: None,
+            added_by: None,
+            added_at: None,
+        }
+    }
+
+    #[test]
+    fn test_week_starts_on_monday() {
+        // 2022-01-01 was a Saturday.
+        let time = Utc.with_ymd_and_hms(2022, 1, 1, 13, 30, 0).unwrap();
+        let monday = Utc.with_ymd_and_hms(2021, 12, 27, 0, 0, 0).unwrap();
+
+        assert_eq!(Bucket::Week.start(time), monday);
+    }
+
+    #[test]
+    fn test_content_series() {
+        let first = Utc.with_ymd_and_hms(2022, 1, 1, 9, 0, 0).unwrap();
+        let data = vec![
+            content("tweet", first),
+            content("tweet", first + Duration::hours(2)),
+            content("tweet", first + Duration::days(2)),
+        ];
+
+        let analytics = Analytics::from_data(&data, Bucket::Day, None, None);
+
+        assert_eq!(analytics.content.len(), 1);
+        let counts: Vec<u64> = analytics.content[0]
+            .points
+            .iter()
+            .map(|p| p.count)
+            .collect();
+        assert_eq!(counts, vec![2, 0, 1]);
+        assert_eq!(analytics.posting_hours[9], 2);
+        assert_eq!(analytics.posting_hours[11], 1);
+        assert_eq!(
+            analytics.average_post_gap_secs,
+            Some(Duration::days(1).num_seconds() as f64)
+        );
+    }
+
+    #[test]
+    fn test_range_is_limited() {
+        let end = Utc.with_ymd_and_hms(2022, 1, 1, 9, 0, 0).unwrap();
+        let start = Utc.with_ymd_and_hms(1, 1, 1, 0, 0, 0).unwrap();
+
+        let buckets = Bucket::Hour.range(start, end);
+
+        assert_eq!(buckets.len(), MAX_BUCKETS as usize);
+        assert_eq!(buckets.last(), Some(&end));
+    }
+
+    #[test]
+    fn test_meta_changes() {
+        let first = Utc.with_ymd_and_hms(2022, 1, 1, 9, 0, 0).unwrap();
+        let data = [
+            meta("old", first),
+            meta("old", first + Duration::days(1)),
+            meta("new", first + Duration::days(2)),
+        ];
+        let metas: Vec<&Data> = data.iter().collect();
+
+        let changes = meta_changes(&metas);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "username");
+        assert_eq!(changes[0].from.as_deref(), Some("old"));
+        assert_eq!(changes[0].to.as_deref(), Some("new"));
+    }
+}
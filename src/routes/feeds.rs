@@ -0,0 +1,177 @@
+//! Routes for following subjects in a feed reader.
+//!
+//! The /feeds/tokens and /feeds/{uuid}.{format} routes are implemented here.
+//!
+//! A user creates a token for a subject they can see, and the subject's feed
+//! is then served at /feeds/{uuid}.atom, /feeds/{uuid}.rss or
+//! /feeds/{uuid}.json with the token as the `token` query parameter. See
+//! [`crate::concepts::feed`].
+
+use axum::extract::{Path, Query};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::{http::StatusCode, Extension, Json};
+use mongodb::bson::doc;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::acl;
+use crate::concepts::feed::{self, Feed, FeedFormat, FeedToken};
+use crate::concepts::reputation::Reputation;
+use crate::concepts::user::User;
+use crate::config::IConfig;
+use crate::database::DBHandle;
+use crate::routes::response::{ErrorResponse, FeedTokenResponse, OkResponse};
+use crate::routes::view;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateFeedTokenRequest {
+    pub subject: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RevokeFeedTokenRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct TokenQuery {
+    token: String,
+}
+
+pub async fn create(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<CreateFeedTokenRequest>,
+) -> Result<
+    (StatusCode, Json<FeedTokenResponse>),
+    (StatusCode, Json<ErrorResponse>),
+> {
+    let subjects = [req.subject];
+    if acl::visible_subjects(&subjects, &[], &user.uuid, &mut db)
+        .await
+        .is_err()
+    {
+        return error!(
+            NOT_FOUND,
+            "Subject does not exist or is not visible to you."
+        );
+    }
+
+    let (feed_token, token) = FeedToken::new(&subjects[0], &user.uuid);
+    let t_coll: Collection<FeedToken> = db.collection("feed_tokens");
+    t_coll
+        .insert_one_with_session(feed_token, None, &mut db.session)
+        .await
+        .unwrap();
+    db.session.commit_transaction().await.unwrap();
+    ok!(CREATED, FeedTokenResponse::new(&subjects[0], token))
+}
+
+pub async fn revoke(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<RevokeFeedTokenRequest>,
+) -> impl IntoResponse {
+    let revoked = match FeedToken::with_token(&req.token, &mut db).await {
+        Some(feed_token) if feed_token.created_by == user.uuid => {
+            let t_coll: Collection<FeedToken> = db.collection("feed_tokens");
+            t_coll
+                .delete_one_with_session(
+                    doc! {"hashed_token": &feed_token.hashed_token},
+                    None,
+                    &mut db.session,
+                )
+                .await
+                .unwrap();
+            true
+        }
+        _ => false,
+    };
+    if !revoked {
+        return error!(
+            BAD_REQUEST,
+            "Feed token does not exist or was not created by you."
+        );
+    }
+    db.session.commit_transaction().await.unwrap();
+    ok!()
+}
+
+pub async fn feed(
+    Path(file): Path<String>,
+    token: Option<Query<TokenQuery>>,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let Some((uuid, format)) = parse_file(&file) else {
+        return error!(NOT_FOUND, "Feeds are served as .atom, .rss or .json.");
+    };
+    let feed_token = match token {
+        Some(Query(q)) => FeedToken::with_token(&q.token, &mut db).await,
+        None => None,
+    };
+    let Some(feed_token) = feed_token.filter(|t| t.subject == uuid) else {
+        return error!(UNAUTHORIZED, "Unauthorised.");
+    };
+
+    // The token only works while its creator can see the subject.
+    let subjects = match acl::visible_subjects(
+        &[uuid.to_string()],
+        &[],
+        &feed_token.created_by,
+        &mut db,
+    )
+    .await
+    {
+        Ok(subjects) => subjects,
+        Err(_) => {
+            return error!(
+                NOT_FOUND,
+                "Subject does not exist or is no longer visible."
+            )
+        }
+    };
+
+    let subject = subjects[0].clone();
+    let hidden =
+        Reputation::low_providers(config.reputation.min_score, &mut db).await;
+    let mut entries = feed::meta_entries(&subject, &hidden, &mut db).await;
+    let view_data = view::view_data(subjects, &[], &config, &mut db).await;
+    entries.extend(feed::content_entries(&view_data));
+    db.session.commit_transaction().await.unwrap();
+
+    let feed = Feed::new(&format!("urn:uuid:{uuid}"), &subject.name, entries);
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, format.content_type())],
+        feed.render(format),
+    )
+        .into_response())
+}
+
+// Splits a feed's file name into the subject's UUID and the format.
+fn parse_file(file: &str) -> Option<(&str, FeedFormat)> {
+    let (uuid, extension) = file.rsplit_once('.')?;
+    let format = match extension {
+        "atom" => FeedFormat::Atom,
+        "rss" => FeedFormat::Rss,
+        "json" => FeedFormat::Json,
+        _ => return None,
+    };
+    Some((uuid, format))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_file_extensions() {
+        assert_eq!(parse_file("abc.atom"), Some(("abc", FeedFormat::Atom)));
+        assert_eq!(parse_file("abc.rss"), Some(("abc", FeedFormat::Rss)));
+        assert_eq!(parse_file("abc.json"), Some(("abc", FeedFormat::Json)));
+        assert_eq!(parse_file("abc.xml"), None);
+        assert_eq!(parse_file("abc"), None);
+    }
+}
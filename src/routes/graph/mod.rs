@@ -0,0 +1,4 @@
+//! Routes for the reference graph.
+
+pub mod neighbours;
+pub mod path;
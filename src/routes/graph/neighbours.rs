@@ -0,0 +1,75 @@
+//! Route for the neighbours of a profile or subject in the reference graph.
+//!
+//! The /graph/neighbours route is implemented here.
+//!
+//! See endpoint documentation at
+//! <https://docs.berserksystems.com/endpoints/graph/neighbours/>.
+
+use axum::{extract::Query, http::StatusCode, Json};
+use serde::Deserialize;
+
+use crate::concepts::edge::{Edge, Node};
+use crate::concepts::user::User;
+use crate::database::DBHandle;
+use crate::routes::response::{ErrorResponse, NeighboursResponse};
+
+#[derive(Deserialize)]
+pub struct NeighboursQuery {
+    subject: Option<String>,
+    platform: Option<String>,
+    id: Option<String>,
+}
+
+pub async fn neighbours(
+    neighbours_query: Option<Query<NeighboursQuery>>,
+    mut db: DBHandle,
+    user: User,
+) -> Result<
+    (StatusCode, Json<NeighboursResponse>),
+    (StatusCode, Json<ErrorResponse>),
+> {
+    let nodes = match neighbours_query {
+        Some(Query(NeighboursQuery {
+            subject: Some(subject),
+            ..
+        })) => {
+            let subjects =
+                user.selected_subjects(&[subject], &[], &mut db).await;
+            if subjects.is_empty() {
+                return error!(
+                    BAD_REQUEST,
+                    "No such subject exists or it is not visible to you."
+                );
+            }
+            subjects[0].nodes()
+        }
+        Some(Query(NeighboursQuery {
+            platform: Some(platform),
+            id: Some(id),
+            ..
+        })) => {
+            let node = Node::new(&platform, &id);
+            let subjects = user.subjects(&mut db).await.unwrap_or_default();
+            if !subjects.iter().any(|s| s.nodes().contains(&node)) {
+                return error!(
+                    BAD_REQUEST,
+                    "The given profile does not belong to any of your \
+                    subjects."
+                );
+            }
+            vec![node]
+        }
+        _ => {
+            return error!(
+                BAD_REQUEST,
+                "You must provide either a subject or a platform and an id."
+            );
+        }
+    };
+
+    let outgoing = Edge::for_nodes(&nodes, true, &mut db).await;
+    let incoming = Edge::for_nodes(&nodes, false, &mut db).await;
+
+    db.session.commit_transaction().await.unwrap();
+    ok!(OK, NeighboursResponse::new(outgoing, incoming))
+}
@@ -0,0 +1,116 @@
+//! Route for finding a path between two subjects in the reference graph.
+//!
+//! The /graph/path route is implemented here.
+//!
+//! See endpoint documentation at
+//! <https://docs.berserksystems.com/endpoints/graph/path/>.
+//!
+//! Paths are found with a breadth first search that treats edges as
+//! undirected, so the path found is one of the shortest. The search gives up
+//! after `MAX_DEPTH` edges.
+
+use std::collections::{HashMap, HashSet};
+
+use axum::{extract::Query, http::StatusCode, Json};
+use serde::Deserialize;
+
+use crate::concepts::edge::{Edge, Node};
+use crate::concepts::user::User;
+use crate::database::DBHandle;
+use crate::routes::response::{ErrorResponse, PathResponse};
+
+const MAX_DEPTH: usize = 4;
+// Upper bound on the number of nodes explored per step of the search.
+const MAX_FRONTIER: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct PathQuery {
+    from: String,
+    to: String,
+}
+
+pub async fn path(
+    path_query: Option<Query<PathQuery>>,
+    mut db: DBHandle,
+    user: User,
+) -> Result<(StatusCode, Json<PathResponse>), (StatusCode, Json<ErrorResponse>)>
+{
+    if path_query.is_none() {
+        return error!(
+            BAD_REQUEST,
+            "You must provide a subject to start from and a subject to end at."
+        );
+    }
+    let Query(PathQuery { from, to }) = path_query.unwrap();
+
+    let subjects = user
+        .selected_subjects(&[from.clone(), to.clone()], &[], &mut db)
+        .await;
+    let from = subjects.iter().find(|s| s.uuid == from);
+    let to = subjects.iter().find(|s| s.uuid == to);
+    let (start, targets) = match (from, to) {
+        (Some(from), Some(to)) => (from.nodes(), to.nodes()),
+        _ => {
+            return error!(
+                BAD_REQUEST,
+                "One or more of the subjects does not exist or is not visible \
+                to you."
+            )
+        }
+    };
+
+    let found = shortest_path(start, &targets, &mut db).await;
+    db.session.commit_transaction().await.unwrap();
+
+    match found {
+        Some((nodes, edges)) => ok!(OK, PathResponse::new(nodes, edges)),
+        None => error!(NOT_FOUND, "No path was found between the subjects."),
+    }
+}
+
+async fn shortest_path(
+    start: Vec<Node>,
+    targets: &[Node],
+    db: &mut DBHandle,
+) -> Option<(Vec<Node>, Vec<Edge>)> {
+    let mut parents: HashMap<Node, Option<(Node, Edge)>> = HashMap::new();
+    for node in &start {
+        parents.insert(node.clone(), None);
+    }
+
+    let mut frontier = start;
+    let mut found = frontier.iter().find(|n| targets.contains(n)).cloned();
+    let mut depth = 0;
+    while found.is_none() && depth < MAX_DEPTH && !frontier.is_empty() {
+        let current: HashSet<Node> = frontier.drain(..).collect();
+        let current_nodes: Vec<Node> = current.iter().cloned().collect();
+        let mut edges = Edge::for_nodes(&current_nodes, true, db).await;
+        edges.extend(Edge::for_nodes(&current_nodes, false, db).await);
+
+        for edge in edges {
+            for (a, b) in [(&edge.from, &edge.to), (&edge.to, &edge.from)] {
+                if current.contains(a) && !parents.contains_key(b) {
+                    parents.insert(b.clone(), Some((a.clone(), edge.clone())));
+                    frontier.push(b.clone());
+                    if found.is_none() && targets.contains(b) {
+                        found = Some(b.clone());
+                    }
+                }
+            }
+        }
+        frontier.truncate(MAX_FRONTIER);
+        depth += 1;
+    }
+
+    let mut node = found?;
+    let mut nodes = vec![node.clone()];
+    let mut edges = Vec::new();
+    while let Some(Some((parent, edge))) = parents.get(&node) {
+        edges.push(edge.clone());
+        nodes.push(parent.clone());
+        node = parent.clone();
+    }
+    nodes.reverse();
+    edges.reverse();
+    Some((nodes, edges))
+}
@@ -7,11 +7,11 @@
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
 use chrono::Utc;
-use mongodb::bson::doc;
 use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::concepts::acl::Visibility;
 use crate::concepts::group::Group;
 use crate::concepts::subject::Subject;
 use crate::concepts::user::User;
@@ -23,6 +23,9 @@ pub struct CreateGroupRequest {
     pub name: String,
     pub subjects: Vec<String>,
     pub description: Option<String>,
+    // UUIDs of groups to nest in the group.
+    #[serde(default)]
+    pub groups: Vec<String>,
 }
 
 pub async fn create(
@@ -31,20 +34,19 @@ pub async fn create(
     Json(data): Json<CreateGroupRequest>,
 ) -> impl IntoResponse {
     let group_coll: Collection<Group> = db.collection("groups");
-    let group = group_from_create(data, user).await;
-    let subj_coll: Collection<Subject> = db.collection("subjects");
-    for s in &group.subjects {
-        let subject = subj_coll
-            .find_one_with_session(doc! {"uuid": s}, None, &mut db.session)
-            .await
-            .unwrap();
-        if subject.is_none() {
-            return error!(
-                BAD_REQUEST,
-                "One or more of the subjects does not exist."
-            );
-        }
+    if !Group::all_readable(&data.groups, &user.uuid, &mut db).await {
+        return error!(
+            BAD_REQUEST,
+            "One or more of the groups does not exist."
+        );
+    }
+    if !Subject::all_readable(&data.subjects, &user.uuid, &mut db).await {
+        return error!(
+            BAD_REQUEST,
+            "One or more of the subjects does not exist."
+        );
     }
+    let group = group_from_create(data, user).await;
     group_coll
         .insert_one_with_session(&group, None, &mut db.session)
         .await
@@ -65,5 +67,10 @@ pub async fn group_from_create(cg: CreateGroupRequest, user: User) -> Group {
         name: cg.name,
         subjects: cg.subjects,
         description: cg.description,
+        editors: Vec::new(),
+        viewers: Vec::new(),
+        visibility: Visibility::default(),
+        groups: cg.groups,
+        version: 0,
     }
 }
@@ -4,13 +4,18 @@
 //!
 //! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/groups/delete/>.
+//!
+//! Deleted groups are moved to the trash and can be restored through
+//! /groups/restore. See [`crate::concepts::trash`].
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
+use futures_util::TryStreamExt;
 use mongodb::bson::doc;
 use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 
 use crate::concepts::group::Group;
+use crate::concepts::trash::{TrashItem, TrashKind};
 use crate::concepts::user::User;
 use crate::database::DBHandle;
 use crate::routes::response::ErrorResponse;
@@ -30,7 +35,7 @@ pub async fn delete(
     // UUID of the requester.
     let req_uuid = user.uuid;
     let group_coll: Collection<Group> = db.collection("groups");
-    if let Ok(Some(_)) = group_coll
+    if let Ok(Some(group)) = group_coll
         .find_one_with_session(
             doc! {"uuid": &data.uuid, "created_by": &req_uuid},
             None,
@@ -46,6 +51,35 @@ pub async fn delete(
             )
             .await
             .unwrap();
+        let mut cursor = group_coll
+            .find_with_session(
+                doc! {"groups": &data.uuid},
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+        let parents: Vec<Group> =
+            cursor.stream(&mut db.session).try_collect().await.unwrap();
+        group_coll
+            .update_many_with_session(
+                doc! {"groups": &data.uuid},
+                doc! {"$pull": {"groups": &data.uuid}},
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+        TrashItem::new(
+            TrashKind::Group,
+            &group.uuid,
+            &group.name,
+            &group.created_by,
+            parents.into_iter().map(|g| g.uuid).collect(),
+            &group,
+        )
+        .insert(&mut db)
+        .await;
         db.session.commit_transaction().await.unwrap();
         ok!()
     } else {
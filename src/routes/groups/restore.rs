@@ -0,0 +1,73 @@
+//! Route for restoring deleted groups.
+//!
+//! The /groups/restore route is implemented here.
+//!
+//! A restored group is nested again in the groups it was removed from when it
+//! was deleted, if they still exist and doing so would not create a cycle. See
+//! [`crate::concepts::trash`].
+
+use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
+use mongodb::bson::{self, doc};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::group::Group;
+use crate::concepts::trash::{TrashItem, TrashKind};
+use crate::concepts::user::User;
+use crate::config::IConfig;
+use crate::database::DBHandle;
+use crate::routes::response::ErrorResponse;
+use crate::routes::response::OkResponse;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RestoreGroupRequest {
+    pub uuid: String,
+}
+
+pub async fn restore(
+    user: User,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+    Json(req): Json<RestoreGroupRequest>,
+) -> impl IntoResponse {
+    let Some(item) = TrashItem::find(
+        TrashKind::Group,
+        &req.uuid,
+        &user.uuid,
+        &config.trash,
+        &mut db,
+    )
+    .await
+    else {
+        return error!(
+            BAD_REQUEST,
+            "No such group is in the trash, or it was not deleted by the user with the given key."
+        );
+    };
+    let group: Group = bson::from_document(item.document.clone()).unwrap();
+
+    let group_coll: Collection<Group> = db.collection("groups");
+    group_coll
+        .insert_one_with_session(&group, None, &mut db.session)
+        .await
+        .unwrap();
+    let nested = [group.uuid.clone()];
+    for parent in &item.groups {
+        if Group::creates_cycle(parent, &nested, &mut db).await {
+            continue;
+        }
+        group_coll
+            .update_one_with_session(
+                doc! {"uuid": parent},
+                doc! {"$addToSet": {"groups": &group.uuid}},
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+    }
+    item.remove(&mut db).await;
+
+    db.session.commit_transaction().await.unwrap();
+    ok!()
+}
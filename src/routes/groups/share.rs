@@ -0,0 +1,100 @@
+//! Routes for sharing groups.
+//!
+//! The /groups/share, /groups/unshare and /groups/visibility routes are
+//! implemented here.
+//!
+//! See [`crate::concepts::acl`] for what each role allows.
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::acl::{self, Role, Visibility};
+use crate::concepts::user::User;
+use crate::database::DBHandle;
+use crate::routes::response::{ErrorResponse, OkResponse};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShareGroupRequest {
+    pub uuid: String,
+    // UUID of the user to share the group with.
+    pub user: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UnshareGroupRequest {
+    pub uuid: String,
+    pub user: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GroupVisibilityRequest {
+    pub uuid: String,
+    pub visibility: Visibility,
+}
+
+pub async fn share(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<ShareGroupRequest>,
+) -> impl IntoResponse {
+    if req.user == user.uuid {
+        return error!(BAD_REQUEST, "You cannot share a group with yourself.");
+    }
+    if User::with_uuid(&req.user, &mut db).await.is_none() {
+        return error!(BAD_REQUEST, "User does not exist.");
+    }
+
+    let shared = acl::share(
+        "groups", &req.uuid, &user.uuid, &req.user, req.role, &mut db,
+    )
+    .await;
+    if !shared {
+        return error!(
+            BAD_REQUEST,
+            "Group does not exist or was not created by you."
+        );
+    }
+    db.session.commit_transaction().await.unwrap();
+    ok!()
+}
+
+pub async fn unshare(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<UnshareGroupRequest>,
+) -> impl IntoResponse {
+    let unshared =
+        acl::unshare("groups", &req.uuid, &user.uuid, &req.user, &mut db).await;
+    if !unshared {
+        return error!(
+            BAD_REQUEST,
+            "Group does not exist or was not created by you."
+        );
+    }
+    db.session.commit_transaction().await.unwrap();
+    ok!()
+}
+
+pub async fn visibility(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<GroupVisibilityRequest>,
+) -> impl IntoResponse {
+    let set = acl::set_visibility(
+        "groups",
+        &req.uuid,
+        &user.uuid,
+        req.visibility,
+        &mut db,
+    )
+    .await;
+    if !set {
+        return error!(
+            BAD_REQUEST,
+            "Group does not exist or was not created by you."
+        );
+    }
+    db.session.commit_transaction().await.unwrap();
+    ok!()
+}
@@ -4,17 +4,25 @@
 //!
 //! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/groups/update/>.
+//!
+//! POST replaces the name, subjects, description and nested groups of a group.
+//! PATCH changes only what is given: it can rename the group, change its
+//! description and add or remove individual subjects and nested groups.
+//! Either can be given the version of the group the change was made against,
+//! in which case the change is refused with 409 Conflict if the group has
+//! changed since.
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
 use mongodb::bson::doc;
 use mongodb::{bson, Collection};
 use serde::{Deserialize, Serialize};
 
+use crate::concepts::acl;
 use crate::concepts::group::Group;
 use crate::concepts::subject::*;
 use crate::concepts::user::User;
-use crate::database::DBHandle;
-use crate::routes::response::{ErrorResponse, OkResponse};
+use crate::database::{unless_conflict, DBHandle};
+use crate::routes::response::{ErrorResponse, VersionResponse};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UpdateGroupRequest {
@@ -22,6 +30,32 @@ pub struct UpdateGroupRequest {
     pub name: String,
     pub subjects: Vec<String>,
     pub description: Option<String>,
+    // UUIDs of groups to nest in the group.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    // The version of the group the update was made against.
+    #[serde(default)]
+    pub version: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PatchGroupRequest {
+    pub uuid: String,
+    #[serde(default)]
+    pub version: Option<u64>,
+    #[serde(default)]
+    pub name: Option<String>,
+    // An empty description removes it.
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub add_subjects: Vec<String>,
+    #[serde(default)]
+    pub remove_subjects: Vec<String>,
+    #[serde(default)]
+    pub add_groups: Vec<String>,
+    #[serde(default)]
+    pub remove_groups: Vec<String>,
 }
 
 pub async fn update(
@@ -29,55 +63,168 @@ pub async fn update(
     mut db: DBHandle,
     Json(data): Json<UpdateGroupRequest>,
 ) -> impl IntoResponse {
-    let UpdateGroupRequest {
-        uuid,
-        name,
-        subjects,
-        description,
-    } = data;
+    let Some(group) = editable_group(&data.uuid, &user, &mut db).await else {
+        return error!(
+            BAD_REQUEST,
+            "Group does not exist or you cannot edit it."
+        );
+    };
+    if data.version.is_some_and(|v| v != group.version) {
+        return error!(CONFLICT, "Group has changed since that version.");
+    }
+
+    let changes = Changes {
+        name: data.name,
+        subjects: data.subjects,
+        description: data.description,
+        groups: data.groups,
+    };
+    write(group, changes, &user, &mut db).await
+}
+
+pub async fn patch(
+    user: User,
+    mut db: DBHandle,
+    Json(data): Json<PatchGroupRequest>,
+) -> impl IntoResponse {
+    let Some(group) = editable_group(&data.uuid, &user, &mut db).await else {
+        return error!(
+            BAD_REQUEST,
+            "Group does not exist or you cannot edit it."
+        );
+    };
+    if data.version.is_some_and(|v| v != group.version) {
+        return error!(CONFLICT, "Group has changed since that version.");
+    }
+
+    let changes = Changes {
+        name: data.name.unwrap_or_else(|| group.name.clone()),
+        subjects: patched(
+            &group.subjects,
+            data.add_subjects,
+            &data.remove_subjects,
+        ),
+        description: match data.description {
+            Some(d) if d.is_empty() => None,
+            Some(d) => Some(d),
+            None => group.description.clone(),
+        },
+        groups: patched(&group.groups, data.add_groups, &data.remove_groups),
+    };
+    write(group, changes, &user, &mut db).await
+}
+
+// The new contents of a group.
+struct Changes {
+    name: String,
+    subjects: Vec<String>,
+    description: Option<String>,
+    groups: Vec<String>,
+}
 
-    let req_uuid = &user.uuid;
+// The UUIDs with those added that aren't already present and those removed.
+fn patched(
+    current: &[String],
+    add: Vec<String>,
+    remove: &[String],
+) -> Vec<String> {
+    let mut uuids = current.to_vec();
+    for uuid in add {
+        if !uuids.contains(&uuid) {
+            uuids.push(uuid);
+        }
+    }
+    uuids.retain(|u| !remove.contains(u));
+    uuids
+}
+
+async fn editable_group(
+    uuid: &str,
+    user: &User,
+    db: &mut DBHandle,
+) -> Option<Group> {
     let group_coll: Collection<Group> = db.collection("groups");
-    if let Ok(Some(_)) = group_coll
+    group_coll
         .find_one_with_session(
-            doc! {"uuid": &uuid, "created_by": &req_uuid},
+            acl::editable_by(doc! {"uuid": uuid}, &user.uuid),
             None,
             &mut db.session,
         )
         .await
-    {
-        let subj_coll: Collection<Subject> = db.collection("subjects");
-        for s in &subjects {
-            let subject = subj_coll
-                .find_one_with_session(doc! {"uuid": s}, None, &mut db.session)
-                .await
-                .unwrap();
-            if subject.is_none() {
-                return error!(
-                    BAD_REQUEST,
-                    "One or more of the subjects does not exist."
-                );
-            }
-        }
+        .unwrap()
+}
+
+// Writes the changes if they are valid and the group is still at the version
+// it was read at.
+async fn write(
+    group: Group,
+    changes: Changes,
+    user: &User,
+    db: &mut DBHandle,
+) -> Result<
+    (StatusCode, Json<VersionResponse>),
+    (StatusCode, Json<ErrorResponse>),
+> {
+    // Subjects and groups already in the group stay even if the user can't
+    // read them, but only subjects and groups the user can read can be added.
+    let added: Vec<String> = changes
+        .subjects
+        .iter()
+        .filter(|s| !group.subjects.contains(s))
+        .cloned()
+        .collect();
+    if !Subject::all_readable(&added, &user.uuid, db).await {
+        return error!(
+            BAD_REQUEST,
+            "One or more of the subjects does not exist."
+        );
+    }
+    let added: Vec<String> = changes
+        .groups
+        .iter()
+        .filter(|g| !group.groups.contains(g))
+        .cloned()
+        .collect();
+    if !Group::all_readable(&added, &user.uuid, db).await {
+        return error!(
+            BAD_REQUEST,
+            "One or more of the groups does not exist."
+        );
+    }
+    if Group::creates_cycle(&group.uuid, &changes.groups, db).await {
+        return error!(
+            BAD_REQUEST,
+            "A group cannot be nested in itself, directly or through other \
+            groups."
+        );
+    }
+
+    let group_coll: Collection<Group> = db.collection("groups");
+    // Another update to the group in a transaction that hasn't committed yet
+    // is a conflict too.
+    let result = unless_conflict(
         group_coll
             .update_one_with_session(
-                doc! {"uuid": &uuid, "created_by": &req_uuid},
-                doc! {"$set":
-                    {"name": name,
-                    "subjects": bson::to_bson(&subjects).unwrap(),
-                    "description": description}
+                at_version(doc! {"uuid": &group.uuid}, group.version),
+                doc! {
+                    "$set": {
+                        "name": changes.name,
+                        "subjects": bson::to_bson(&changes.subjects).unwrap(),
+                        "description": changes.description,
+                        "groups": bson::to_bson(&changes.groups).unwrap()
+                    },
+                    "$inc": {"version": 1_i64}
                 },
                 None,
                 &mut db.session,
             )
-            .await
-            .unwrap();
-        db.session.commit_transaction().await.unwrap();
-        ok!()
-    } else {
-        error!(
-            BAD_REQUEST,
-            "Group does not exist or was not created by you."
-        )
+            .await,
+    );
+    if result.map_or(0, |r| r.matched_count) == 0 {
+        return error!(CONFLICT, "Group has changed since that version.");
+    }
+    if unless_conflict(db.session.commit_transaction().await).is_none() {
+        return error!(CONFLICT, "Group has changed since that version.");
     }
+    ok!(OK, VersionResponse::new(&group.uuid, group.version + 1))
 }
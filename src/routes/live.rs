@@ -0,0 +1,241 @@
+//! Route for subscribing to a live feed of data about subjects or groups.
+//!
+//! The /view/live route is implemented here.
+//!
+//! See endpoint documentation at
+//! <https://docs.berserksystems.com/endpoints/view/>.
+//!
+//! The feed is served as Server-Sent Events. It takes the same subject and
+//! group selectors as /search and, once connected, pushes every data item
+//! about the selected subjects' profiles as it is committed by /add. Each
+//! event carries the item's ID in the `data` collection as its event ID, so a
+//! client that reconnects with a `Last-Event-ID` header is first sent every
+//! item it missed.
+
+use std::collections::{HashSet, VecDeque};
+use std::convert::Infallible;
+
+use axum::extract::Query;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::{Extension, Json};
+use futures_util::stream::{self, Stream};
+use futures_util::TryStreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{self, doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::concepts::acl;
+use crate::concepts::data::Data;
+use crate::concepts::edge::Node;
+use crate::concepts::user::User;
+use crate::database::DBHandle;
+use crate::routes::response::ErrorResponse;
+use crate::utils::deserialise_array::deserialise_array;
+
+// Events buffered for each subscriber before it starts missing them.
+const CHANNEL_CAPACITY: usize = 1024;
+// Items sent to a resuming subscriber before it is sent live events.
+const MAX_RESUME_ITEMS: i64 = 1000;
+
+/// A committed data item and its ID in the `data` collection.
+#[derive(Debug, Clone)]
+pub struct LiveEvent {
+    pub id: ObjectId,
+    pub data: Data,
+}
+
+/// The channel committed data is published to for live subscribers.
+#[derive(Clone)]
+pub struct LiveFeed {
+    sender: broadcast::Sender<LiveEvent>,
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl LiveFeed {
+    /// Publish committed data. Events are dropped if nobody is subscribed.
+    pub fn publish(&self, events: Vec<LiveEvent>) {
+        for event in events {
+            let _ = self.sender.send(event);
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LiveQuery {
+    #[serde(default, deserialize_with = "deserialise_array")]
+    subjects: Vec<String>,
+    #[serde(default, deserialize_with = "deserialise_array")]
+    groups: Vec<String>,
+}
+
+struct Subscription {
+    backlog: VecDeque<LiveEvent>,
+    receiver: broadcast::Receiver<LiveEvent>,
+    profiles: HashSet<Node>,
+    // Live events up to this ID have already been sent from the backlog.
+    resumed_to: Option<ObjectId>,
+}
+
+pub async fn live(
+    live_query: Option<Query<LiveQuery>>,
+    headers: HeaderMap,
+    mut db: DBHandle,
+    user: User,
+    Extension(feed): Extension<LiveFeed>,
+) -> Result<
+    Sse<impl Stream<Item = Result<Event, Infallible>>>,
+    (StatusCode, Json<ErrorResponse>),
+> {
+    if live_query.is_none() {
+        return error!(BAD_REQUEST, "You must provide subjects or groups.");
+    }
+    let Query(live_query) = live_query.unwrap();
+    if live_query.subjects.is_empty() && live_query.groups.is_empty() {
+        return error!(BAD_REQUEST, "You must provide subjects or groups.");
+    }
+
+    let last_event_id = match headers.get("last-event-id") {
+        Some(value) => {
+            match value.to_str().ok().and_then(|v| v.parse::<ObjectId>().ok()) {
+                Some(id) => Some(id),
+                None => {
+                    return error!(BAD_REQUEST, "Invalid Last-Event-ID.");
+                }
+            }
+        }
+        None => None,
+    };
+
+    let subjects = match acl::visible_subjects(
+        &live_query.subjects,
+        &live_query.groups,
+        &user.uuid,
+        &mut db,
+    )
+    .await
+    {
+        Ok(subjects) => subjects,
+        Err(invisible) => {
+            return error!(
+                NOT_FOUND,
+                &format!(
+                    "Subjects or groups do not exist or are not visible to \
+                    you: {}.",
+                    invisible.join(", ")
+                )
+            );
+        }
+    };
+    if subjects.is_empty() {
+        return error!(BAD_REQUEST, "No valid subjects were selected.");
+    }
+    let profiles: HashSet<Node> =
+        subjects.iter().flat_map(|s| s.nodes()).collect();
+
+    // Subscribe before reading the backlog so nothing committed in between
+    // is missed. Duplicates are skipped by comparing IDs.
+    let receiver = feed.subscribe();
+    let backlog = match last_event_id {
+        Some(id) => missed_events(&id, &profiles, &mut db).await,
+        None => VecDeque::new(),
+    };
+    db.session.commit_transaction().await.unwrap();
+
+    let resumed_to = backlog.back().map(|e| e.id).or(last_event_id);
+    let subscription = Subscription {
+        backlog,
+        receiver,
+        profiles,
+        resumed_to,
+    };
+
+    Ok(Sse::new(events(subscription)).keep_alive(KeepAlive::default()))
+}
+
+async fn missed_events(
+    last_event_id: &ObjectId,
+    profiles: &HashSet<Node>,
+    db: &mut DBHandle,
+) -> VecDeque<LiveEvent> {
+    let matches: Vec<Document> = profiles
+        .iter()
+        .map(|n| doc! {"platform": &n.platform, "id": &n.id})
+        .collect();
+    let filter = doc! {"_id": {"$gt": last_event_id}, "$or": matches};
+    let options = FindOptions::builder()
+        .sort(doc! {"_id": 1_i32})
+        .limit(MAX_RESUME_ITEMS)
+        .build();
+
+    let data_coll: Collection<Document> = db.collection("data");
+    let mut cursor = data_coll
+        .find_with_session(filter, options, &mut db.session)
+        .await
+        .unwrap();
+    let documents: Vec<Document> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+
+    documents
+        .into_iter()
+        .filter_map(|mut d| {
+            let id = d.remove("_id")?.as_object_id()?;
+            let data: Data = bson::from_document(d).ok()?;
+            Some(LiveEvent { id, data })
+        })
+        .collect()
+}
+
+fn events(
+    subscription: Subscription,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold(subscription, |mut s| async move {
+        let event = match s.backlog.pop_front() {
+            Some(event) => event,
+            None => loop {
+                match s.receiver.recv().await {
+                    Ok(event) => {
+                        let seen =
+                            s.resumed_to.is_some_and(|id| event.id <= id);
+                        if !seen && s.profiles.contains(&profile(&event.data)) {
+                            break event;
+                        }
+                    }
+                    // Ending the stream of a lagging subscriber prompts it to
+                    // reconnect with the last event ID it received, recovering
+                    // the items it missed from the data collection.
+                    Err(RecvError::Lagged(_)) | Err(RecvError::Closed) => {
+                        return None
+                    }
+                }
+            },
+        };
+        let sse_event = Event::default()
+            .id(event.id.to_hex())
+            .json_data(&event.data)
+            .unwrap();
+        Some((Ok(sse_event), s))
+    })
+}
+
+fn profile(data: &Data) -> Node {
+    match data {
+        Data::Presence { platform, id, .. }
+        | Data::Content { platform, id, .. }
+        | Data::Meta { platform, id, .. } => Node::new(platform, id),
+    }
+}
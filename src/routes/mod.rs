@@ -3,14 +3,24 @@
 #[macro_use]
 pub mod response;
 pub mod add;
+pub mod analytics;
 pub mod default;
+pub mod feeds;
 pub mod frontpage;
 pub mod halt;
+pub mod live;
 pub mod queue;
+pub mod reputation;
+pub mod search;
+pub mod tags;
+pub mod trash;
 pub mod types;
 pub mod view;
 
+pub mod admin;
+pub mod graph;
 pub mod groups;
+pub mod public;
 pub mod subjects;
 pub mod user;
 pub mod users;
@@ -0,0 +1,59 @@
+//! Route for following public subjects and groups in a feed reader.
+//!
+//! The /public/feed route is implemented here. It renders the content /view
+//! assembles about public subjects and the subjects in public groups as an
+//! Atom, RSS or JSON Feed document, see [`crate::concepts::feed`].
+
+use axum::extract::Query;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::{http::StatusCode, Extension, Json};
+use serde::Deserialize;
+
+use crate::concepts::feed::{Feed, FeedFormat};
+use crate::config::IConfig;
+use crate::database::DBHandle;
+use crate::routes::public::view::{public_subjects, selection, PublicQuery};
+use crate::routes::public::PublicClient;
+use crate::routes::response::ErrorResponse;
+use crate::routes::view;
+
+#[derive(Deserialize)]
+pub struct FormatQuery {
+    format: FeedFormat,
+}
+
+pub async fn feed(
+    _client: PublicClient,
+    query: Option<Query<PublicQuery>>,
+    format: Option<Query<FormatQuery>>,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let Some(Query(FormatQuery { format })) = format else {
+        return error!(
+            BAD_REQUEST,
+            "You must provide a format of atom, rss or json."
+        );
+    };
+    let query = selection(query)?;
+    let subjects = public_subjects(&query, &mut db).await?;
+
+    let names: Vec<&str> = subjects.iter().map(|s| s.name.as_str()).collect();
+    let title = names.join(", ");
+    let mut uuids: Vec<&str> =
+        subjects.iter().map(|s| s.uuid.as_str()).collect();
+    uuids.sort_unstable();
+    let id = format!("urn:instrumentality:public:{}", uuids.join(","));
+
+    let view_data = view::view_data(subjects, &[], &config, &mut db).await;
+    db.session.commit_transaction().await.unwrap();
+
+    let feed = Feed::from_view_data(&id, &title, &view_data);
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, format.content_type())],
+        feed.render(format),
+    )
+        .into_response())
+}
@@ -0,0 +1,50 @@
+//! Routes for viewing public subjects and groups without a key.
+//!
+//! Every route here is read-only and rate limited per client, see
+//! [`crate::config::PublicConfig`].
+
+pub mod feed;
+pub mod view;
+
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::{request::Parts, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{async_trait, Json};
+
+use crate::routes::response::ErrorResponse;
+use crate::utils::rate_limit::RateLimiter;
+
+/// A client of the public routes that is within its rate limit.
+pub struct PublicClient;
+
+#[async_trait]
+impl<S> FromRequestParts<S> for PublicClient
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let limiter = parts.extensions.get::<RateLimiter>().unwrap();
+        // Clients are told apart by address where the server knows it.
+        let client = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map_or_else(String::new, |c| c.0.ip().to_string());
+
+        if limiter.allow(&client) {
+            Ok(PublicClient)
+        } else {
+            Err(response!(
+                TOO_MANY_REQUESTS,
+                ErrorResponse::from_text("Too many requests.")
+            )
+            .into_response())
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Route for viewing data about public subjects and groups.
+//!
+//! The /public/view route is implemented here. It serves the same data as
+//! /view, but only about public subjects and the subjects in public groups,
+//! and without a key.
+
+use axum::extract::Query;
+use axum::{http::StatusCode, Extension, Json};
+use serde::Deserialize;
+
+use crate::concepts::acl;
+use crate::concepts::subject::Subject;
+use crate::config::IConfig;
+use crate::database::DBHandle;
+use crate::routes::public::PublicClient;
+use crate::routes::response::{ErrorResponse, ViewResponse};
+use crate::routes::view;
+use crate::utils::deserialise_array::deserialise_array;
+
+#[derive(Deserialize)]
+pub struct PublicQuery {
+    #[serde(default, deserialize_with = "deserialise_array")]
+    pub subjects: Vec<String>,
+    #[serde(default, deserialize_with = "deserialise_array")]
+    pub groups: Vec<String>,
+}
+
+pub async fn view(
+    _client: PublicClient,
+    query: Option<Query<PublicQuery>>,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+) -> Result<(StatusCode, Json<ViewResponse>), (StatusCode, Json<ErrorResponse>)>
+{
+    let query = selection(query)?;
+
+    let subjects = public_subjects(&query, &mut db).await?;
+    let view_data = view::view_data(subjects, &[], &config, &mut db).await;
+    db.session.commit_transaction().await.unwrap();
+    ok!(OK, ViewResponse::from_view_data(view_data))
+}
+
+/// The query, or an error if it selects nothing.
+pub fn selection(
+    query: Option<Query<PublicQuery>>,
+) -> Result<PublicQuery, (StatusCode, Json<ErrorResponse>)> {
+    match query {
+        Some(Query(q)) if !q.subjects.is_empty() || !q.groups.is_empty() => {
+            Ok(q)
+        }
+        _ => error!(
+            BAD_REQUEST,
+            "You must provide a list of subjects or groups."
+        ),
+    }
+}
+
+/// The public subjects selected by the query, or an error naming the subjects
+/// and groups that aren't public.
+pub async fn public_subjects(
+    query: &PublicQuery,
+    db: &mut DBHandle,
+) -> Result<Vec<Subject>, (StatusCode, Json<ErrorResponse>)> {
+    match acl::public_subjects(&query.subjects, &query.groups, db).await {
+        Ok(subjects) => Ok(subjects),
+        Err(invisible) => error!(
+            NOT_FOUND,
+            &format!(
+                "Subjects or groups do not exist or are not public: {}.",
+                invisible.join(", ")
+            )
+        ),
+    }
+}
@@ -13,6 +13,52 @@
 //! another. This massively increases the system throughput given that each
 //! fetch has an opportunity cost.
 //!
+//! When consensus is enabled, a job is locked by one provider at a time but is
+//! only marked as processed once enough different providers have done it. See
+//! [`crate::concepts::consensus`].
+//!
+//! A provider working on a slow job can extend its lock through
+//! /queue/heartbeat, and a provider that can't do a job can hand it back
+//! through /queue/release. Giving a reason for the release, such as "rate
+//! limited" or "profile not found", marks the job as failed, as does letting
+//! the lock time out. Jobs that fail repeatedly are backed off rather than
+//! being handed straight back out, and their failure count is reset once data
+//! is added for them.
+//!
+//! # Resolution
+//! Profiles added to subjects have unconfirmed queue items until it is known
+//! whether they were added by username or platform ID. Unconfirmed items are
+//! not handed out through /queue. Providers lease them through
+//! GET /queue/resolve and either report the platform ID for the username, with
+//! evidence, through POST /queue/resolve or submit metadata for the profile
+//! through /add, which confirms the item. See [`crate::concepts::resolution`].
+//!
+//! # Refresh policies
+//! Each platform can be given a refresh policy under `[queue.platforms]` in
+//! the config. Profiles on a platform are not handed out more often than its
+//! minimum interval, and profiles that haven't been refreshed within its
+//! maximum interval are handed out before anything else. A provider doing jobs
+//! for several platforms is given jobs from each in proportion to the
+//! platforms' weights, and a platform can cap how many of its jobs are leased
+//! at once, for instance to keep under a platform's rate limits.
+//!
+//! Administrators can inspect and manage the queue through the /admin/queue
+//! routes, including raising the priority of individual items so that they
+//! are handed out first. See [`crate::routes::admin::queue`].
+//!
+//! # Fairness
+//! Left alone, the queue gives the next job to whoever asks first, so one
+//! aggressive provider can starve the rest. The `[queue]` config section can
+//! cap how many jobs each provider holds at once, make providers asking for
+//! jobs on the same platform take turns, and have providers avoid doing the
+//! same profile twice in a row where another profile is available. Spreading
+//! jobs across providers also keeps the provenance of the data diverse.
+//!
+//! Turns are taken per request, so a provider leasing several jobs at once is
+//! given all of them in its turn. A provider that was given nothing the last
+//! time it was its turn, for instance because no job matched the platforms it
+//! asked for, doesn't hold up the others until it is given a job again.
+//!
 //! # Incentives
 //! Doing jobs in the queue should be preferable to simply posting whatever
 //! data the provider cares to. Ideally there would be a leaderboard that awards
@@ -41,6 +87,10 @@
 //! this will be heavily platform specific and falls outside the scope of
 //! Instrumentality.
 //!
+//! To help with this, each job is given every username seen in metadata for
+//! the profile along with when it was last seen, so that providers can try
+//! older usernames when the most recent one fails.
+//!
 //! # Round robin vs. Alternatives
 //! A naive queue implementation would be to take every platform user and cycle
 //! them, putting most recently fetched data at the bottom of the queue.
@@ -63,24 +113,34 @@
 //!
 //! Additionally, profiles under a single subject become hot by association.
 
+use axum::response::Response;
 use axum::Extension;
 use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
 use chrono::offset::TimeZone;
 use chrono::{DateTime, Duration, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson;
 use mongodb::bson::doc;
 use mongodb::bson::Bson;
-use mongodb::options::{FindOneAndUpdateOptions, FindOneOptions};
+use mongodb::bson::Document;
+use mongodb::options::{
+    FindOneAndUpdateOptions, ReturnDocument, UpdateOptions,
+};
 use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::concepts::data::Data;
-use crate::concepts::subject::Subject;
+use crate::concepts::resolution::{self, MappingStatus};
 use crate::concepts::user::User;
-use crate::config::IConfig;
+use crate::config::{IConfig, Settings};
 use crate::database::DBHandle;
-use crate::routes::response::{ErrorResponse, QueueResponse};
+use crate::routes::response::{
+    ErrorResponse, OkResponse, QueueBatchResponse, QueueResponse,
+    ResolutionResponse,
+};
 use crate::utils::deserialise_array::deserialise_array;
+use crate::utils::random;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InternalQueueItem {
@@ -92,6 +152,30 @@ pub struct InternalQueueItem {
     pub lock_acquired_at: Option<DateTime<Utc>>,
     pub references: u64,
     pub confirmed_id: bool,
+    // Providers that have submitted data for the current consensus round.
+    #[serde(default)]
+    pub round_providers: Vec<String>,
+    // Consecutive failures since the item was last processed.
+    #[serde(default)]
+    pub failures: u64,
+    #[serde(default)]
+    pub last_failure: Option<String>,
+    // The item is not handed out again until this time.
+    #[serde(default)]
+    pub backoff_until: Option<DateTime<Utc>>,
+    // Items with a higher priority are handed out first.
+    #[serde(default)]
+    pub priority: i64,
+    // The provider that last did the job.
+    #[serde(default)]
+    pub last_provider: Option<String>,
+}
+
+/// A time in the form queue items store it in, for writing it in an update or
+/// comparing against it. Times are stored as strings, so they only compare
+/// correctly if they are all in this form.
+pub fn timestamp(time: DateTime<Utc>) -> Bson {
+    bson::to_bson(&time).unwrap()
 }
 
 impl InternalQueueItem {
@@ -105,14 +189,37 @@ impl InternalQueueItem {
             lock_acquired_at: None,
             references: 1,
             confirmed_id: false,
+            round_providers: Vec::new(),
+            failures: 0,
+            last_failure: None,
+            backoff_until: None,
+            priority: 0,
+            last_provider: None,
         }
     }
 }
 
+// The most past usernames given with a job.
+const MAX_PAST_USERNAMES: i64 = 20;
+
+// The most jobs that can be leased in one request.
+const MAX_LEASES: u64 = 100;
+
 #[derive(Deserialize)]
 pub struct QueueQuery {
     #[serde(deserialize_with = "deserialise_array")]
     platforms: Vec<String>,
+    // Lease up to this many jobs at once.
+    count: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Job {
+    pub queue_id: String,
+    pub platform: String,
+    pub platform_id: String,
+    pub platform_username_hint: String,
+    pub platform_username_history: Vec<PastUsername>,
 }
 
 pub async fn queue(
@@ -122,7 +229,7 @@ pub async fn queue(
     // We use an Option so we can return a useful error when /queue is called
     // with no arguments.
     queue_query: Option<Query<QueueQuery>>,
-) -> impl IntoResponse {
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
     if queue_query.is_none() {
         return error!(
             BAD_REQUEST,
@@ -130,7 +237,8 @@ pub async fn queue(
         );
     }
 
-    let platforms = &queue_query.unwrap().platforms;
+    let Query(queue_query) = queue_query.unwrap();
+    let platforms = &queue_query.platforms;
     if platforms.is_empty() {
         return error!(
             BAD_REQUEST,
@@ -138,54 +246,359 @@ pub async fn queue(
         );
     }
     if platforms.iter().any(|p| !config.valid_platform(p)) {
-        error!(
+        return error!(
             BAD_REQUEST,
             "One or more of your given platforms is not valid.
              See /types for supported platforms."
-        )
+        );
+    }
+    if queue_query.count.is_some_and(|c| c == 0 || c > MAX_LEASES) {
+        return error!(
+            BAD_REQUEST,
+            &format!("You may lease between 1 and {MAX_LEASES} jobs.")
+        );
+    }
+
+    let mut turns = Vec::new();
+    for platform in platforms {
+        if !config.queue.round_robin
+            || provider_turn(&user, platform, &config, &mut db).await
+        {
+            turns.push(platform.clone());
+        }
+    }
+
+    let count = queue_query.count.unwrap_or(1);
+    let mut jobs = Vec::new();
+    for _ in 0..count {
+        match lease(&user, &turns, &config, &mut db).await {
+            Some(job) => jobs.push(job),
+            None => break,
+        }
+    }
+    if config.queue.round_robin && jobs.is_empty() {
+        for platform in &turns {
+            record_starved(&user, platform, &mut db).await;
+        }
+    }
+
+    // Committed even when there are no jobs so that the request still counts
+    // towards the provider's turn.
+    db.session.commit_transaction().await.unwrap();
+    if jobs.is_empty() {
+        return error!(
+            OK,
+            "There are no jobs available. Please try again later."
+        );
+    }
+
+    // Requests without a count are served a single job as they always were.
+    if queue_query.count.is_some() {
+        Ok(response!(OK, QueueBatchResponse::new(jobs)).into_response())
     } else {
-        let filter_builder = FindOneAndUpdateOptions::builder()
-            .sort(doc! {"last_processed": -1_i32});
+        let job = jobs.remove(0);
+        Ok(response!(
+            OK,
+            QueueResponse::new(
+                job.queue_id,
+                job.platform,
+                job.platform_id,
+                job.platform_username_hint,
+                job.platform_username_history,
+            )
+        )
+        .into_response())
+    }
+}
 
-        let filter = filter_builder.build();
+// Locks the next available confirmed job on any of the given platforms for the
+// user, following each platform's refresh policy and the queue's fairness
+// settings. Platforms that have reached their lease cap are skipped, overdue
+// profiles are handed out first and the remaining platforms are tried in a
+// weighted random order.
+async fn lease(
+    user: &User,
+    platforms: &[String],
+    config: &IConfig,
+    db: &mut DBHandle,
+) -> Option<Job> {
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let now = Utc::now();
 
-        let q_coll: Collection<InternalQueueItem> = db.collection("queue");
-        let result = q_coll
-            .find_one_and_update_with_session(
-                doc! {"lock_holder": Bson::Null, "platform": {"$in": &platforms}},
-                doc! {"$set": 
-                        {
-                        "lock_holder": user.uuid,
-                        "lock_acquired_at": Utc::now().to_string()
-                        }
-                    },
-                filter,
+    if let Some(max_leases) = config.queue.max_leases_per_provider {
+        let held = q_coll
+            .count_documents_with_session(
+                doc! {"lock_holder": &user.uuid},
+                None,
                 &mut db.session,
             )
             .await
             .unwrap();
-        if let Some(queue_item) = result {
-            let username_hint: String = get_username_hint(
-                &queue_item.platform_id,
-                &queue_item.platform,
-                &mut db,
+        if held >= max_leases {
+            return None;
+        }
+    }
+
+    let mut weighted = Vec::new();
+    for platform in platforms {
+        let policy = config.queue.policy(platform);
+        if let Some(max_leases) = policy.max_leases {
+            let leased = q_coll
+                .count_documents_with_session(
+                    doc! {
+                        "platform": platform,
+                        "lock_holder": {"$ne": Bson::Null}
+                    },
+                    None,
+                    &mut db.session,
+                )
+                .await
+                .unwrap();
+            if leased >= max_leases {
+                continue;
+            }
+        }
+        weighted.push((platform.clone(), policy.weight));
+    }
+    let order = weighted_order(weighted, random::new_u64);
+
+    for platform in &order {
+        let policy = config.queue.policy(platform);
+        if let Some(max_interval) = policy.max_interval_secs {
+            let due = timestamp(now - Duration::seconds(max_interval));
+            let job = take(
+                user,
+                platform,
+                doc! {"last_processed": {"$lte": due}, "confirmed_id": true},
+                doc! {"priority": -1_i32, "last_processed": 1_i32},
+                config,
+                db,
             )
             .await;
+            if job.is_some() {
+                return job;
+            }
+        }
+    }
 
-            db.session.commit_transaction().await.unwrap();
-            ok!(
-                OK,
-                QueueResponse::new(
-                    queue_item.queue_id,
-                    queue_item.platform,
-                    queue_item.platform_id,
-                    username_hint,
-                )
+    for platform in &order {
+        let policy = config.queue.policy(platform);
+        let mut filter = doc! {"confirmed_id": true};
+        if policy.min_interval_secs > 0 {
+            let fresh = now - Duration::seconds(policy.min_interval_secs);
+            filter.insert("last_processed", doc! {"$lte": timestamp(fresh)});
+        }
+        let job = take(
+            user,
+            platform,
+            filter,
+            doc! {"priority": -1_i32, "last_processed": -1_i32},
+            config,
+            db,
+        )
+        .await;
+        if job.is_some() {
+            return job;
+        }
+    }
+
+    None
+}
+
+// Locks the first available job on the platform matching the filter.
+async fn take(
+    user: &User,
+    platform: &str,
+    mut filter: Document,
+    sort: Document,
+    config: &IConfig,
+    db: &mut DBHandle,
+) -> Option<Job> {
+    let options = FindOneAndUpdateOptions::builder().sort(sort).build();
+
+    let now = timestamp(Utc::now());
+    filter.insert("lock_holder", Bson::Null);
+    filter.insert("platform", platform);
+    filter.insert(
+        "$or",
+        vec![
+            doc! {"backoff_until": Bson::Null},
+            doc! {"backoff_until": {"$lte": &now}},
+        ],
+    );
+    // Each provider may only do a job once per consensus round.
+    if config.consensus.enabled {
+        filter.insert("round_providers", doc! {"$ne": &user.uuid});
+    }
+
+    // Profiles last done by someone else are preferred if providers should
+    // avoid doing the same profile twice in a row.
+    let mut filters = vec![filter.clone()];
+    if config.queue.avoid_repeat_provider {
+        filter.insert("last_provider", doc! {"$ne": &user.uuid});
+        filters.insert(0, filter);
+    }
+
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let mut queue_item = None;
+    for filter in filters {
+        queue_item = q_coll
+            .find_one_and_update_with_session(
+                filter,
+                doc! {"$set":
+                    {
+                    "lock_holder": &user.uuid,
+                    "lock_acquired_at": &now
+                    }
+                },
+                options.clone(),
+                &mut db.session,
             )
-        } else {
-            error!(OK, "There are no jobs available. Please try again later.")
+            .await
+            .unwrap();
+        if queue_item.is_some() {
+            break;
         }
     }
+    let queue_item = queue_item?;
+
+    if config.queue.round_robin {
+        record_assignment(user, platform, db).await;
+    }
+
+    let (username_hint, username_history) =
+        get_username_hints(&queue_item.platform_id, &queue_item.platform, db)
+            .await;
+
+    Some(Job {
+        queue_id: queue_item.queue_id,
+        platform: queue_item.platform,
+        platform_id: queue_item.platform_id,
+        platform_username_hint: username_hint,
+        platform_username_history: username_history,
+    })
+}
+
+// When a provider last asked for and was last given a job on a platform, and
+// whether there was nothing for it the last time it was its turn.
+#[derive(Debug, Serialize, Deserialize)]
+struct ProviderTurn {
+    platform: String,
+    provider: String,
+    last_requested: String,
+    last_assigned: String,
+    #[serde(default)]
+    starved: bool,
+}
+
+// Whether it is the user's turn to be given a job on the platform. Providers
+// that have asked for jobs on the platform recently take turns, with the one
+// given a job least recently going first. Providers that were given nothing
+// on their last turn are skipped.
+async fn provider_turn(
+    user: &User,
+    platform: &str,
+    config: &IConfig,
+    db: &mut DBHandle,
+) -> bool {
+    let now = Utc::now();
+    let t_coll: Collection<ProviderTurn> = db.collection("queue_providers");
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+    let turn = t_coll
+        .find_one_and_update_with_session(
+            doc! {"platform": platform, "provider": &user.uuid},
+            doc! {
+                "$set": {"last_requested": now.to_string()},
+                "$setOnInsert": {"last_assigned": ""}
+            },
+            options,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    let Some(turn) = turn else {
+        return true;
+    };
+
+    let active_since = now - Duration::seconds(config.queue.round_robin_secs);
+    let waiting = t_coll
+        .find_one_with_session(
+            doc! {
+                "platform": platform,
+                "provider": {"$ne": &user.uuid},
+                "last_requested": {"$gte": active_since.to_string()},
+                "last_assigned": {"$lt": &turn.last_assigned},
+                "starved": {"$ne": true}
+            },
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    waiting.is_none()
+}
+
+async fn record_assignment(user: &User, platform: &str, db: &mut DBHandle) {
+    let t_coll: Collection<ProviderTurn> = db.collection("queue_providers");
+    t_coll
+        .update_one_with_session(
+            doc! {"platform": platform, "provider": &user.uuid},
+            doc! {"$set": {
+                "last_requested": Utc::now().to_string(),
+                "last_assigned": Utc::now().to_string(),
+                "starved": false
+            }},
+            UpdateOptions::builder().upsert(true).build(),
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+}
+
+async fn record_starved(user: &User, platform: &str, db: &mut DBHandle) {
+    let t_coll: Collection<ProviderTurn> = db.collection("queue_providers");
+    t_coll
+        .update_one_with_session(
+            doc! {"platform": platform, "provider": &user.uuid},
+            doc! {"$set": {"starved": true}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+}
+
+// Orders the platforms by drawing them one at a time, each with a chance
+// proportional to its weight. Platforms with no weight are drawn last.
+fn weighted_order(
+    mut weighted: Vec<(String, u64)>,
+    mut roll: impl FnMut() -> u64,
+) -> Vec<String> {
+    let mut order = Vec::new();
+    while !weighted.is_empty() {
+        let total: u64 = weighted.iter().map(|(_, w)| w).sum();
+        let index = if total == 0 {
+            0
+        } else {
+            let mut target = roll() % total;
+            weighted
+                .iter()
+                .position(|(_, w)| {
+                    if target < *w {
+                        true
+                    } else {
+                        target -= w;
+                        false
+                    }
+                })
+                .unwrap()
+        };
+        order.push(weighted.remove(index).0);
+    }
+    order
 }
 
 // This is a really bad function. The logic should be simplified significantly.
@@ -202,6 +615,7 @@ pub async fn process(
     db: &mut DBHandle,
 ) -> bool {
     let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let is_meta = username.is_some();
     // If this is a metadata update...
     if let Some(username) = username {
         let find_result = q_coll
@@ -219,28 +633,21 @@ pub async fn process(
             )
             .await
             .unwrap();
-        // and if so...
+        // and if so, the metadata tells us the ID that the username belongs
+        // to.
         if find_result.is_some() {
-            // Remove the temporary username queue item...
-            remove_queue_item(&username, platform, db).await;
-            // and either merge the username with the already existing queue
-            // item with that name or create a new one with the
-            // platform id.
-            add_queue_item(id, platform, db, true).await;
-
-            // and update the subject to use the platform ID instead of a
-            // username.
-            let subj_coll: Collection<Subject> = db.collection("subjects");
-            subj_coll
-                .update_one_with_session(
-                    doc! {&format!("profiles.{platform}"): &username},
-                    doc! {"$set": {&format!("profiles.{platform}.$"): id}},
-                    None,
-                    &mut db.session,
-                )
-                .await
-                .unwrap();
-            return true;
+            let status = resolution::resolve(
+                platform,
+                &username,
+                id,
+                added_by,
+                "Metadata submitted through /add.",
+                db,
+            )
+            .await;
+            if status == MappingStatus::Applied {
+                return true;
+            }
         }
     }
 
@@ -251,7 +658,10 @@ pub async fn process(
                 {
                     "lock_holder": Bson::Null,
                     "lock_acquired_at": Bson::Null,
-                    "last_processed": Utc::now().to_string()
+                    "last_processed": timestamp(Utc::now()),
+                    "last_provider": added_by,
+                    "failures": 0_i64,
+                    "backoff_until": Bson::Null
                 }
             },
             None,
@@ -260,6 +670,20 @@ pub async fn process(
         .await
         .unwrap();
 
+    // Metadata for the profile under the queue item's own platform ID
+    // confirms the ID.
+    if is_meta && q_update_result.modified_count == 1 {
+        q_coll
+            .update_one_with_session(
+                doc! {"queue_id": queue_id, "platform_id": id},
+                doc! {"$set": {"confirmed_id": true}},
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+    }
+
     q_update_result.modified_count == 1
 }
 
@@ -342,58 +766,342 @@ pub async fn remove_queue_item(
     }
 }
 
-pub async fn get_username_hint(
+/// A username a profile has had and when it was last seen.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PastUsername {
+    pub username: String,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// The most recent username for a profile, falling back to the platform ID,
+/// and every username seen for the profile, most recently seen first.
+pub async fn get_username_hints(
     platform_id: &str,
     platform: &str,
     db: &mut DBHandle,
-) -> String {
-    async fn from_meta(
-        platform_id: &str,
-        platform: &str,
-        db: &mut DBHandle,
-    ) -> Option<String> {
-        let filter = FindOneOptions::builder()
-            .projection(doc! {"username": 1_u32})
-            .build();
-
-        #[derive(Debug, Serialize, Deserialize)]
-        struct Username {
-            username: String,
+) -> (String, Vec<PastUsername>) {
+    let pipeline = vec![
+        doc! {"$match": {
+            "id": platform_id,
+            "platform": platform,
+            "username": {"$exists": true}
+        }},
+        doc! {"$group": {
+            "_id": "$username",
+            "last_seen": {"$max": "$retrieved_at"}
+        }},
+        doc! {"$sort": {"last_seen": -1_i32}},
+        doc! {"$limit": MAX_PAST_USERNAMES},
+        doc! {"$project": {"_id": 0_i32, "username": "$_id", "last_seen": 1_i32}},
+    ];
+
+    let data_coll: Collection<Data> = db.collection("data");
+    let mut cursor = data_coll
+        .aggregate_with_session(pipeline, None, &mut db.session)
+        .await
+        .unwrap();
+    let mut history: Vec<PastUsername> = Vec::new();
+    while let Some(document) = cursor.next(&mut db.session).await {
+        if let Ok(past) = bson::from_document(document.unwrap()) {
+            history.push(past);
         }
+    }
 
-        let data_coll: Collection<Data> = db.collection("data");
-        let username = data_coll
-            .clone_with_type::<Username>()
-            .find_one_with_session(
-                doc! {"id": &platform_id, "platform": &platform},
-                filter,
-                &mut db.session,
-            )
-            .await;
+    let hint = history
+        .first()
+        .map(|p| p.username.clone())
+        .unwrap_or_else(|| platform_id.to_string());
+    (hint, history)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HeartbeatRequest {
+    pub queue_id: String,
+}
+
+/// Extend a held lock on a queue item for another `queue_timeout_secs`.
+pub async fn heartbeat(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<HeartbeatRequest>,
+) -> impl IntoResponse {
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let result = q_coll
+        .update_one_with_session(
+            doc! {"queue_id": &req.queue_id, "lock_holder": &user.uuid},
+            doc! {"$set": {"lock_acquired_at": timestamp(Utc::now())}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+
+    if result.matched_count == 0 {
+        return error!(BAD_REQUEST, "You do not hold a lock on this job.");
+    }
+
+    db.session.commit_transaction().await.unwrap();
+    ok!()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReleaseRequest {
+    pub queue_id: String,
+    // Why the job couldn't be done, e.g. "rate limited" or "profile not
+    // found". Releasing with a reason counts as a failure.
+    pub reason: Option<String>,
+}
+
+/// Hand a held queue item back to the queue. Items released with a reason
+/// are backed off before being handed out again.
+pub async fn release(
+    user: User,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+    Json(req): Json<ReleaseRequest>,
+) -> impl IntoResponse {
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let filter = doc! {"queue_id": &req.queue_id, "lock_holder": &user.uuid};
+    let queue_item = q_coll
+        .find_one_with_session(filter.clone(), None, &mut db.session)
+        .await
+        .unwrap();
+
+    let Some(queue_item) = queue_item else {
+        return error!(BAD_REQUEST, "You do not hold a lock on this job.");
+    };
+
+    let update = match req.reason {
+        Some(reason) => {
+            failure_update(queue_item.failures, &reason, &config.settings)
+        }
+        None => unlock_update(),
+    };
+    q_coll
+        .update_one_with_session(filter, update, None, &mut db.session)
+        .await
+        .unwrap();
+
+    db.session.commit_transaction().await.unwrap();
+    ok!()
+}
+
+#[derive(Deserialize)]
+pub struct ResolutionQueueQuery {
+    #[serde(deserialize_with = "deserialise_array")]
+    platforms: Vec<String>,
+}
+
+/// Lease an unconfirmed queue item, one whose platform ID is really a
+/// username, so that the username can be resolved to a platform ID.
+pub async fn resolution_queue(
+    user: User,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+    query: Option<Query<ResolutionQueueQuery>>,
+) -> impl IntoResponse {
+    let Some(Query(query)) = query else {
+        return error!(
+            BAD_REQUEST,
+            "You must provide a list of supported platforms."
+        );
+    };
+    if query.platforms.is_empty()
+        || query.platforms.iter().any(|p| !config.valid_platform(p))
+    {
+        return error!(
+            BAD_REQUEST,
+            "One or more of your given platforms is not valid.
+             See /types for supported platforms."
+        );
+    }
 
-        if let Ok(Some(username)) = username {
-            return Some(username.username);
+    for platform in &query.platforms {
+        let job = take(
+            &user,
+            platform,
+            doc! {"confirmed_id": false},
+            doc! {"priority": -1_i32, "last_processed": 1_i32},
+            &config,
+            &mut db,
+        )
+        .await;
+        if let Some(job) = job {
+            db.session.commit_transaction().await.unwrap();
+            return ok!(
+                OK,
+                QueueResponse::new(
+                    job.queue_id,
+                    job.platform,
+                    job.platform_id,
+                    job.platform_username_hint,
+                    job.platform_username_history,
+                )
+            );
         }
-        None
     }
 
-    from_meta(platform_id, platform, db)
+    error!(
+        OK,
+        "There are no usernames to resolve. Please try again later."
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResolveRequest {
+    pub queue_id: String,
+    // The platform ID that the queue item's username belongs to.
+    pub platform_id: String,
+    // Why the provider believes the username belongs to the ID, such as a
+    // link to the profile's page.
+    pub evidence: String,
+}
+
+/// Report the platform ID for a leased unconfirmed queue item. See
+/// [`crate::concepts::resolution`].
+pub async fn resolve(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<ResolveRequest>,
+) -> impl IntoResponse {
+    if req.platform_id.is_empty() || req.evidence.is_empty() {
+        return error!(
+            BAD_REQUEST,
+            "You must provide a platform ID and evidence for it."
+        );
+    }
+
+    let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let filter = doc! {
+        "queue_id": &req.queue_id,
+        "lock_holder": &user.uuid,
+        "confirmed_id": false
+    };
+    let queue_item = q_coll
+        .find_one_with_session(filter.clone(), None, &mut db.session)
         .await
-        .unwrap_or_else(|| platform_id.to_string())
+        .unwrap();
+    let Some(queue_item) = queue_item else {
+        return error!(
+            BAD_REQUEST,
+            "You do not hold a lock on an unconfirmed job with this ID."
+        );
+    };
+
+    let status = resolution::resolve(
+        &queue_item.platform,
+        &queue_item.platform_id,
+        &req.platform_id,
+        &user.uuid,
+        &req.evidence,
+        &mut db,
+    )
+    .await;
+    // A conflicting mapping leaves the username unresolved.
+    if status == MappingStatus::Conflict {
+        q_coll
+            .update_one_with_session(
+                filter,
+                unlock_update(),
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+    }
+
+    db.session.commit_transaction().await.unwrap();
+    ok!(OK, ResolutionResponse::new(status))
+}
+
+fn unlock_update() -> Document {
+    doc! {"$set": {"lock_holder": Bson::Null, "lock_acquired_at": Bson::Null}}
 }
 
-pub async fn clear_old_locks(db: &mut DBHandle, timeout: Duration) {
+// Unlocks a queue item that has failed `failures` times before and backs it
+// off.
+fn failure_update(
+    failures: u64,
+    reason: &str,
+    settings: &Settings,
+) -> Document {
+    let failures = failures + 1;
+    let backoff_until = Utc::now() + settings.queue_backoff(failures);
+    doc! {"$set": {
+        "lock_holder": Bson::Null,
+        "lock_acquired_at": Bson::Null,
+        "failures": failures as i64,
+        "last_failure": reason,
+        "backoff_until": timestamp(backoff_until)
+    }}
+}
+
+/// Release locks held for longer than `queue_timeout_secs`. Jobs abandoned
+/// like this count as failures.
+pub async fn clear_old_locks(db: &mut DBHandle, settings: &Settings) {
     let q_coll: Collection<InternalQueueItem> = db.collection("queue");
+    let timeout = Duration::seconds(settings.queue_timeout_secs);
     let thirty_seconds_ago = Utc::now() - timeout;
-    q_coll
-        .update_many(
-            doc! {"lock_acquired_at": {"$lt": thirty_seconds_ago.to_string()}},
-            doc! {"$set":
-                {"lock_acquired_at": Bson::Null,
-                "lock_holder": Bson::Null}
-            },
+    let mut cursor = q_coll
+        .find(
+            doc! {"lock_acquired_at": {"$lt": timestamp(thirty_seconds_ago)}},
             None,
         )
         .await
         .unwrap();
+    while let Some(queue_item) = cursor.try_next().await.unwrap() {
+        q_coll
+            .update_one(
+                doc! {
+                    "queue_id": &queue_item.queue_id,
+                    "lock_holder": &queue_item.lock_holder
+                },
+                failure_update(queue_item.failures, "Lock expired.", settings),
+                None,
+            )
+            .await
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn weighted(weights: &[(&str, u64)]) -> Vec<(String, u64)> {
+        weights.iter().map(|(p, w)| (p.to_string(), *w)).collect()
+    }
+
+    #[test]
+    fn test_weighted_order_follows_rolls() {
+        let weights = weighted(&[("A", 1), ("B", 3), ("C", 1)]);
+
+        let order = weighted_order(weights.clone(), || 0);
+        assert_eq!(order, vec!["A", "B", "C"]);
+
+        let mut rolls = vec![1, 0, 0].into_iter();
+        let order = weighted_order(weights, || rolls.next().unwrap());
+        assert_eq!(order, vec!["B", "A", "C"]);
+    }
+
+    #[test]
+    fn test_weighted_order_puts_unweighted_last() {
+        let weights = weighted(&[("A", 0), ("B", 2), ("C", 0)]);
+        let order = weighted_order(weights, || 1);
+        assert_eq!(order, vec!["B", "A", "C"]);
+    }
+
+    #[test]
+    fn test_timestamp_matches_stored_items() {
+        let item = InternalQueueItem::new("ID".into(), "PLATFORM".into());
+        let stored = bson::to_document(&item).unwrap();
+        assert_eq!(
+            stored.get("last_processed"),
+            Some(&timestamp(item.last_processed))
+        );
+
+        let earlier = timestamp(Utc::now() - Duration::hours(1));
+        let later = timestamp(Utc::now());
+        assert!(earlier.as_str().unwrap() < later.as_str().unwrap());
+    }
 }
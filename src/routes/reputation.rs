@@ -0,0 +1,35 @@
+//! Route for reviewing the reputation of data providers.
+//!
+//! The /reputation route is implemented here.
+//!
+//! See endpoint documentation at
+//! <https://docs.berserksystems.com/endpoints/reputation/>.
+//!
+//! Only administrators may review reputations. See
+//! [`crate::concepts::reputation`] for how they are scored.
+
+use axum::Extension;
+use axum::{http::StatusCode, response::IntoResponse, Json};
+
+use crate::concepts::reputation::Reputation;
+use crate::concepts::user::User;
+use crate::config::IConfig;
+use crate::database::DBHandle;
+use crate::routes::response::{ErrorResponse, ReputationResponse};
+
+pub async fn reputation(
+    user: User,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+) -> impl IntoResponse {
+    if !user.admin {
+        return error!(UNAUTHORIZED, "Unauthorised.");
+    }
+
+    let reputations = Reputation::all(&mut db).await;
+    db.session.commit_transaction().await.unwrap();
+    ok!(
+        OK,
+        ReputationResponse::new(config.reputation.min_score, reputations)
+    )
+}
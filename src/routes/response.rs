@@ -44,6 +44,53 @@ impl InviteResponse {
     }
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct FeedTokenResponse {
+    pub response: String,
+    pub subject: String,
+    pub token: String,
+}
+
+impl FeedTokenResponse {
+    pub fn new(subject: &str, token: String) -> Self {
+        Self {
+            response: "OK".to_string(),
+            subject: subject.to_string(),
+            token,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TagsResponse {
+    pub response: String,
+    pub tags: Vec<crate::concepts::tag::TagUsage>,
+}
+
+impl TagsResponse {
+    pub fn new(tags: Vec<crate::concepts::tag::TagUsage>) -> Self {
+        Self {
+            response: "OK".to_string(),
+            tags,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TrashResponse {
+    pub response: String,
+    pub trash: Vec<crate::concepts::trash::TrashEntry>,
+}
+
+impl TrashResponse {
+    pub fn new(trash: Vec<crate::concepts::trash::TrashEntry>) -> Self {
+        Self {
+            response: "OK".to_string(),
+            trash,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct QueueResponse {
     pub response: String,
@@ -51,6 +98,8 @@ pub struct QueueResponse {
     pub platform: String,
     pub platform_id: String,
     pub platform_username_hint: String,
+    #[serde(default)]
+    pub platform_username_history: Vec<crate::routes::queue::PastUsername>,
 }
 
 impl QueueResponse {
@@ -59,6 +108,7 @@ impl QueueResponse {
         platform: String,
         platform_id: String,
         platform_username_hint: String,
+        platform_username_history: Vec<crate::routes::queue::PastUsername>,
     ) -> Self {
         Self {
             response: "OK".to_string(),
@@ -66,6 +116,116 @@ impl QueueResponse {
             platform,
             platform_id,
             platform_username_hint,
+            platform_username_history,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct QueueBatchResponse {
+    pub response: String,
+    pub jobs: Vec<crate::routes::queue::Job>,
+}
+
+impl QueueBatchResponse {
+    pub fn new(jobs: Vec<crate::routes::queue::Job>) -> Self {
+        Self {
+            response: "OK".to_string(),
+            jobs,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QueueItemsResponse {
+    pub response: String,
+    pub items: Vec<crate::routes::queue::InternalQueueItem>,
+}
+
+impl QueueItemsResponse {
+    pub fn new(items: Vec<crate::routes::queue::InternalQueueItem>) -> Self {
+        Self {
+            response: "OK".to_string(),
+            items,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StalenessResponse {
+    pub response: String,
+    pub platforms: Vec<crate::routes::admin::queue::PlatformStaleness>,
+}
+
+impl StalenessResponse {
+    pub fn new(
+        platforms: Vec<crate::routes::admin::queue::PlatformStaleness>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            platforms,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PurgeResponse {
+    pub response: String,
+    pub purged: u64,
+}
+
+impl PurgeResponse {
+    pub fn new(purged: u64) -> Self {
+        Self {
+            response: "OK".to_string(),
+            purged,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResolutionResponse {
+    pub response: String,
+    pub status: crate::concepts::resolution::MappingStatus,
+}
+
+impl ResolutionResponse {
+    pub fn new(status: crate::concepts::resolution::MappingStatus) -> Self {
+        Self {
+            response: "OK".to_string(),
+            status,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UsernameMappingsResponse {
+    pub response: String,
+    pub mappings: Vec<crate::concepts::resolution::UsernameMapping>,
+}
+
+impl UsernameMappingsResponse {
+    pub fn new(
+        mappings: Vec<crate::concepts::resolution::UsernameMapping>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            mappings,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RetentionResponse {
+    pub response: String,
+    pub reports: Vec<crate::concepts::retention::RuleReport>,
+}
+
+impl RetentionResponse {
+    pub fn new(reports: Vec<crate::concepts::retention::RuleReport>) -> Self {
+        Self {
+            response: "OK".to_string(),
+            reports,
         }
     }
 }
@@ -105,6 +265,123 @@ impl ViewResponse {
     }
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct AnalyticsResponse {
+    pub response: String,
+    pub subjects: Vec<crate::routes::analytics::SubjectAnalytics>,
+    pub groups: Vec<crate::routes::analytics::GroupAnalytics>,
+}
+
+impl AnalyticsResponse {
+    pub fn new(
+        subjects: Vec<crate::routes::analytics::SubjectAnalytics>,
+        groups: Vec<crate::routes::analytics::GroupAnalytics>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            subjects,
+            groups,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NeighboursResponse {
+    pub response: String,
+    pub outgoing: Vec<crate::concepts::edge::Edge>,
+    pub incoming: Vec<crate::concepts::edge::Edge>,
+}
+
+impl NeighboursResponse {
+    pub fn new(
+        outgoing: Vec<crate::concepts::edge::Edge>,
+        incoming: Vec<crate::concepts::edge::Edge>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            outgoing,
+            incoming,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PathResponse {
+    pub response: String,
+    pub path: Vec<crate::concepts::edge::Node>,
+    pub edges: Vec<crate::concepts::edge::Edge>,
+}
+
+impl PathResponse {
+    pub fn new(
+        path: Vec<crate::concepts::edge::Node>,
+        edges: Vec<crate::concepts::edge::Edge>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            path,
+            edges,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuggestionsResponse {
+    pub response: String,
+    pub suggestions: Vec<crate::routes::subjects::suggestions::Suggestion>,
+}
+
+impl SuggestionsResponse {
+    pub fn new(
+        suggestions: Vec<crate::routes::subjects::suggestions::Suggestion>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            suggestions,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SearchResponse {
+    pub response: String,
+    pub page: u64,
+    pub results: Vec<crate::routes::search::SearchResult>,
+}
+
+impl SearchResponse {
+    pub fn new(
+        page: u64,
+        results: Vec<crate::routes::search::SearchResult>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            page,
+            results,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReputationResponse {
+    pub response: String,
+    pub min_score: f64,
+    pub reputations: Vec<crate::concepts::reputation::Reputation>,
+}
+
+impl ReputationResponse {
+    pub fn new(
+        min_score: f64,
+        reputations: Vec<crate::concepts::reputation::Reputation>,
+    ) -> Self {
+        Self {
+            response: "OK".to_string(),
+            min_score,
+            reputations,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct TypesResponse {
     pub response: String,
@@ -146,6 +423,11 @@ pub struct LoginResponse {
     pub user: crate::concepts::user::User,
     pub subjects: Vec<crate::concepts::subject::Subject>,
     pub groups: Vec<crate::concepts::group::Group>,
+    // Subjects and groups other users have shared with this user.
+    #[serde(default)]
+    pub shared_subjects: Vec<crate::concepts::subject::Subject>,
+    #[serde(default)]
+    pub shared_groups: Vec<crate::concepts::group::Group>,
 }
 
 impl LoginResponse {
@@ -153,12 +435,16 @@ impl LoginResponse {
         user: crate::concepts::user::User,
         subjects: Vec<crate::concepts::subject::Subject>,
         groups: Vec<crate::concepts::group::Group>,
+        shared_subjects: Vec<crate::concepts::subject::Subject>,
+        shared_groups: Vec<crate::concepts::group::Group>,
     ) -> Self {
         Self {
             response: "OK".to_string(),
             user,
             subjects,
             groups,
+            shared_subjects,
+            shared_groups,
         }
     }
 }
@@ -178,6 +464,50 @@ impl CreateResponse {
     }
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct ImportResponse {
+    pub response: String,
+    pub created: Vec<crate::routes::subjects::import::ImportedSubject>,
+    pub errors: Vec<crate::routes::subjects::import::RowError>,
+}
+
+impl ImportResponse {
+    pub fn new(report: crate::routes::subjects::import::ImportReport) -> Self {
+        Self {
+            response: "OK".to_string(),
+            created: report.created,
+            errors: report.errors,
+        }
+    }
+
+    pub fn error(
+        report: crate::routes::subjects::import::ImportReport,
+    ) -> Self {
+        Self {
+            response: "ERROR".to_string(),
+            created: report.created,
+            errors: report.errors,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct VersionResponse {
+    pub response: String,
+    pub uuid: String,
+    pub version: u64,
+}
+
+impl VersionResponse {
+    pub fn new(uuid: &str, version: u64) -> Self {
+        Self {
+            response: "OK".to_string(),
+            uuid: uuid.to_string(),
+            version,
+        }
+    }
+}
+
 macro_rules! ok {
     () => {
         ok!(OK)
@@ -201,4 +531,3 @@ macro_rules! response {
         (StatusCode::$code, Json($response))
     };
 }
-pub(crate) use response;
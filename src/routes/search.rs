@@ -0,0 +1,243 @@
+//! Route for searching content bodies and profile metadata.
+//!
+//! The /search route is implemented here.
+//!
+//! See endpoint documentation at
+//! <https://docs.berserksystems.com/endpoints/search/>.
+//!
+//! Searching is backed by a MongoDB text index over `body` for content and
+//! `bio` and `display_name` for metadata. Queries use the MongoDB text search
+//! syntax, so a phrase can be matched by surrounding it with double quotes and
+//! a term can be excluded by prefixing it with a hyphen.
+//!
+//! Results are restricted to the profiles of the subjects the user can see,
+//! either those given directly, those belonging to the given groups or, if
+//! neither are given, every subject the user has created. Giving a subject or
+//! group the user can't see is an error. Results can be narrowed to subjects
+//! with any of a set of tags and to content with any of a set of tags.
+//!
+//! Like /analytics, the `from` and `to` range applies to when content was
+//! created, falling back to when it was retrieved, and to when presence and
+//! metadata were retrieved.
+
+use std::collections::HashMap;
+
+use axum::{extract::Query, http::StatusCode, Json};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::acl;
+use crate::concepts::data::Data;
+use crate::concepts::tag;
+use crate::concepts::user::User;
+use crate::database::DBHandle;
+use crate::routes::response::{ErrorResponse, SearchResponse};
+use crate::utils::deserialise_array::deserialise_array;
+
+const RESULTS_PER_PAGE: i64 = 25;
+
+#[derive(Serialize, Deserialize)]
+pub struct SearchResult {
+    pub score: f64,
+    pub data: Data,
+    // Tags on the content.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: String,
+    #[serde(default, deserialize_with = "deserialise_array")]
+    subjects: Vec<String>,
+    #[serde(default, deserialize_with = "deserialise_array")]
+    groups: Vec<String>,
+    #[serde(default, deserialize_with = "deserialise_array")]
+    platforms: Vec<String>,
+    // Content types to include. The special type "meta" includes profile
+    // metadata.
+    #[serde(default, deserialize_with = "deserialise_array")]
+    types: Vec<String>,
+    // Only subjects with any of these tags are searched.
+    #[serde(default, deserialize_with = "deserialise_array")]
+    tags: Vec<String>,
+    // Only content with any of these tags is included.
+    #[serde(default, deserialize_with = "deserialise_array")]
+    content_tags: Vec<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    page: u64,
+}
+
+pub async fn search(
+    search_query: Option<Query<SearchQuery>>,
+    mut db: DBHandle,
+    user: User,
+) -> Result<(StatusCode, Json<SearchResponse>), (StatusCode, Json<ErrorResponse>)>
+{
+    if search_query.is_none() {
+        return error!(BAD_REQUEST, "You must provide a search query.");
+    }
+    let Query(search_query) = search_query.unwrap();
+    if search_query.q.trim().is_empty() {
+        return error!(BAD_REQUEST, "You must provide a search query.");
+    }
+
+    let (Some(tags), Some(content_tags)) = (
+        tag::normalise(&search_query.tags),
+        tag::normalise(&search_query.content_tags),
+    ) else {
+        return error!(BAD_REQUEST, "Invalid tag.");
+    };
+
+    let selected =
+        if search_query.subjects.is_empty() && search_query.groups.is_empty() {
+            Ok(user.selected_subjects(&[], &[], &mut db).await)
+        } else {
+            acl::visible_subjects(
+                &search_query.subjects,
+                &search_query.groups,
+                &user.uuid,
+                &mut db,
+            )
+            .await
+        };
+    let mut subjects = match selected {
+        Ok(subjects) => subjects,
+        Err(invisible) => {
+            return error!(
+                NOT_FOUND,
+                &format!(
+                    "Subjects or groups do not exist or are not visible to \
+                    you: {}.",
+                    invisible.join(", ")
+                )
+            );
+        }
+    };
+    if !tags.is_empty() {
+        subjects.retain(|s| s.tags.iter().any(|t| tags.contains(t)));
+    }
+
+    let mut profiles: Vec<Document> = Vec::new();
+    for subject in &subjects {
+        for (platform, ids) in &subject.profiles {
+            if search_query.platforms.is_empty()
+                || search_query.platforms.contains(platform)
+            {
+                profiles.push(doc! {"platform": platform, "id": {"$in": ids}});
+            }
+        }
+    }
+
+    let tagged = if content_tags.is_empty() {
+        None
+    } else {
+        Some(tag::tagged_content(&content_tags, &mut db).await)
+    };
+
+    if profiles.is_empty() || tagged.as_ref().is_some_and(|t| t.is_empty()) {
+        db.session.commit_transaction().await.unwrap();
+        return ok!(OK, SearchResponse::new(search_query.page, Vec::new()));
+    }
+
+    let filter = search_filter(&search_query, profiles, tagged);
+    // MongoDB takes the number of results to skip as a signed integer.
+    let skip = search_query
+        .page
+        .saturating_mul(RESULTS_PER_PAGE as u64)
+        .min(i64::MAX as u64);
+    let options = FindOptions::builder()
+        .projection(doc! {"score": {"$meta": "textScore"}})
+        .sort(doc! {"score": {"$meta": "textScore"}, "retrieved_at": -1_i32})
+        .skip(skip)
+        .limit(RESULTS_PER_PAGE)
+        .build();
+
+    let data_coll: Collection<Document> = db.collection("data");
+    let mut cursor = data_coll
+        .find_with_session(filter, options, &mut db.session)
+        .await
+        .unwrap();
+    let documents: Vec<Document> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+
+    let mut results = Vec::new();
+    for document in documents {
+        let score = document.get_f64("score").unwrap_or_default();
+        if let Ok(data) = bson::from_document::<Data>(document) {
+            results.push(SearchResult {
+                score,
+                data,
+                tags: Vec::new(),
+            });
+        }
+    }
+
+    for result in &mut results {
+        if let Data::Content {
+            platform,
+            content_id,
+            ..
+        } = &result.data
+        {
+            let ids = [content_id.clone()];
+            let mut tags = tag::content_tags(platform, &ids, &mut db).await;
+            result.tags = tags.remove(content_id).unwrap_or_default();
+        }
+    }
+
+    db.session.commit_transaction().await.unwrap();
+    ok!(OK, SearchResponse::new(search_query.page, results))
+}
+
+fn search_filter(
+    search_query: &SearchQuery,
+    profiles: Vec<Document>,
+    tagged: Option<HashMap<String, Vec<String>>>,
+) -> Document {
+    let mut conditions: Vec<Document> = vec![doc! {"$or": profiles}];
+
+    if let Some(tagged) = tagged {
+        let content: Vec<Document> = tagged
+            .into_iter()
+            .map(|(platform, ids)| {
+                doc! {"platform": platform, "content_id": {"$in": ids}}
+            })
+            .collect();
+        conditions.push(doc! {"$or": content});
+    }
+
+    if !search_query.types.is_empty() {
+        let mut types =
+            vec![doc! {"content_type": {"$in": &search_query.types}}];
+        if search_query.types.iter().any(|t| t == "meta") {
+            types.push(doc! {"username": {"$exists": true}});
+        }
+        conditions.push(doc! {"$or": types});
+    }
+
+    let mut range = Document::new();
+    if let Some(from) = &search_query.from {
+        range.insert("$gte", bson::to_bson(from).unwrap());
+    }
+    if let Some(to) = &search_query.to {
+        range.insert("$lte", bson::to_bson(to).unwrap());
+    }
+    if !range.is_empty() {
+        conditions.push(doc! {"$or": [
+            {"created_at": &range},
+            {"created_at": null, "retrieved_at": range}
+        ]});
+    }
+
+    doc! {
+        "$text": {"$search": &search_query.q},
+        "$and": conditions,
+    }
+}
@@ -15,6 +15,7 @@ use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::concepts::acl::Visibility;
 use crate::concepts::subject::*;
 use crate::concepts::user::User;
 use crate::config::IConfig;
@@ -72,5 +73,10 @@ pub async fn subject_from_create(
         name: cs.name,
         profiles: cs.profiles,
         description: cs.description,
+        editors: Vec::new(),
+        viewers: Vec::new(),
+        visibility: Visibility::default(),
+        tags: Vec::new(),
+        version: 0,
     }
 }
@@ -4,14 +4,19 @@
 //!
 //! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/subjects/delete/>.
+//!
+//! Deleted subjects are moved to the trash and can be restored through
+//! /subjects/restore. See [`crate::concepts::trash`].
 
 use axum::{http::StatusCode, response::IntoResponse, Json};
+use futures_util::TryStreamExt;
 use mongodb::bson::doc;
 use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 
 use crate::concepts::group::Group;
 use crate::concepts::subject::*;
+use crate::concepts::trash::{TrashItem, TrashKind};
 use crate::concepts::user::User;
 use crate::database::DBHandle;
 use crate::routes::queue;
@@ -41,6 +46,16 @@ pub async fn delete(
         .await
     {
         let group_coll: Collection<Group> = db.collection("groups");
+        let mut cursor = group_coll
+            .find_with_session(
+                doc! {"subjects": &data.uuid},
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+        let groups: Vec<Group> =
+            cursor.stream(&mut db.session).try_collect().await.unwrap();
         let result = group_coll
             .update_many_with_session(
                 doc! {"subjects": &data.uuid},
@@ -59,6 +74,16 @@ pub async fn delete(
                 )
                 .await
                 .unwrap();
+            TrashItem::new(
+                TrashKind::Subject,
+                &subject.uuid,
+                &subject.name,
+                &subject.created_by,
+                groups.into_iter().map(|g| g.uuid).collect(),
+                &subject,
+            )
+            .insert(&mut db)
+            .await;
 
             for platform in subject.profiles.keys() {
                 for id in subject.profiles.get(platform).unwrap() {
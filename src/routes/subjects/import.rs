@@ -0,0 +1,464 @@
+//! Route for importing subjects in bulk.
+//!
+//! The /subjects/import route is implemented here, along with the import used
+//! by the `instrumentality import` command.
+//!
+//! Subjects can be imported from JSON, as a list of objects with a name,
+//! profiles, an optional description and optional groups:
+//! ```json
+//! [{"name": "Alice", "profiles": {"PLATFORM_1": ["alice"]}, "groups": ["Team"]}]
+//! ```
+//! or from CSV, with one subject per row. The `name` column is required and
+//! the `description` and `groups` columns are optional. Every other column is
+//! a platform, and its cells hold IDs on that platform. A cell can hold
+//! several IDs or groups separated by semicolons:
+//! ```csv
+//! name,groups,PLATFORM_1,PLATFORM_2
+//! Alice,Team,alice;alice_alt,alice
+//! ```
+//!
+//! Groups are named, and are created for the user if they don't have a group
+//! by that name. Every row is checked before anything is imported, and errors
+//! are reported by row, counting from 1 for the first subject. In
+//! transactional mode nothing is imported if any row has an error. In best
+//! effort mode every row without an error is imported. The profiles of
+//! imported subjects are queued.
+
+use std::collections::{HashMap, HashSet};
+
+use axum::extract::Query;
+use axum::response::{IntoResponse, Response};
+use axum::{http::StatusCode, Extension, Json};
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::group::Group;
+use crate::concepts::subject::Subject;
+use crate::concepts::user::User;
+use crate::config::IConfig;
+use crate::database::DBHandle;
+use crate::routes::groups::create::{group_from_create, CreateGroupRequest};
+use crate::routes::queue;
+use crate::routes::response::{ErrorResponse, ImportResponse};
+use crate::routes::subjects::create::{
+    subject_from_create, CreateSubjectRequest,
+};
+
+// The most subjects that can be imported at once.
+const MAX_IMPORT_ROWS: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    #[default]
+    Transactional,
+    BestEffort,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ImportRow {
+    pub name: String,
+    #[serde(default)]
+    pub profiles: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub description: Option<String>,
+    // Names of the groups to put the subject in.
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportedSubject {
+    pub row: usize,
+    pub name: String,
+    pub uuid: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RowError {
+    pub row: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ImportReport {
+    pub created: Vec<ImportedSubject>,
+    pub errors: Vec<RowError>,
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    format: ImportFormat,
+    #[serde(default)]
+    mode: ImportMode,
+}
+
+// A row, or why it couldn't be read.
+type ParsedRow = Result<ImportRow, String>;
+
+pub async fn import(
+    user: User,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+    import_query: Option<Query<ImportQuery>>,
+    body: String,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let Some(Query(query)) = import_query else {
+        return error!(
+            BAD_REQUEST,
+            "You must provide a format of either csv or json."
+        );
+    };
+    let rows = match parse(query.format, &body) {
+        Ok(rows) => rows,
+        Err(e) => return error!(BAD_REQUEST, &e),
+    };
+
+    let report = import_rows(rows, query.mode, &user, &config, &mut db).await;
+    if query.mode == ImportMode::Transactional && !report.errors.is_empty() {
+        return Ok(response!(BAD_REQUEST, ImportResponse::error(report))
+            .into_response());
+    }
+    db.session.commit_transaction().await.unwrap();
+    Ok(response!(OK, ImportResponse::new(report)).into_response())
+}
+
+/// Reads subjects from CSV or JSON. Fails if the whole document can't be
+/// read, and otherwise reports rows that can't be read individually.
+pub fn parse(
+    format: ImportFormat,
+    text: &str,
+) -> Result<Vec<ParsedRow>, String> {
+    let rows = match format {
+        ImportFormat::Json => serde_json::from_str::<Vec<ImportRow>>(text)
+            .map_err(|e| format!("Invalid JSON: {e}."))?
+            .into_iter()
+            .map(Ok)
+            .collect(),
+        ImportFormat::Csv => csv_rows(text)?,
+    };
+    if rows.len() > MAX_IMPORT_ROWS {
+        return Err(format!(
+            "At most {MAX_IMPORT_ROWS} subjects can be imported at once."
+        ));
+    }
+    Ok(rows)
+}
+
+/// Checks every row and, unless a transactional import has errors, creates
+/// the subjects, queues their profiles and puts them in their groups. The
+/// caller commits the transaction.
+pub async fn import_rows(
+    rows: Vec<ParsedRow>,
+    mode: ImportMode,
+    user: &User,
+    config: &IConfig,
+    db: &mut DBHandle,
+) -> ImportReport {
+    let names: Vec<String> = rows
+        .iter()
+        .flatten()
+        .map(|r| r.name.trim().to_string())
+        .collect();
+    let existing = existing_names(&names, user, db).await;
+    let mut report = ImportReport::default();
+    let mut valid: Vec<(usize, ImportRow)> = Vec::new();
+    for (i, row) in rows.into_iter().enumerate() {
+        let checked = match row {
+            Ok(row) => check(row, &valid, &existing, config),
+            Err(e) => Err(e),
+        };
+        match checked {
+            Ok(row) => valid.push((i + 1, row)),
+            Err(error) => report.errors.push(RowError { row: i + 1, error }),
+        }
+    }
+    if mode == ImportMode::Transactional && !report.errors.is_empty() {
+        return report;
+    }
+
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    let mut groups: HashMap<String, String> = HashMap::new();
+    for (row, import_row) in valid {
+        let create = CreateSubjectRequest {
+            name: import_row.name,
+            profiles: import_row.profiles,
+            description: import_row.description,
+        };
+        let subject = subject_from_create(create, user.clone()).await;
+        subj_coll
+            .insert_one_with_session(&subject, None, &mut db.session)
+            .await
+            .unwrap();
+        for (platform, ids) in &subject.profiles {
+            for id in ids {
+                queue::add_queue_item(id, platform, db, false).await;
+            }
+        }
+        for name in &import_row.groups {
+            let group = match groups.get(name) {
+                Some(uuid) => uuid.clone(),
+                None => group_named(name, user, db).await,
+            };
+            let group_coll: Collection<Group> = db.collection("groups");
+            group_coll
+                .update_one_with_session(
+                    doc! {"uuid": &group},
+                    doc! {"$addToSet": {"subjects": &subject.uuid}},
+                    None,
+                    &mut db.session,
+                )
+                .await
+                .unwrap();
+            groups.insert(name.clone(), group);
+        }
+        report.created.push(ImportedSubject {
+            row,
+            name: subject.name,
+            uuid: subject.uuid,
+        });
+    }
+    report
+}
+
+// The names of the user's subjects that are among the names.
+async fn existing_names(
+    names: &[String],
+    user: &User,
+    db: &mut DBHandle,
+) -> HashSet<String> {
+    let subj_coll: Collection<Document> = db.collection("subjects");
+    let options = FindOptions::builder().projection(doc! {"name": 1}).build();
+    let mut cursor = subj_coll
+        .find_with_session(
+            doc! {"created_by": &user.uuid, "name": {"$in": names}},
+            options,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    let subjects: Vec<Document> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+    subjects
+        .iter()
+        .filter_map(|s| s.get_str("name").ok())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+// The row tidied up, or why it can't be imported. `existing` are the names of
+// the user's subjects.
+fn check(
+    mut row: ImportRow,
+    valid: &[(usize, ImportRow)],
+    existing: &HashSet<String>,
+    config: &IConfig,
+) -> Result<ImportRow, String> {
+    row.name = row.name.trim().to_string();
+    if row.name.is_empty() {
+        return Err("The subject must have a name.".to_string());
+    }
+    if let Some(platform) =
+        row.profiles.keys().find(|p| !config.valid_platform(p))
+    {
+        return Err(format!("{platform} is not a supported platform."));
+    }
+    if row.groups.iter().any(|g| g.trim().is_empty()) {
+        return Err("Groups must have a name.".to_string());
+    }
+    row.groups = row.groups.iter().map(|g| g.trim().to_string()).collect();
+    if valid.iter().any(|(_, r)| r.name == row.name) {
+        return Err("Subject by that name is already being imported.".into());
+    }
+    if existing.contains(&row.name) {
+        return Err("Subject by that name already exists.".to_string());
+    }
+    for ids in row.profiles.values_mut() {
+        let mut unique: Vec<String> = Vec::new();
+        for id in ids.drain(..) {
+            if !id.trim().is_empty() && !unique.contains(&id) {
+                unique.push(id);
+            }
+        }
+        *ids = unique;
+    }
+    row.profiles.retain(|_, ids| !ids.is_empty());
+    Ok(row)
+}
+
+// The UUID of the user's group with the name, created if there isn't one.
+async fn group_named(name: &str, user: &User, db: &mut DBHandle) -> String {
+    let group_coll: Collection<Group> = db.collection("groups");
+    let existing = group_coll
+        .find_one_with_session(
+            doc! {"created_by": &user.uuid, "name": name},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    if let Some(group) = existing {
+        return group.uuid;
+    }
+    let create = CreateGroupRequest {
+        name: name.to_string(),
+        subjects: Vec::new(),
+        description: None,
+        groups: Vec::new(),
+    };
+    let group = group_from_create(create, user.clone()).await;
+    group_coll
+        .insert_one_with_session(&group, None, &mut db.session)
+        .await
+        .unwrap();
+    group.uuid
+}
+
+fn csv_rows(text: &str) -> Result<Vec<ParsedRow>, String> {
+    let mut records = parse_csv(text)?.into_iter();
+    let header: Vec<String> = match records.next() {
+        Some(header) => header.iter().map(|h| h.trim().to_string()).collect(),
+        None => return Ok(Vec::new()),
+    };
+    if !header.iter().any(|h| h == "name") {
+        return Err("The CSV must have a name column.".to_string());
+    }
+
+    let split = |cell: &str| -> Vec<String> {
+        cell.split(';')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    };
+    let mut rows = Vec::new();
+    for record in records {
+        if record.len() != header.len() {
+            rows.push(Err(format!(
+                "Expected {} fields but found {}.",
+                header.len(),
+                record.len()
+            )));
+            continue;
+        }
+        let mut row = ImportRow {
+            name: String::new(),
+            profiles: HashMap::new(),
+            description: None,
+            groups: Vec::new(),
+        };
+        for (column, cell) in header.iter().zip(record) {
+            match column.as_str() {
+                "name" => row.name = cell,
+                "description" if !cell.trim().is_empty() => {
+                    row.description = Some(cell)
+                }
+                "description" => (),
+                "groups" => row.groups = split(&cell),
+                platform => {
+                    let ids = split(&cell);
+                    if !ids.is_empty() {
+                        row.profiles.insert(platform.to_string(), ids);
+                    }
+                }
+            }
+        }
+        rows.push(Ok(row));
+    }
+    Ok(rows)
+}
+
+// Splits CSV into records of fields. Fields can be quoted to contain commas,
+// newlines and quotes, with quotes doubled. Blank lines are skipped.
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' => (),
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|f| !f.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Invalid CSV: a quoted field is never closed.".to_string());
+    }
+    record.push(field);
+    if record.iter().any(|f| !f.is_empty()) {
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_quotes() {
+        let records = parse_csv("a,\"b, \"\"c\"\"\"\r\n\n\"d\ne\",\n").unwrap();
+        assert_eq!(
+            records,
+            vec![
+                vec!["a".to_string(), "b, \"c\"".to_string()],
+                vec!["d\ne".to_string(), String::new()],
+            ]
+        );
+        assert!(parse_csv("\"a").is_err());
+    }
+
+    #[test]
+    fn test_csv_rows_split_cells() {
+        let text = "name,description,groups,PLATFORM_1,PLATFORM_2\n\
+                    Alice,,Team; Other,alice;alice_alt,\n\
+                    Bob,Too short\n";
+        let rows = parse(ImportFormat::Csv, text).unwrap();
+        assert_eq!(rows.len(), 2);
+        let alice = rows[0].as_ref().unwrap();
+        assert_eq!(alice.name, "Alice");
+        assert_eq!(alice.description, None);
+        assert_eq!(alice.groups, vec!["Team", "Other"]);
+        assert_eq!(
+            alice.profiles,
+            HashMap::from([(
+                "PLATFORM_1".to_string(),
+                vec!["alice".to_string(), "alice_alt".to_string()]
+            )])
+        );
+        assert!(rows[1].is_err());
+        assert!(parse(ImportFormat::Csv, "id\n1\n").is_err());
+    }
+}
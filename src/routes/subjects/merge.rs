@@ -0,0 +1,183 @@
+//! Route for merging subjects.
+//!
+//! The /subjects/merge route is implemented here.
+//!
+//! Merging subjects into a subject gives it every profile and tag of the
+//! merged subjects, puts it in every group they were in and moves their feed
+//! tokens to it. The merged subjects are then deleted. Each profile is
+//! referenced by the queue once per subject with it, so the references of
+//! profiles the subjects had in common are released. Either everything
+//! happens or nothing does.
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use futures_util::TryStreamExt;
+use mongodb::bson::{self, doc, Document};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::acl;
+use crate::concepts::group::Group;
+use crate::concepts::subject::*;
+use crate::concepts::user::User;
+use crate::database::{unless_conflict, DBHandle};
+use crate::routes::queue;
+use crate::routes::response::{ErrorResponse, VersionResponse};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MergeSubjectsRequest {
+    // UUID of the subject to merge the others into.
+    pub uuid: String,
+    // UUIDs of the subjects to merge into it.
+    pub subjects: Vec<String>,
+    // The version of the subject the merge was made against.
+    #[serde(default)]
+    pub version: Option<u64>,
+}
+
+pub async fn merge(
+    user: User,
+    mut db: DBHandle,
+    Json(mut req): Json<MergeSubjectsRequest>,
+) -> impl IntoResponse {
+    req.subjects.sort();
+    req.subjects.dedup();
+    if req.subjects.is_empty() || req.subjects.contains(&req.uuid) {
+        return error!(
+            BAD_REQUEST,
+            "You must provide other subjects to merge into the subject."
+        );
+    }
+
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    let Some(mut subject) = subj_coll
+        .find_one_with_session(
+            acl::editable_by(doc! {"uuid": &req.uuid}, &user.uuid),
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap()
+    else {
+        return error!(
+            BAD_REQUEST,
+            "Subject does not exist or you cannot edit it."
+        );
+    };
+    if req.version.is_some_and(|v| v != subject.version) {
+        return error!(CONFLICT, "Subject has changed since that version.");
+    }
+
+    // Merged subjects are deleted, so only their owner can merge them.
+    let mut cursor = subj_coll
+        .find_with_session(
+            doc! {"uuid": {"$in": &req.subjects}, "created_by": &user.uuid},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    let merged: Vec<Subject> =
+        cursor.stream(&mut db.session).try_collect().await.unwrap();
+    if merged.len() != req.subjects.len() {
+        return error!(
+            BAD_REQUEST,
+            "One or more of the subjects does not exist or was not created by \
+            you."
+        );
+    }
+
+    let version = subject.version;
+    for other in &merged {
+        for node in other.nodes() {
+            let ids =
+                subject.profiles.entry(node.platform.clone()).or_default();
+            if ids.contains(&node.id) {
+                queue::remove_queue_item(&node.id, &node.platform, &mut db)
+                    .await;
+            } else {
+                ids.push(node.id);
+            }
+        }
+        for tag in &other.tags {
+            if !subject.tags.contains(tag) {
+                subject.tags.push(tag.clone());
+            }
+        }
+        if subject.description.is_none() {
+            subject.description = other.description.clone();
+        }
+    }
+
+    // Another update to the subject in a transaction that hasn't committed
+    // yet is a conflict too.
+    let result = unless_conflict(
+        subj_coll
+            .update_one_with_session(
+                at_version(doc! {"uuid": &subject.uuid}, version),
+                doc! {
+                    "$set": {
+                        "profiles": bson::to_bson(&subject.profiles).unwrap(),
+                        "tags": &subject.tags,
+                        "description": &subject.description
+                    },
+                    "$inc": {"version": 1_i64}
+                },
+                None,
+                &mut db.session,
+            )
+            .await,
+    );
+    if result.map_or(0, |r| r.matched_count) == 0 {
+        return error!(CONFLICT, "Subject has changed since that version.");
+    }
+    subj_coll
+        .delete_many_with_session(
+            doc! {"uuid": {"$in": &req.subjects}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+
+    let group_coll: Collection<Group> = db.collection("groups");
+    group_coll
+        .update_many_with_session(
+            doc! {"subjects": {"$in": &req.subjects}},
+            doc! {
+                "$addToSet": {"subjects": &subject.uuid},
+                "$inc": {"version": 1_i64}
+            },
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    group_coll
+        .update_many_with_session(
+            doc! {"subjects": {"$in": &req.subjects}},
+            doc! {
+                "$pull": {"subjects": {"$in": &req.subjects}},
+                "$inc": {"version": 1_i64}
+            },
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+
+    let t_coll: Collection<Document> = db.collection("feed_tokens");
+    t_coll
+        .update_many_with_session(
+            doc! {"subject": {"$in": &req.subjects}},
+            doc! {"$set": {"subject": &subject.uuid}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+
+    if unless_conflict(db.session.commit_transaction().await).is_none() {
+        return error!(CONFLICT, "Subject has changed since that version.");
+    }
+    ok!(OK, VersionResponse::new(&subject.uuid, version + 1))
+}
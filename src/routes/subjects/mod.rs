@@ -2,4 +2,10 @@
 
 pub mod create;
 pub mod delete;
+pub mod import;
+pub mod merge;
+pub mod restore;
+pub mod share;
+pub mod split;
+pub mod suggestions;
 pub mod update;
@@ -0,0 +1,90 @@
+//! Route for restoring deleted subjects.
+//!
+//! The /subjects/restore route is implemented here.
+//!
+//! A restored subject is put back in the groups it was removed from when it
+//! was deleted, if they still exist, and its profiles are queued again. See
+//! [`crate::concepts::trash`].
+
+use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
+use mongodb::bson::{self, doc};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::group::Group;
+use crate::concepts::subject::Subject;
+use crate::concepts::trash::{TrashItem, TrashKind};
+use crate::concepts::user::User;
+use crate::config::IConfig;
+use crate::database::DBHandle;
+use crate::routes::queue;
+use crate::routes::response::ErrorResponse;
+use crate::routes::response::OkResponse;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RestoreSubjectRequest {
+    pub uuid: String,
+}
+
+pub async fn restore(
+    user: User,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+    Json(req): Json<RestoreSubjectRequest>,
+) -> impl IntoResponse {
+    let Some(item) = TrashItem::find(
+        TrashKind::Subject,
+        &req.uuid,
+        &user.uuid,
+        &config.trash,
+        &mut db,
+    )
+    .await
+    else {
+        return error!(
+            BAD_REQUEST,
+            "No such subject is in the trash, or it was not deleted by the user with the given key."
+        );
+    };
+    let subject: Subject = bson::from_document(item.document.clone()).unwrap();
+
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    let existing = subj_coll
+        .find_one_with_session(
+            doc! {"created_by": &subject.created_by, "name": &subject.name},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    if existing.is_some() {
+        return error!(CONFLICT, "Subject by that name already exists.");
+    }
+
+    subj_coll
+        .insert_one_with_session(&subject, None, &mut db.session)
+        .await
+        .unwrap();
+    let group_coll: Collection<Group> = db.collection("groups");
+    group_coll
+        .update_many_with_session(
+            doc! {"uuid": {"$in": &item.groups}},
+            doc! {"$addToSet": {"subjects": &subject.uuid}},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    item.remove(&mut db).await;
+
+    if db.session.commit_transaction().await.is_ok() {
+        for platform in subject.profiles.keys() {
+            for id in subject.profiles.get(platform).unwrap() {
+                queue::add_queue_item(id, platform, &mut db, false).await;
+            }
+        }
+        ok!()
+    } else {
+        error!(CONFLICT, "Subject by that name already exists.")
+    }
+}
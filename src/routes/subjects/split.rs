@@ -0,0 +1,163 @@
+//! Route for splitting subjects.
+//!
+//! The /subjects/split route is implemented here.
+//!
+//! Splitting a subject moves some of its profiles into a new subject, such as
+//! when a subject turns out to be two people. The new subject has the same
+//! owner, sharing and visibility as the subject it was split from and is put
+//! in the same groups. The queue references of the moved profiles move with
+//! them. Either everything happens or nothing does.
+
+use std::collections::HashMap;
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use mongodb::bson::{self, doc};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::acl;
+use crate::concepts::edge::Node;
+use crate::concepts::group::Group;
+use crate::concepts::subject::*;
+use crate::concepts::user::User;
+use crate::database::{unless_conflict, DBHandle};
+use crate::routes::response::{CreateResponse, ErrorResponse};
+use crate::routes::subjects::create::{
+    subject_from_create, CreateSubjectRequest,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SplitSubjectRequest {
+    // UUID of the subject to split.
+    pub uuid: String,
+    // The profiles to move into the new subject.
+    pub profiles: HashMap<String, Vec<String>>,
+    // Name of the new subject.
+    pub name: String,
+    pub description: Option<String>,
+    // The version of the subject the split was made against.
+    #[serde(default)]
+    pub version: Option<u64>,
+}
+
+pub async fn split(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<SplitSubjectRequest>,
+) -> impl IntoResponse {
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    let Some(subject) = subj_coll
+        .find_one_with_session(
+            acl::editable_by(doc! {"uuid": &req.uuid}, &user.uuid),
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap()
+    else {
+        return error!(
+            BAD_REQUEST,
+            "Subject does not exist or you cannot edit it."
+        );
+    };
+    if req.version.is_some_and(|v| v != subject.version) {
+        return error!(CONFLICT, "Subject has changed since that version.");
+    }
+
+    let nodes = subject.nodes();
+    let moved: Vec<Node> = req
+        .profiles
+        .iter()
+        .flat_map(|(platform, ids)| {
+            ids.iter().map(|id| Node::new(platform, id))
+        })
+        .collect();
+    if moved.is_empty() || moved.iter().any(|n| !nodes.contains(n)) {
+        return error!(
+            BAD_REQUEST,
+            "You must provide profiles of the subject to move."
+        );
+    }
+
+    let existing = subj_coll
+        .find_one_with_session(
+            doc! {"created_by": &subject.created_by, "name": &req.name},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    if existing.is_some() {
+        return error!(CONFLICT, "Subject by that name already exists.");
+    }
+
+    let mut profiles = subject.profiles.clone();
+    for (platform, ids) in &req.profiles {
+        if let Some(remaining) = profiles.get_mut(platform) {
+            remaining.retain(|id| !ids.contains(id));
+        }
+    }
+    profiles.retain(|_, ids| !ids.is_empty());
+
+    // Another update to the subject in a transaction that hasn't committed
+    // yet is a conflict too.
+    let result = unless_conflict(
+        subj_coll
+            .update_one_with_session(
+                at_version(doc! {"uuid": &subject.uuid}, subject.version),
+                doc! {
+                    "$set": {"profiles": bson::to_bson(&profiles).unwrap()},
+                    "$inc": {"version": 1_i64}
+                },
+                None,
+                &mut db.session,
+            )
+            .await,
+    );
+    if result.map_or(0, |r| r.matched_count) == 0 {
+        return error!(CONFLICT, "Subject has changed since that version.");
+    }
+
+    let mut moved_profiles: HashMap<String, Vec<String>> = HashMap::new();
+    for node in moved {
+        let ids = moved_profiles.entry(node.platform).or_default();
+        if !ids.contains(&node.id) {
+            ids.push(node.id);
+        }
+    }
+    let create = CreateSubjectRequest {
+        name: req.name,
+        profiles: moved_profiles,
+        description: req.description,
+    };
+    let new_subject = Subject {
+        created_by: subject.created_by.clone(),
+        editors: subject.editors.clone(),
+        viewers: subject.viewers.clone(),
+        visibility: subject.visibility,
+        ..subject_from_create(create, user).await
+    };
+    subj_coll
+        .insert_one_with_session(&new_subject, None, &mut db.session)
+        .await
+        .unwrap();
+
+    let group_coll: Collection<Group> = db.collection("groups");
+    group_coll
+        .update_many_with_session(
+            doc! {"subjects": &subject.uuid},
+            doc! {
+                "$addToSet": {"subjects": &new_subject.uuid},
+                "$inc": {"version": 1_i64}
+            },
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+
+    if unless_conflict(db.session.commit_transaction().await).is_none() {
+        return error!(CONFLICT, "Subject has changed since that version.");
+    }
+    ok!(CREATED, CreateResponse::from_uuid(&new_subject.uuid))
+}
@@ -0,0 +1,416 @@
+//! Routes for suggesting and accepting profiles for subjects.
+//!
+//! The /subjects/suggestions and /subjects/suggestions/accept routes are
+//! implemented here.
+//!
+//! See endpoint documentation at
+//! <https://docs.berserksystems.com/endpoints/subjects/suggestions/>.
+//!
+//! Suggestions are drawn from the evidence Instrumentality already holds about
+//! a subject's profiles:
+//! - references from the subject's profiles to other profiles, see [`Edge`]. A
+//!   "link" reference is strong evidence, anything else is weak.
+//! - references from other profiles that link to one of the subject's profiles.
+//! - the link in the most recent metadata of each profile, if it points to a
+//!   supported platform.
+//! - handles mentioned in the bio of the most recent metadata of each profile,
+//!   either after the name of a supported platform or prefixed with an @.
+//!
+//! Each piece of evidence carries a confidence between 0 and 1. These are
+//! combined as independent probabilities, so more evidence for the same
+//! profile raises its confidence.
+
+use std::collections::HashMap;
+
+use axum::Extension;
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
+use mongodb::bson::doc;
+use mongodb::options::FindOneOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::acl;
+use crate::concepts::data::Data;
+use crate::concepts::edge::{Edge, Node};
+use crate::concepts::subject::Subject;
+use crate::concepts::user::User;
+use crate::config::IConfig;
+use crate::database::DBHandle;
+use crate::routes::queue;
+use crate::routes::response::{ErrorResponse, OkResponse, SuggestionsResponse};
+
+const LINK_REFERENCE_CONFIDENCE: f64 = 0.6;
+const INCOMING_LINK_REFERENCE_CONFIDENCE: f64 = 0.4;
+const OTHER_REFERENCE_CONFIDENCE: f64 = 0.05;
+const META_LINK_CONFIDENCE: f64 = 0.5;
+const BIO_PLATFORM_HANDLE_CONFIDENCE: f64 = 0.4;
+const BIO_HANDLE_CONFIDENCE: f64 = 0.2;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Suggestion {
+    pub platform: String,
+    pub id: String,
+    pub confidence: f64,
+    pub evidence: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SuggestionsQuery {
+    subject: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AcceptSuggestionRequest {
+    pub subject: String,
+    pub platform: String,
+    pub id: String,
+}
+
+pub async fn suggestions(
+    suggestions_query: Option<Query<SuggestionsQuery>>,
+    mut db: DBHandle,
+    user: User,
+    Extension(config): Extension<IConfig>,
+) -> impl IntoResponse {
+    if suggestions_query.is_none() {
+        return error!(BAD_REQUEST, "You must provide a subject.");
+    }
+    let Query(SuggestionsQuery { subject }) = suggestions_query.unwrap();
+
+    let subjects = user.selected_subjects(&[subject], &[], &mut db).await;
+    if subjects.is_empty() {
+        return error!(
+            BAD_REQUEST,
+            "No such subject exists or it is not visible to you."
+        );
+    }
+    let subject = &subjects[0];
+    let nodes = subject.nodes();
+
+    let mut evidence: Vec<(Node, f64, String)> = Vec::new();
+    for edge in Edge::for_nodes(&nodes, true, &mut db).await {
+        let confidence = if edge.kind == "link" {
+            LINK_REFERENCE_CONFIDENCE
+        } else {
+            OTHER_REFERENCE_CONFIDENCE
+        };
+        let description = format!(
+            "{}:{} references this profile as '{}' {} time(s).",
+            edge.from.platform, edge.from.id, edge.kind, edge.count
+        );
+        evidence.push((edge.to, confidence, description));
+    }
+    for edge in Edge::for_nodes(&nodes, false, &mut db).await {
+        if edge.kind == "link" {
+            let description = format!(
+                "This profile references {}:{} as 'link'.",
+                edge.to.platform, edge.to.id
+            );
+            evidence.push((
+                edge.from,
+                INCOMING_LINK_REFERENCE_CONFIDENCE,
+                description,
+            ));
+        }
+    }
+    for node in &nodes {
+        if let Some(meta) = latest_meta(node, &mut db).await {
+            evidence.extend(meta_evidence(node, &meta, &config));
+        }
+    }
+
+    db.session.commit_transaction().await.unwrap();
+    ok!(
+        OK,
+        SuggestionsResponse::new(combine_evidence(evidence, &nodes))
+    )
+}
+
+pub async fn accept(
+    user: User,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+    Json(req): Json<AcceptSuggestionRequest>,
+) -> impl IntoResponse {
+    if !config.valid_platform(&req.platform) {
+        return error!(BAD_REQUEST, "The given platform is not supported.");
+    }
+
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    let subject = subj_coll
+        .find_one_with_session(
+            acl::editable_by(doc! {"uuid": &req.subject}, &user.uuid),
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    match subject {
+        Some(subject) => {
+            if subject.nodes().contains(&Node::new(&req.platform, &req.id)) {
+                return error!(
+                    CONFLICT,
+                    "The subject already contains this profile."
+                );
+            }
+            let profiles_key = format!("profiles.{}", req.platform);
+            subj_coll
+                .update_one_with_session(
+                    acl::editable_by(doc! {"uuid": &req.subject}, &user.uuid),
+                    doc! {
+                        "$push": {profiles_key: &req.id},
+                        "$inc": {"version": 1_i64}
+                    },
+                    None,
+                    &mut db.session,
+                )
+                .await
+                .unwrap();
+            queue::add_queue_item(&req.id, &req.platform, &mut db, false).await;
+            db.session.commit_transaction().await.unwrap();
+            ok!(CREATED)
+        }
+        None => {
+            error!(BAD_REQUEST, "Subject does not exist or you cannot edit it.")
+        }
+    }
+}
+
+async fn latest_meta(node: &Node, db: &mut DBHandle) -> Option<Data> {
+    let options = FindOneOptions::builder()
+        .sort(doc! {"retrieved_at": -1_i32})
+        .build();
+    let data_coll: Collection<Data> = db.collection("data");
+    data_coll
+        .find_one_with_session(
+            doc! {
+                "id": &node.id,
+                "platform": &node.platform,
+                "username": {"$exists": true}
+            },
+            options,
+            &mut db.session,
+        )
+        .await
+        .unwrap()
+}
+
+fn meta_evidence(
+    node: &Node,
+    meta: &Data,
+    config: &IConfig,
+) -> Vec<(Node, f64, String)> {
+    let mut evidence = Vec::new();
+    if let Data::Meta { link, bio, .. } = meta {
+        if let Some(link) = link {
+            if let Some(candidate) = link_candidate(link, config) {
+                let description = format!(
+                    "The profile link of {}:{} is {link}.",
+                    node.platform, node.id
+                );
+                evidence.push((candidate, META_LINK_CONFIDENCE, description));
+            }
+        }
+        if let Some(bio) = bio {
+            for (candidate, confidence) in
+                bio_candidates(&node.platform, bio, config)
+            {
+                let description = format!(
+                    "The bio of {}:{} mentions {}.",
+                    node.platform, node.id, candidate.id
+                );
+                evidence.push((candidate, confidence, description));
+            }
+        }
+    }
+    evidence
+}
+
+// Platform names are compared without case or separators, so "last_fm"
+// matches "last.fm" and "LastFM".
+fn normalise_platform(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn platforms(config: &IConfig) -> Vec<String> {
+    let mut platforms: Vec<String> = config
+        .content_types
+        .keys()
+        .chain(config.presence_types.keys())
+        .cloned()
+        .collect();
+    platforms.sort();
+    platforms.dedup();
+    platforms
+}
+
+/// A profile on a supported platform from a URL such as
+/// "https://twitter.com/handle".
+fn link_candidate(link: &str, config: &IConfig) -> Option<Node> {
+    let without_scheme = link.split("://").last()?;
+    let mut parts = without_scheme.split('/');
+    let host = normalise_platform(parts.next()?);
+    let handle = parts.rfind(|p| !p.is_empty())?.trim_start_matches('@');
+    if handle.is_empty() {
+        return None;
+    }
+    platforms(config)
+        .into_iter()
+        .find(|p| {
+            let platform = normalise_platform(p);
+            !platform.is_empty() && host.contains(&platform)
+        })
+        .map(|p| Node::new(&p, handle))
+}
+
+/// Handles mentioned in a bio, either following the name of a supported
+/// platform (e.g. "twitter: handle" or "twitter @handle") or prefixed with an
+/// @, in which case they are assumed to be on the same platform.
+fn bio_candidates(
+    platform: &str,
+    bio: &str,
+    config: &IConfig,
+) -> Vec<(Node, f64)> {
+    let platforms = platforms(config);
+    let words: Vec<&str> = bio.split_whitespace().collect();
+    let mut candidates = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i].trim_end_matches(':');
+        let named = platforms
+            .iter()
+            .find(|p| normalise_platform(p) == normalise_platform(word));
+        match (named, words.get(i + 1)) {
+            (Some(named), Some(next)) => {
+                let handle = clean_handle(next);
+                if !handle.is_empty() {
+                    candidates.push((
+                        Node::new(named, &handle),
+                        BIO_PLATFORM_HANDLE_CONFIDENCE,
+                    ));
+                }
+                i += 2;
+                continue;
+            }
+            _ => {
+                if word.starts_with('@') {
+                    let handle = clean_handle(word);
+                    if !handle.is_empty() {
+                        candidates.push((
+                            Node::new(platform, &handle),
+                            BIO_HANDLE_CONFIDENCE,
+                        ));
+                    }
+                }
+            }
+        }
+        i += 1;
+    }
+    candidates
+}
+
+fn clean_handle(word: &str) -> String {
+    word.trim_start_matches('@')
+        .trim_end_matches(|c: char| !c.is_alphanumeric() && c != '_')
+        .to_string()
+}
+
+fn combine_evidence(
+    evidence: Vec<(Node, f64, String)>,
+    existing: &[Node],
+) -> Vec<Suggestion> {
+    let mut combined: HashMap<Node, (f64, Vec<String>)> = HashMap::new();
+    for (node, confidence, description) in evidence {
+        if existing.contains(&node) {
+            continue;
+        }
+        let entry = combined.entry(node).or_insert((1.0, Vec::new()));
+        // Track the probability that all evidence is wrong.
+        entry.0 *= 1.0 - confidence;
+        entry.1.push(description);
+    }
+
+    let mut suggestions: Vec<Suggestion> = combined
+        .into_iter()
+        .map(|(node, (unlikely, evidence))| Suggestion {
+            platform: node.platform,
+            id: node.id,
+            confidence: 1.0 - unlikely,
+            evidence,
+        })
+        .collect();
+    suggestions.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    suggestions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> IConfig {
+        toml::from_str(
+            "[content_types]
+            twitter = [\"tweet\"]
+            last_fm = [\"scrobble\"]
+            [presence_types]
+            [mongodb]
+            address = \"127.0.0.1\"
+            port = \"27017\"
+            database = \"instrumentality\"
+            [network]
+            address = \"127.0.0.1\"
+            port = \"12321\"
+            [tls]
+            cert = \"\"
+            key = \"\"",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_link_candidate() {
+        let config = config();
+
+        assert_eq!(
+            link_candidate("https://www.last.fm/user/someone", &config),
+            Some(Node::new("last_fm", "someone"))
+        );
+        assert_eq!(
+            link_candidate("https://example.com/someone", &config),
+            None
+        );
+    }
+
+    #[test]
+    fn test_bio_candidates() {
+        let config = config();
+        let candidates = bio_candidates(
+            "twitter",
+            "Music on last.fm: someone. Alt @other!",
+            &config,
+        );
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].0, Node::new("last_fm", "someone"));
+        assert_eq!(candidates[1].0, Node::new("twitter", "other"));
+    }
+
+    #[test]
+    fn test_combine_evidence() {
+        let evidence = vec![
+            (Node::new("twitter", "a"), 0.5, "one".to_string()),
+            (Node::new("twitter", "a"), 0.5, "two".to_string()),
+            (Node::new("twitter", "b"), 0.5, "three".to_string()),
+        ];
+
+        let suggestions =
+            combine_evidence(evidence, &[Node::new("twitter", "b")]);
+
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].confidence, 0.75);
+        assert_eq!(suggestions[0].evidence.len(), 2);
+    }
+}
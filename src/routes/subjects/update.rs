@@ -4,19 +4,27 @@
 //!
 //! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/subjects/update/>.
+//!
+//! POST replaces the name, profiles and description of a subject. PATCH
+//! changes only what is given: it can rename the subject, change its
+//! description and add or remove individual profiles. Either can be given the
+//! version of the subject the change was made against, in which case the
+//! change is refused with 409 Conflict if the subject has changed since.
 
 use std::collections::HashMap;
 
-use axum::{http::StatusCode, response::IntoResponse, Json};
+use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
 use mongodb::bson::doc;
 use mongodb::{bson, Collection};
 use serde::{Deserialize, Serialize};
 
+use crate::concepts::acl;
 use crate::concepts::subject::*;
 use crate::concepts::user::User;
-use crate::database::DBHandle;
+use crate::config::IConfig;
+use crate::database::{unless_conflict, DBHandle};
 use crate::routes::queue;
-use crate::routes::response::{ErrorResponse, OkResponse};
+use crate::routes::response::{ErrorResponse, VersionResponse};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UpdateSubjectRequest {
@@ -24,6 +32,25 @@ pub struct UpdateSubjectRequest {
     pub name: String,
     pub profiles: HashMap<String, Vec<String>>,
     pub description: Option<String>,
+    // The version of the subject the update was made against.
+    #[serde(default)]
+    pub version: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PatchSubjectRequest {
+    pub uuid: String,
+    #[serde(default)]
+    pub version: Option<u64>,
+    #[serde(default)]
+    pub name: Option<String>,
+    // An empty description removes it.
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub add_profiles: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub remove_profiles: HashMap<String, Vec<String>>,
 }
 
 pub async fn update(
@@ -36,65 +63,145 @@ pub async fn update(
         name,
         profiles,
         description,
+        version,
     } = data;
-    let req_uuid = &user.uuid;
+    let Some(subject) = editable_subject(&uuid, &user, &mut db).await else {
+        return error!(
+            BAD_REQUEST,
+            "Subject does not exist or you cannot edit it."
+        );
+    };
+    if version.is_some_and(|v| v != subject.version) {
+        return error!(CONFLICT, "Subject has changed since that version.");
+    }
+
+    write(subject, name, profiles, description, &mut db).await
+}
+
+pub async fn patch(
+    user: User,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+    Json(data): Json<PatchSubjectRequest>,
+) -> impl IntoResponse {
+    let Some(subject) = editable_subject(&data.uuid, &user, &mut db).await
+    else {
+        return error!(
+            BAD_REQUEST,
+            "Subject does not exist or you cannot edit it."
+        );
+    };
+    if data.version.is_some_and(|v| v != subject.version) {
+        return error!(CONFLICT, "Subject has changed since that version.");
+    }
+    if data.add_profiles.keys().any(|p| !config.valid_platform(p)) {
+        return error!(
+            BAD_REQUEST,
+            "Profiles contains unsupported platform(s)."
+        );
+    }
+
+    let mut profiles = subject.profiles.clone();
+    for (platform, ids) in data.add_profiles {
+        let existing = profiles.entry(platform).or_default();
+        for id in ids {
+            if !existing.contains(&id) {
+                existing.push(id);
+            }
+        }
+    }
+    for (platform, ids) in &data.remove_profiles {
+        if let Some(existing) = profiles.get_mut(platform) {
+            existing.retain(|id| !ids.contains(id));
+        }
+    }
+    profiles.retain(|_, ids| !ids.is_empty());
+
+    let name = data.name.unwrap_or_else(|| subject.name.clone());
+    let description = match data.description {
+        Some(d) if d.is_empty() => None,
+        Some(d) => Some(d),
+        None => subject.description.clone(),
+    };
+    write(subject, name, profiles, description, &mut db).await
+}
+
+async fn editable_subject(
+    uuid: &str,
+    user: &User,
+    db: &mut DBHandle,
+) -> Option<Subject> {
     let subj_coll: Collection<Subject> = db.collection("subjects");
-    if let Ok(Some(subject)) = subj_coll
+    subj_coll
         .find_one_with_session(
-            doc! {"uuid": &uuid, "created_by": &req_uuid},
+            acl::editable_by(doc! {"uuid": uuid}, &user.uuid),
             None,
             &mut db.session,
         )
         .await
-    {
-        let mut old_profiles: Vec<(&String, &String)> = Vec::new();
-        for platform in subject.profiles.keys() {
-            for id in subject.profiles.get(platform).unwrap() {
-                old_profiles.push((platform, id));
-            }
-        }
-        let mut new_profiles: Vec<(&String, &String)> = Vec::new();
-        for platform in profiles.keys() {
-            for id in profiles.get(platform).unwrap() {
-                new_profiles.push((platform, id));
-            }
-        }
-
-        let removed_profiles: Vec<_> = old_profiles
-            .iter()
-            .filter(|x| new_profiles.contains(x))
-            .collect();
-        let added_profiles: Vec<_> = new_profiles
-            .iter()
-            .filter(|x| old_profiles.contains(x))
-            .collect();
+        .unwrap()
+}
 
-        for (platform, id) in added_profiles {
-            queue::add_queue_item(id, platform, &mut db, false).await;
-        }
-        for (platform, id) in removed_profiles {
-            queue::remove_queue_item(id, platform, &mut db).await;
+// Writes the changes if the subject is still at the version it was read at,
+// queueing any added profiles and releasing any removed ones.
+async fn write(
+    subject: Subject,
+    name: String,
+    profiles: HashMap<String, Vec<String>>,
+    description: Option<String>,
+    db: &mut DBHandle,
+) -> Result<
+    (StatusCode, Json<VersionResponse>),
+    (StatusCode, Json<ErrorResponse>),
+> {
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    if name != subject.name {
+        let existing = subj_coll
+            .find_one_with_session(
+                doc! {"created_by": &subject.created_by, "name": &name},
+                None,
+                &mut db.session,
+            )
+            .await
+            .unwrap();
+        if existing.is_some() {
+            return error!(CONFLICT, "Subject by that name already exists.");
         }
+    }
 
+    // Another update to the subject in a transaction that hasn't committed
+    // yet is a conflict too.
+    let result = unless_conflict(
         subj_coll
             .update_one_with_session(
-                doc! {"uuid": &uuid, "created_by": &req_uuid},
-                doc! {"$set":
-                    {"name": name,
-                    "profiles": bson::to_bson(&profiles).unwrap(),
-                    "description": description}
+                at_version(doc! {"uuid": &subject.uuid}, subject.version),
+                doc! {
+                    "$set": {
+                        "name": name,
+                        "profiles": bson::to_bson(&profiles).unwrap(),
+                        "description": description
+                    },
+                    "$inc": {"version": 1_i64}
                 },
                 None,
                 &mut db.session,
             )
-            .await
-            .unwrap();
-        db.session.commit_transaction().await.unwrap();
-        ok!()
-    } else {
-        error!(
-            BAD_REQUEST,
-            "Subject does not exist or was not created by you."
-        )
+            .await,
+    );
+    if result.map_or(0, |r| r.matched_count) == 0 {
+        return error!(CONFLICT, "Subject has changed since that version.");
+    }
+
+    let (added, removed) = profile_changes(&subject.profiles, &profiles);
+    for node in added {
+        queue::add_queue_item(&node.id, &node.platform, db, false).await;
+    }
+    for node in removed {
+        queue::remove_queue_item(&node.id, &node.platform, db).await;
+    }
+
+    if unless_conflict(db.session.commit_transaction().await).is_none() {
+        return error!(CONFLICT, "Subject has changed since that version.");
     }
+    ok!(OK, VersionResponse::new(&subject.uuid, subject.version + 1))
 }
@@ -0,0 +1,195 @@
+//! Routes for tagging subjects and content.
+//!
+//! The /tags, /tags/subjects and /tags/content routes are implemented here.
+//!
+//! See [`crate::concepts::tag`] for how tags are stored.
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use mongodb::bson::{doc, Document};
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::concepts::acl;
+use crate::concepts::subject::Subject;
+use crate::concepts::tag;
+use crate::concepts::user::User;
+use crate::database::DBHandle;
+use crate::routes::response::{ErrorResponse, OkResponse, TagsResponse};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubjectTagsRequest {
+    pub uuid: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContentTagsRequest {
+    pub platform: String,
+    pub content_id: String,
+    pub tags: Vec<String>,
+}
+
+/// How often each tag is used on the subjects the user owns or was shared
+/// with and on content about them.
+pub async fn tags(user: User, mut db: DBHandle) -> impl IntoResponse {
+    let subjects = user.selected_subjects(&[], &[], &mut db).await;
+    let usage = tag::usage(&subjects, &mut db).await;
+    db.session.commit_transaction().await.unwrap();
+    response!(OK, TagsResponse::new(usage))
+}
+
+pub async fn tag_subject(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<SubjectTagsRequest>,
+) -> impl IntoResponse {
+    let Some(tags) = tag::normalise(&req.tags) else {
+        return error!(BAD_REQUEST, "Invalid tag.");
+    };
+    let update = doc! {"$addToSet": {"tags": {"$each": tags}}};
+    update_subject_tags(&req.uuid, update, &user, &mut db).await
+}
+
+pub async fn untag_subject(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<SubjectTagsRequest>,
+) -> impl IntoResponse {
+    let Some(tags) = tag::normalise(&req.tags) else {
+        return error!(BAD_REQUEST, "Invalid tag.");
+    };
+    let update = doc! {"$pull": {"tags": {"$in": tags}}};
+    update_subject_tags(&req.uuid, update, &user, &mut db).await
+}
+
+pub async fn tag_content(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<ContentTagsRequest>,
+) -> impl IntoResponse {
+    let Some(tags) = tag::normalise(&req.tags) else {
+        return error!(BAD_REQUEST, "Invalid tag.");
+    };
+    let Some(id) =
+        readable_content(&req.platform, &req.content_id, &user, &mut db).await
+    else {
+        return error!(
+            BAD_REQUEST,
+            "Content does not exist or you cannot see it."
+        );
+    };
+    tag::tag_content(
+        &req.platform,
+        &id,
+        &req.content_id,
+        &tags,
+        &user.uuid,
+        &mut db,
+    )
+    .await;
+    db.session.commit_transaction().await.unwrap();
+    ok!()
+}
+
+pub async fn untag_content(
+    user: User,
+    mut db: DBHandle,
+    Json(req): Json<ContentTagsRequest>,
+) -> impl IntoResponse {
+    let Some(tags) = tag::normalise(&req.tags) else {
+        return error!(BAD_REQUEST, "Invalid tag.");
+    };
+    let Some(id) =
+        readable_content(&req.platform, &req.content_id, &user, &mut db).await
+    else {
+        return error!(
+            BAD_REQUEST,
+            "Content does not exist or you cannot see it."
+        );
+    };
+    // Editors of a subject the content is about can remove any tag, everyone
+    // else only the tags they added.
+    let editor =
+        has_subject(&req.platform, &id, acl::editable_by, &user, &mut db).await;
+    let tagged_by = (!editor).then_some(user.uuid.as_str());
+    tag::untag_content(
+        &req.platform,
+        &req.content_id,
+        &tags,
+        tagged_by,
+        &mut db,
+    )
+    .await;
+    db.session.commit_transaction().await.unwrap();
+    ok!()
+}
+
+async fn update_subject_tags(
+    uuid: &str,
+    update: Document,
+    user: &User,
+    db: &mut DBHandle,
+) -> Result<(StatusCode, Json<OkResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    let result = subj_coll
+        .update_one_with_session(
+            acl::editable_by(doc! {"uuid": uuid}, &user.uuid),
+            update,
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap();
+    if result.matched_count == 0 {
+        return error!(
+            BAD_REQUEST,
+            "Subject does not exist or you cannot edit it."
+        );
+    }
+    db.session.commit_transaction().await.unwrap();
+    ok!()
+}
+
+// The ID of the profile an item of content is about, if it exists and the
+// user can see a subject with that profile.
+async fn readable_content(
+    platform: &str,
+    content_id: &str,
+    user: &User,
+    db: &mut DBHandle,
+) -> Option<String> {
+    let data_coll: Collection<Document> = db.collection("data");
+    let content = data_coll
+        .find_one_with_session(
+            doc! {"platform": platform, "content_id": content_id},
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap()?;
+    let id = content.get_str("id").ok()?.to_string();
+    has_subject(platform, &id, acl::readable_by, user, db)
+        .await
+        .then_some(id)
+}
+
+// Whether the user has access to a subject with the profile, as given by one
+// of the filters in [`acl`].
+async fn has_subject(
+    platform: &str,
+    id: &str,
+    access: fn(Document, &str) -> Document,
+    user: &User,
+    db: &mut DBHandle,
+) -> bool {
+    let subj_coll: Collection<Subject> = db.collection("subjects");
+    subj_coll
+        .find_one_with_session(
+            access(doc! {format!("profiles.{platform}"): id}, &user.uuid),
+            None,
+            &mut db.session,
+        )
+        .await
+        .unwrap()
+        .is_some()
+}
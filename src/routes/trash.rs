@@ -0,0 +1,28 @@
+//! Route for the trash bin.
+//!
+//! The /trash route is implemented here.
+//!
+//! See [`crate::concepts::trash`].
+
+use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
+
+use crate::concepts::trash;
+use crate::concepts::user::User;
+use crate::config::IConfig;
+use crate::database::DBHandle;
+use crate::routes::response::TrashResponse;
+
+/// The subjects and groups the user has deleted that can still be restored.
+pub async fn trash(
+    user: User,
+    mut db: DBHandle,
+    Extension(config): Extension<IConfig>,
+) -> impl IntoResponse {
+    let entries = trash::list(&user.uuid, &config.trash, &mut db)
+        .await
+        .iter()
+        .map(|i| i.entry(&config.trash))
+        .collect();
+    db.session.commit_transaction().await.unwrap();
+    response!(OK, TrashResponse::new(entries))
+}
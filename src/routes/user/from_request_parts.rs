@@ -6,7 +6,7 @@ use axum::{Extension, Json};
 
 use crate::concepts::user::User;
 use crate::database::DBPool;
-use crate::routes::response::{response, ErrorResponse};
+use crate::routes::response::ErrorResponse;
 use crate::utils::random;
 
 #[async_trait]
@@ -14,7 +14,15 @@ use crate::routes::response::LoginResponse;
 pub async fn login(user: User, mut db: DBHandle) -> impl IntoResponse {
     let subjects = user.subjects(&mut db).await.unwrap_or_default();
     let groups = user.groups(&mut db).await.unwrap_or_default();
-    let resp = LoginResponse::from_user_data(user.clone(), subjects, groups);
+    let shared_subjects = user.shared_subjects(&mut db).await;
+    let shared_groups = user.shared_groups(&mut db).await;
+    let resp = LoginResponse::from_user_data(
+        user.clone(),
+        subjects,
+        groups,
+        shared_subjects,
+        shared_groups,
+    );
 
     db.session.commit_transaction().await.unwrap();
     response!(OK, resp)
@@ -5,17 +5,24 @@
 //! See endpoint documentation at
 //! <https://docs.berserksystems.com/endpoints/view/>.
 
+use std::collections::HashMap;
+
+use axum::Extension;
 use axum::{extract::Query, http::StatusCode, Json};
 use futures_util::TryStreamExt;
 use mongodb::bson::doc;
-use mongodb::bson::Document;
 use mongodb::options::FindOptions;
 use mongodb::Collection;
 use serde::{Deserialize, Serialize};
 
+use crate::concepts::acl;
 use crate::concepts::data::Data;
+use crate::concepts::reputation::Reputation;
+use crate::concepts::retention::{self, PresenceInterval};
 use crate::concepts::subject::Subject;
+use crate::concepts::tag;
 use crate::concepts::user::User;
+use crate::config::IConfig;
 use crate::database::DBHandle;
 use crate::routes::response::{ErrorResponse, ViewResponse};
 use crate::utils::deserialise_array::deserialise_array;
@@ -68,6 +75,12 @@ pub struct ProfileData {
     pub meta: Option<Data>,
     pub content: Vec<Data>,
     pub presence: Vec<Data>,
+    // Presence older than its retention period, aggregated into intervals.
+    #[serde(default)]
+    pub presence_intervals: Vec<PresenceInterval>,
+    // Tags on the content, by content ID.
+    #[serde(default)]
+    pub content_tags: HashMap<String, Vec<String>>,
 }
 
 impl ProfileData {
@@ -76,27 +89,110 @@ impl ProfileData {
             meta,
             content: Vec::new(),
             presence: Vec::new(),
+            presence_intervals: Vec::new(),
+            content_tags: HashMap::new(),
         }
     }
 }
 
 #[derive(Deserialize)]
 pub struct ViewQuery {
-    #[serde(deserialize_with = "deserialise_array")]
+    #[serde(default, deserialize_with = "deserialise_array")]
     subjects: Vec<String>,
+    // Groups whose subjects, and those of the groups nested in them, are
+    // viewed.
+    #[serde(default, deserialize_with = "deserialise_array")]
+    groups: Vec<String>,
+    // Only subjects with any of these tags are viewed. If no subjects or
+    // groups are given, every subject the user owns or was shared with is
+    // considered.
+    #[serde(default, deserialize_with = "deserialise_array")]
+    tags: Vec<String>,
+    // Only content with any of these tags is viewed.
+    #[serde(default, deserialize_with = "deserialise_array")]
+    content_tags: Vec<String>,
 }
 
 pub async fn view(
     view_query: Option<Query<ViewQuery>>,
     mut db: DBHandle,
-    _user: User,
+    user: User,
+    Extension(config): Extension<IConfig>,
 ) -> Result<(StatusCode, Json<ViewResponse>), (StatusCode, Json<ErrorResponse>)>
 {
-    if view_query.is_none() {
-        return error!(BAD_REQUEST, "You must provide a list of subjects.");
+    let query = match view_query {
+        Some(Query(q))
+            if !q.subjects.is_empty()
+                || !q.groups.is_empty()
+                || !q.tags.is_empty() =>
+        {
+            q
+        }
+        _ => {
+            return error!(
+                BAD_REQUEST,
+                "You must provide a list of subjects, groups or tags."
+            )
+        }
+    };
+    let (Some(tags), Some(content_tags)) = (
+        tag::normalise(&query.tags),
+        tag::normalise(&query.content_tags),
+    ) else {
+        return error!(BAD_REQUEST, "Invalid tag.");
+    };
+
+    let selected = if query.subjects.is_empty() && query.groups.is_empty() {
+        Ok(user.selected_subjects(&[], &[], &mut db).await)
+    } else {
+        acl::visible_subjects(
+            &query.subjects,
+            &query.groups,
+            &user.uuid,
+            &mut db,
+        )
+        .await
+    };
+    let mut subjects = match selected {
+        Ok(subjects) => subjects,
+        Err(invisible) => {
+            return error!(
+                NOT_FOUND,
+                &format!(
+                    "Subjects or groups do not exist or are not visible to \
+                    you: {}.",
+                    invisible.join(", ")
+                )
+            );
+        }
+    };
+
+    if !tags.is_empty() {
+        subjects.retain(|s| s.tags.iter().any(|t| tags.contains(t)));
     }
 
-    let subjects = &view_query.unwrap().subjects;
+    let view_data = view_data(subjects, &content_tags, &config, &mut db).await;
+    db.session.commit_transaction().await.unwrap();
+    ok!(OK, ViewResponse::from_view_data(view_data))
+}
+
+/// The most recent data about every profile of the subjects, with content
+/// narrowed to items with any of the content tags if any are given.
+pub async fn view_data(
+    subjects: Vec<Subject>,
+    content_tags: &[String],
+    config: &IConfig,
+    db: &mut DBHandle,
+) -> ViewData {
+    let tagged = if content_tags.is_empty() {
+        None
+    } else {
+        Some(tag::tagged_content(content_tags, db).await)
+    };
+
+    // Data from providers with a low reputation is hidden.
+    let hidden =
+        Reputation::low_providers(config.reputation.min_score, db).await;
 
     let data_coll: Collection<Data> = db.collection("data");
     let filter_builder = FindOptions::builder()
@@ -105,18 +201,6 @@ pub async fn view(
         .batch_size(100);
     let filter = filter_builder.build();
 
-    let subj_coll: Collection<Subject> = db.collection("subjects");
-    let doc: Document = doc! {"uuid": {"$in": &subjects}};
-    let mut subj_cursor = subj_coll
-        .find_with_session(doc, None, &mut db.session)
-        .await
-        .unwrap();
-    let subjects: Vec<Subject> = subj_cursor
-        .stream(&mut db.session)
-        .try_collect()
-        .await
-        .unwrap();
-
     let mut view_data = ViewData::new();
 
     for s in subjects {
@@ -130,7 +214,8 @@ pub async fn view(
                     .find_one_with_session(
                         doc! {"id": &platform_id,
                             "platform": &platform_name,
-                            "profile_picture": {"$exists": true}
+                            "profile_picture": {"$exists": true},
+                            "added_by": {"$nin": &hidden}
                         },
                         None,
                         &mut db.session,
@@ -143,7 +228,8 @@ pub async fn view(
                     .find_with_session(
                         doc! {"id": &platform_id,
                             "platform": &platform_name,
-                            "presence_type": {"$exists": true}
+                            "presence_type": {"$exists": true},
+                            "added_by": {"$nin": &hidden}
                         },
                         f.clone(),
                         &mut db.session,
@@ -156,13 +242,24 @@ pub async fn view(
                     .await
                     .unwrap();
                 profile_data.presence = presence_data;
+                profile_data.presence_intervals =
+                    retention::intervals(platform_id, platform_name, db).await;
 
+                let mut content_filter = doc! {"id": &platform_id,
+                    "platform": &platform_name,
+                    "content_type": {"$exists": true},
+                    "added_by": {"$nin": &hidden}
+                };
+                if let Some(tagged) = &tagged {
+                    let ids = tagged.get(platform_name).cloned();
+                    content_filter.insert(
+                        "content_id",
+                        doc! {"$in": ids.unwrap_or_default()},
+                    );
+                }
                 let mut content_cursor = data_coll
                     .find_with_session(
-                        doc! {"id": &platform_id,
-                            "platform": &platform_name,
-                            "content_type": {"$exists": true}
-                        },
+                        content_filter,
                         f.clone(),
                         &mut db.session,
                     )
@@ -173,6 +270,17 @@ pub async fn view(
                     .try_collect()
                     .await
                     .unwrap();
+                let content_ids: Vec<String> = content_data
+                    .iter()
+                    .filter_map(|d| match d {
+                        Data::Content { content_id, .. } => {
+                            Some(content_id.clone())
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                profile_data.content_tags =
+                    tag::content_tags(platform_name, &content_ids, db).await;
                 profile_data.content = content_data;
 
                 platform_data.profiles.push(profile_data);
@@ -181,7 +289,5 @@ pub async fn view(
         }
         view_data.subject_data.push(subject_data);
     }
-
-    db.session.commit_transaction().await.unwrap();
-    ok!(OK, ViewResponse::from_view_data(view_data))
+    view_data
 }
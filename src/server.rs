@@ -15,19 +15,21 @@ use axum::{
 };
 use axum_server::tls_rustls::RustlsConfig;
 use axum_server::Handle;
-use chrono::Duration;
 use tower::ServiceBuilder;
 use tower_http::set_header::SetResponseHeaderLayer;
 use tower_http::BoxError;
 use tracing_subscriber::{prelude::*, EnvFilter};
 
+use crate::concepts::retention;
+use crate::concepts::trash;
 use crate::config::IConfig;
 use crate::database;
-use crate::database::DBHandle;
 use crate::database::DBPool;
 use crate::routes::default::error_transformer;
+use crate::routes::live::LiveFeed;
 use crate::routes::queue::clear_old_locks;
 use crate::routes::response::ErrorResponse;
+use crate::utils::rate_limit::RateLimiter;
 
 pub async fn build_server(
     config: &IConfig,
@@ -36,7 +38,7 @@ pub async fn build_server(
 
     let handle: Handle = Handle::new();
 
-    build_workers(db_pool.handle().await, config.clone()).await;
+    build_workers(&db_pool, config.clone()).await;
     tracing::info!("Workers built.");
 
     let app = build_app(config.clone(), db_pool, handle.clone());
@@ -67,9 +69,14 @@ fn build_app(config: IConfig, db_pool: DBPool, handle: Handle) -> Router {
                 error!(INTERNAL_SERVER_ERROR, "Internal server error.")
             }
         }))
+        .layer(Extension(RateLimiter::new(
+            config.public.requests_per_min,
+            std::time::Duration::from_secs(60),
+        )))
         .layer(Extension(config))
         .layer(Extension(db_pool))
         .layer(Extension(handle))
+        .layer(Extension(LiveFeed::default()))
         .layer(SetResponseHeaderLayer::overriding(
             header::SERVER,
             HeaderValue::from_static("instrumentality"),
@@ -82,34 +89,154 @@ fn build_app(config: IConfig, db_pool: DBPool, handle: Handle) -> Router {
     Router::new()
         .route("/", get(crate::routes::frontpage::frontpage))
         .route("/add", post(crate::routes::add::add))
+        .route("/analytics", get(crate::routes::analytics::analytics))
         .route("/halt", get(crate::routes::halt::halt))
         .route("/queue", get(crate::routes::queue::queue))
+        .route("/queue/heartbeat", post(crate::routes::queue::heartbeat))
+        .route("/queue/release", post(crate::routes::queue::release))
+        .route(
+            "/queue/resolve",
+            get(crate::routes::queue::resolution_queue)
+                .post(crate::routes::queue::resolve),
+        )
+        .route("/reputation", get(crate::routes::reputation::reputation))
+        .route("/admin/queue", get(crate::routes::admin::queue::items))
+        .route(
+            "/admin/queue/staleness",
+            get(crate::routes::admin::queue::staleness),
+        )
+        .route(
+            "/admin/queue/unlock",
+            post(crate::routes::admin::queue::unlock),
+        )
+        .route(
+            "/admin/queue/requeue",
+            post(crate::routes::admin::queue::requeue),
+        )
+        .route(
+            "/admin/queue/priority",
+            post(crate::routes::admin::queue::priority),
+        )
+        .route(
+            "/admin/queue/purge",
+            post(crate::routes::admin::queue::purge),
+        )
+        .route(
+            "/admin/resolutions",
+            get(crate::routes::admin::resolutions::resolutions),
+        )
+        .route(
+            "/admin/resolutions/clear",
+            post(crate::routes::admin::resolutions::clear),
+        )
+        .route(
+            "/admin/retention",
+            get(crate::routes::admin::retention::retention),
+        )
+        .route("/search", get(crate::routes::search::search))
+        .route("/tags", get(crate::routes::tags::tags))
+        .route(
+            "/tags/subjects",
+            post(crate::routes::tags::tag_subject)
+                .delete(crate::routes::tags::untag_subject),
+        )
+        .route(
+            "/tags/content",
+            post(crate::routes::tags::tag_content)
+                .delete(crate::routes::tags::untag_content),
+        )
+        .route("/trash", get(crate::routes::trash::trash))
         .route("/types", get(crate::routes::types::types))
         .route("/view", get(crate::routes::view::view))
+        .route("/view/live", get(crate::routes::live::live))
+        .route(
+            "/feeds/tokens",
+            post(crate::routes::feeds::create)
+                .delete(crate::routes::feeds::revoke),
+        )
+        .route("/feeds/:file", get(crate::routes::feeds::feed))
+        .route("/public/view", get(crate::routes::public::view::view))
+        .route("/public/feed", get(crate::routes::public::feed::feed))
+        .route(
+            "/graph/neighbours",
+            get(crate::routes::graph::neighbours::neighbours),
+        )
+        .route("/graph/path", get(crate::routes::graph::path::path))
         .route(
             "/groups/create",
             post(crate::routes::groups::create::create),
         )
         .route(
             "/groups/update",
-            post(crate::routes::groups::update::update),
+            post(crate::routes::groups::update::update)
+                .patch(crate::routes::groups::update::patch),
         )
         .route(
             "/groups/delete",
             delete(crate::routes::groups::delete::delete),
         )
+        .route(
+            "/groups/restore",
+            post(crate::routes::groups::restore::restore),
+        )
+        .route("/groups/share", post(crate::routes::groups::share::share))
+        .route(
+            "/groups/unshare",
+            post(crate::routes::groups::share::unshare),
+        )
+        .route(
+            "/groups/visibility",
+            post(crate::routes::groups::share::visibility),
+        )
         .route(
             "/subjects/create",
             post(crate::routes::subjects::create::create),
         )
+        .route(
+            "/subjects/suggestions",
+            get(crate::routes::subjects::suggestions::suggestions),
+        )
+        .route(
+            "/subjects/suggestions/accept",
+            post(crate::routes::subjects::suggestions::accept),
+        )
         .route(
             "/subjects/update",
-            post(crate::routes::subjects::update::update),
+            post(crate::routes::subjects::update::update)
+                .patch(crate::routes::subjects::update::patch),
         )
         .route(
             "/subjects/delete",
             delete(crate::routes::subjects::delete::delete),
         )
+        .route(
+            "/subjects/import",
+            post(crate::routes::subjects::import::import),
+        )
+        .route(
+            "/subjects/merge",
+            post(crate::routes::subjects::merge::merge),
+        )
+        .route(
+            "/subjects/split",
+            post(crate::routes::subjects::split::split),
+        )
+        .route(
+            "/subjects/restore",
+            post(crate::routes::subjects::restore::restore),
+        )
+        .route(
+            "/subjects/share",
+            post(crate::routes::subjects::share::share),
+        )
+        .route(
+            "/subjects/unshare",
+            post(crate::routes::subjects::share::unshare),
+        )
+        .route(
+            "/subjects/visibility",
+            post(crate::routes::subjects::share::visibility),
+        )
         .route("/user/login", get(crate::routes::user::login::login))
         .route("/user/reset", get(crate::routes::user::reset::reset))
         .route("/users/invite", get(crate::routes::users::invite::invite))
@@ -135,15 +262,38 @@ async fn build_tls(cert: &str, key: &str) -> RustlsConfig {
     }
 }
 
-async fn build_workers(mut db: DBHandle, config: IConfig) {
+async fn build_workers(db_pool: &DBPool, config: IConfig) {
+    let mut db = db_pool.handle().await;
+    let settings = config.settings.clone();
     tokio::spawn(async move {
         loop {
             tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-            clear_old_locks(
-                &mut db,
-                Duration::seconds(config.settings.queue_timeout_secs),
-            )
-            .await;
+            clear_old_locks(&mut db, &settings).await;
+        }
+    });
+
+    let mut trash_db = db_pool.handle().await;
+    let trash = config.trash.clone();
+    tokio::spawn(async move {
+        let interval = std::time::Duration::from_secs(trash.interval_secs);
+        loop {
+            let purged = trash::purge(&trash, &mut trash_db).await;
+            tracing::info!("Purged {} items from the trash.", purged);
+            tokio::time::sleep(interval).await;
         }
     });
+
+    if !config.retention.rules.is_empty() {
+        let mut db = db_pool.handle().await;
+        let rules = config.retention;
+        let interval = std::time::Duration::from_secs(rules.interval_secs);
+        tokio::spawn(async move {
+            loop {
+                let reports = retention::enforce(&rules, false, &mut db).await;
+                let expired: u64 = reports.iter().map(|r| r.expired).sum();
+                tracing::info!("Retention removed {} data.", expired);
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
 }
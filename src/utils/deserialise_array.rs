@@ -12,7 +12,7 @@ where
     let s = String::deserialize(deserializer)?;
     let nb = s
         .chars()
-        .filter(|c| !vec!['[', ']'].contains(c))
+        .filter(|c| !['[', ']'].contains(c))
         .collect::<String>();
     let v = nb
         .split(',')
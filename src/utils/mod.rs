@@ -1,3 +1,4 @@
 //! Common utilities for Instrumentality.
 pub mod deserialise_array;
 pub mod random;
+pub mod rate_limit;
@@ -10,6 +10,16 @@ pub fn new_invite_code() -> (String, String) {
     new_rand_string(64)
 }
 
+pub fn new_feed_token() -> (String, String) {
+    new_rand_string(32)
+}
+
+pub fn new_u64() -> u64 {
+    let mut bytes = [0; 8];
+    getrandom::getrandom(&mut bytes).unwrap();
+    u64::from_le_bytes(bytes)
+}
+
 pub fn hash_string(string: &str) -> String {
     let mut hasher = Sha256::new();
     hasher.update(string);
@@ -0,0 +1,69 @@
+//! Fixed window rate limiting of clients.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Clients tracked before those with expired windows are forgotten.
+const MAX_CLIENTS: usize = 10_000;
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    clients: Arc<Mutex<HashMap<String, (Instant, u32)>>>,
+}
+
+impl RateLimiter {
+    /// Allows each client `limit` requests every `window`.
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            clients: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Counts a request from the client, returning false if it is over the
+    /// limit.
+    pub fn allow(&self, client: &str) -> bool {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_CLIENTS {
+            clients.retain(|_, (start, _)| now - *start < self.window);
+        }
+
+        let (start, count) =
+            clients.entry(client.to_string()).or_insert((now, 0));
+        if now - *start >= self.window {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= self.limit {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_limits_each_client() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        assert!(limiter.allow("a"));
+        assert!(limiter.allow("a"));
+        assert!(!limiter.allow("a"));
+        assert!(limiter.allow("b"));
+    }
+
+    #[test]
+    fn test_window_resets() {
+        let limiter = RateLimiter::new(1, Duration::ZERO);
+        assert!(limiter.allow("a"));
+        assert!(limiter.allow("a"));
+    }
+}
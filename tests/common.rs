@@ -11,6 +11,8 @@
 //! will not be created and your test environment will yield subtly different
 //! outcomes making debugging difficult.
 
+use std::collections::HashMap;
+
 use axum::body::Body;
 use axum::http::Method;
 use axum::http::Request;
@@ -18,13 +20,18 @@ use axum::http::StatusCode;
 use axum::Router;
 use axum_server::Handle;
 use chrono::Utc;
-use instrumentality::concepts::data::Data;
+use instrumentality::concepts::data::{Data, Datas};
 use instrumentality::concepts::user::User;
 use instrumentality::config;
 use instrumentality::config::IConfig;
 use instrumentality::database;
-use instrumentality::routes::response::LoginResponse;
+use instrumentality::routes::groups::create::CreateGroupRequest;
+use instrumentality::routes::response::{
+    CreateResponse, LoginResponse, QueueResponse,
+};
+use instrumentality::routes::subjects::create::CreateSubjectRequest;
 use instrumentality::server;
+use mongodb::bson::{doc, Document};
 use tower::Service;
 use uuid::Uuid;
 
@@ -32,20 +39,30 @@ pub const TEST_ENVIRONMENT_CONFIG: &str = "InstrumentalityTest.toml";
 
 pub struct Environment {
     pub app: Router,
+    #[allow(dead_code)]
     pub user: User,
     pub user_key: String,
     pub config: IConfig,
+    #[allow(dead_code)]
     pub handle: Handle,
 }
 
 impl Environment {
+    #[allow(dead_code)]
     pub async fn default() -> Self {
         Self::new(TEST_ENVIRONMENT_CONFIG).await
     }
 
     // TODO: add an attribute macro that calls new and cleanup for a test.
+    #[allow(dead_code)]
     pub async fn new(config_path: &str) -> Self {
-        let mut config = config::open(config_path).unwrap();
+        let config = config::open(config_path).unwrap();
+        Self::with_config(config).await
+    }
+
+    // Used for tests that need settings other than those in the test config.
+    #[allow(dead_code)]
+    pub async fn with_config(mut config: IConfig) -> Self {
         let test_db_id = Uuid::new_v4().to_string();
         config.mongodb.database = test_db_id.clone();
         let (app, _, _, handle) = server::build_server(&config).await;
@@ -95,7 +112,7 @@ impl Environment {
     }
 
     pub async fn inject_account(config: &IConfig, user: &User) {
-        let database = database::open(&config).await.unwrap();
+        let database = database::open(config).await.unwrap();
 
         let _user_coll = database
             .handle_with_started_transaction()
@@ -107,6 +124,231 @@ impl Environment {
     }
 }
 
+// Marks every queue item as confirmed so that /queue hands it out, as though
+// each profile had been resolved through /queue/resolve.
+#[allow(dead_code)]
+pub async fn confirm_queue(env: &Environment) {
+    let database = database::open(&env.config).await.unwrap();
+    database
+        .handle_with_started_transaction()
+        .await
+        .collection::<Document>("queue")
+        .update_many(doc! {}, doc! {"$set": {"confirmed_id": true}}, None)
+        .await
+        .unwrap();
+}
+
+// Sends a JSON request as the test user.
+#[allow(dead_code)]
+pub async fn call_json<T: serde::Serialize>(
+    env: &mut Environment,
+    method: Method,
+    uri: &str,
+    body: &T,
+) -> (StatusCode, hyper::body::Bytes) {
+    let key = env.user_key.clone();
+    call_json_as(env, method, uri, body, &key).await
+}
+
+// Sends a JSON request as the user with the key.
+#[allow(dead_code)]
+pub async fn call_json_as<T: serde::Serialize>(
+    env: &mut Environment,
+    method: Method,
+    uri: &str,
+    body: &T,
+    key: &str,
+) -> (StatusCode, hyper::body::Bytes) {
+    let body = serde_json::to_vec(body).unwrap();
+    call(env, method, uri, key, Some(body)).await
+}
+
+// Sends a request as the user with the key.
+#[allow(dead_code)]
+pub async fn call(
+    env: &mut Environment,
+    method: Method,
+    uri: &str,
+    key: &str,
+    body: Option<Vec<u8>>,
+) -> (StatusCode, hyper::body::Bytes) {
+    let (status, _, body) = fetch(env, method, uri, Some(key), body).await;
+    (status, body)
+}
+
+// Sends a request as the user with the key, or without a key if there is none,
+// and returns the status, content type and body of the response.
+#[allow(dead_code)]
+pub async fn fetch(
+    env: &mut Environment,
+    method: Method,
+    uri: &str,
+    key: Option<&str>,
+    body: Option<Vec<u8>>,
+) -> (StatusCode, String, hyper::body::Bytes) {
+    let mut req = Request::builder().method(method).uri(uri).header(
+        axum::http::header::CONTENT_TYPE,
+        mime::APPLICATION_JSON.as_ref(),
+    );
+    if let Some(key) = key {
+        req = req.header("X-API-KEY", key);
+    }
+    let res = env
+        .app
+        .call(
+            req.body(body.map(Body::from).unwrap_or_else(Body::empty))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = res.status();
+    let content_type = res
+        .headers()
+        .get(axum::http::header::CONTENT_TYPE)
+        .map(|v| v.to_str().unwrap().to_string())
+        .unwrap_or_default();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    (status, content_type, body)
+}
+
+// Leases a job for PLATFORM_1 from /queue as the test user, if there is one.
+#[allow(dead_code)]
+pub async fn take_job(env: &mut Environment) -> Option<QueueResponse> {
+    let key = env.user_key.clone();
+    take_job_as(env, &key).await
+}
+
+// Leases a job for PLATFORM_1 from /queue as the user with the key, if there
+// is one.
+#[allow(dead_code)]
+pub async fn take_job_as(
+    env: &mut Environment,
+    key: &str,
+) -> Option<QueueResponse> {
+    let (status, body) =
+        call(env, Method::GET, "/queue?platforms=PLATFORM_1", key, None).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).ok()
+}
+
+// Creates a subject as the test user with the IDs as its profiles on the
+// platform, or with no profiles if there are no IDs, and returns its UUID.
+#[allow(dead_code)]
+pub async fn create_subject(
+    env: &mut Environment,
+    name: &str,
+    platform: &str,
+    ids: &[&str],
+) -> String {
+    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    if !ids.is_empty() {
+        profiles.insert(
+            platform.to_string(),
+            ids.iter().map(|id| id.to_string()).collect(),
+        );
+    }
+    let req = CreateSubjectRequest {
+        name: name.to_string(),
+        profiles,
+        description: None,
+    };
+    let (status, body) =
+        call_json(env, Method::POST, "/subjects/create", &req).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let cr: CreateResponse = serde_json::from_slice(&body).unwrap();
+    cr.uuid
+}
+
+// Creates a subject as the test user with the IDs as its profiles on the
+// platform, adds the data through /add and returns the subject's UUID.
+#[allow(dead_code)]
+pub async fn create_subject_with_content(
+    env: &mut Environment,
+    name: &str,
+    platform: &str,
+    ids: &[&str],
+    data: Vec<Data>,
+) -> String {
+    let uuid = create_subject(env, name, platform, ids).await;
+    add_data(env, data).await;
+    uuid
+}
+
+// Adds the data through /add as the test user.
+#[allow(dead_code)]
+pub async fn add_data(env: &mut Environment, data: Vec<Data>) {
+    let datas = Datas {
+        queue_id: None,
+        data,
+    };
+    let (status, _) = call_json(env, Method::POST, "/add", &datas).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+// Adds mock content for the profile through /add as the test user and returns
+// its content ID.
+#[allow(dead_code)]
+pub async fn add_content(
+    env: &mut Environment,
+    id: &str,
+    platform: &str,
+) -> String {
+    let content = create_mock_content(id, platform);
+    let Data::Content { content_id, .. } = &content else {
+        unreachable!()
+    };
+    let content_id = content_id.clone();
+    add_data(env, vec![content]).await;
+    content_id
+}
+
+// Creates a group as the test user and returns its UUID.
+#[allow(dead_code)]
+pub async fn create_group(
+    env: &mut Environment,
+    name: &str,
+    subjects: &[&str],
+    groups: &[&str],
+) -> String {
+    let key = env.user_key.clone();
+    let (status, uuid) =
+        create_group_as(env, name, subjects, groups, &key).await;
+    assert_eq!(status, StatusCode::CREATED);
+    uuid.unwrap()
+}
+
+// Creates a group as the user with the key and returns its UUID if it was
+// created.
+#[allow(dead_code)]
+pub async fn create_group_as(
+    env: &mut Environment,
+    name: &str,
+    subjects: &[&str],
+    groups: &[&str],
+    key: &str,
+) -> (StatusCode, Option<String>) {
+    let req = CreateGroupRequest {
+        name: name.to_string(),
+        subjects: subjects.iter().map(|s| s.to_string()).collect(),
+        description: None,
+        groups: groups.iter().map(|g| g.to_string()).collect(),
+    };
+    let (status, body) =
+        call_json_as(env, Method::POST, "/groups/create", &req, key).await;
+    if status != StatusCode::CREATED {
+        return (status, None);
+    }
+    let cr: CreateResponse = serde_json::from_slice(&body).unwrap();
+    (status, Some(cr.uuid))
+}
+
+// Whether there is a job for PLATFORM_1 once every queue item is confirmed.
+#[allow(dead_code)]
+pub async fn queued(env: &mut Environment) -> bool {
+    confirm_queue(env).await;
+    take_job(env).await.is_some()
+}
+
 #[allow(dead_code)]
 pub fn create_mock_content(id: &str, platform: &str) -> Data {
     Data::Content {
@@ -0,0 +1,131 @@
+mod common;
+use axum::http::Method;
+use axum::http::StatusCode;
+use common::{call, confirm_queue, create_subject, take_job, Environment};
+use instrumentality::concepts::user::User;
+use instrumentality::routes::admin::queue::QueueItemRequest;
+use instrumentality::routes::response::{
+    PurgeResponse, QueueItemsResponse, StalenessResponse,
+};
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+const USERNAME: &str = "TEST_USER_1";
+
+/// admin_queue tests:
+/// - Administrators can list queue items and filter them by lock status.
+/// - Administrators can see the staleness of each platform in the queue.
+/// - Administrators can force-unlock a held queue item.
+/// - Administrators can set the priority of a queue item.
+/// - Requeued items are listed as stale.
+/// - Purging leaves queue items that subjects still refer to.
+/// - Non-administrators cannot use the admin queue routes.
+#[tokio::test]
+async fn admin_queue() {
+    let mut env = Environment::default().await;
+    let (admin, admin_key) = User::new_admin("admin");
+    Environment::inject_account(&env.config, &admin).await;
+    let user_key = env.user_key.clone();
+
+    create_subject(&mut env, USERNAME, PLATFORM_NAME, &[USERNAME]).await;
+
+    confirm_queue(&env).await;
+    let qr = take_job(&mut env).await.unwrap();
+
+    let (status, _) =
+        call(&mut env, Method::GET, "/admin/queue", &user_key, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = call(
+        &mut env,
+        Method::GET,
+        "/admin/queue?locked=true",
+        &admin_key,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let qir: QueueItemsResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(qir.items.len(), 1);
+    assert_eq!(qir.items[0].queue_id, qr.queue_id);
+    assert_eq!(qir.items[0].lock_holder, Some(env.user.uuid.clone()));
+
+    let (status, body) = call(
+        &mut env,
+        Method::GET,
+        "/admin/queue/staleness",
+        &admin_key,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let sr: StalenessResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(sr.platforms.len(), 1);
+    assert_eq!(sr.platforms[0].platform, PLATFORM_NAME);
+    assert_eq!(sr.platforms[0].items, 1);
+    assert_eq!(sr.platforms[0].locked, 1);
+
+    assert!(take_job(&mut env).await.is_none());
+    let req = QueueItemRequest {
+        queue_id: qr.queue_id.clone(),
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _) = call(
+        &mut env,
+        Method::POST,
+        "/admin/queue/unlock",
+        &admin_key,
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(take_job(&mut env).await.is_some());
+
+    let body = serde_json::to_vec(&serde_json::json!({
+        "queue_id": qr.queue_id,
+        "priority": 10
+    }))
+    .unwrap();
+    let (status, _) = call(
+        &mut env,
+        Method::POST,
+        "/admin/queue/priority",
+        &admin_key,
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) =
+        call(&mut env, Method::GET, "/admin/queue", &admin_key, None).await;
+    let qir: QueueItemsResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(qir.items[0].priority, 10);
+
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _) = call(
+        &mut env,
+        Method::POST,
+        "/admin/queue/requeue",
+        &admin_key,
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let uri = "/admin/queue?stale_secs=3600";
+    let (_, body) = call(&mut env, Method::GET, uri, &admin_key, None).await;
+    let qir: QueueItemsResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(qir.items.len(), 1);
+
+    let (status, body) = call(
+        &mut env,
+        Method::POST,
+        "/admin/queue/purge",
+        &admin_key,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let pr: PurgeResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(pr.purged, 0);
+
+    env.cleanup().await;
+}
@@ -0,0 +1,200 @@
+mod common;
+use axum::body::Body;
+use axum::http::Method;
+use axum::http::Request;
+use axum::http::StatusCode;
+use common::{call, create_subject, Environment};
+use tower::Service;
+
+use crate::common::create_mock_content;
+use crate::common::create_mock_presence;
+
+/// analytics tests:
+/// - Instrumentality serves analytics for a subject with content and presence.
+/// - Content and presence counts are bucketed per type.
+/// - Presence coverage is reported for the subject.
+#[tokio::test]
+async fn analytics() {
+    use std::collections::HashMap;
+
+    use instrumentality::concepts::data::Datas;
+    use instrumentality::routes::response::AnalyticsResponse;
+    use instrumentality::routes::response::LoginResponse;
+    use instrumentality::routes::subjects::create::CreateSubjectRequest;
+
+    const PLATFORM_NAME: &str = "PLATFORM_1";
+    const USERNAME: &str = "TEST_USER_1";
+
+    let mut env = Environment::default().await;
+
+    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    profiles.insert(PLATFORM_NAME.to_string(), vec![USERNAME.to_string()]);
+    let new_subject = CreateSubjectRequest {
+        name: USERNAME.to_string(),
+        profiles,
+        description: None,
+    };
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/subjects/create")
+                .header("X-API-KEY", &env.user_key)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    mime::APPLICATION_JSON.as_ref(),
+                )
+                .body(Body::from(serde_json::to_vec(&new_subject).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let datas = Datas {
+        queue_id: None,
+        data: vec![
+            create_mock_content(USERNAME, PLATFORM_NAME),
+            create_mock_content(USERNAME, PLATFORM_NAME),
+            create_mock_presence(USERNAME, PLATFORM_NAME),
+        ],
+    };
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::POST)
+                .header("X-API-KEY", &env.user_key)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    mime::APPLICATION_JSON.as_ref(),
+                )
+                .uri("/add")
+                .body(Body::from(serde_json::to_vec(&datas).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let lr: LoginResponse = env.login().await;
+    let uuid = lr.subjects[0].uuid.clone();
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri(format!("/analytics?subjects={}&bucket=hour", uuid))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let ar: AnalyticsResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(ar.response, "OK");
+    assert_eq!(ar.subjects.len(), 1);
+
+    let analytics = &ar.subjects[0].analytics;
+    assert_eq!(analytics.content.len(), 1);
+    assert_eq!(analytics.content[0].label, "story");
+    let content_count: u64 =
+        analytics.content[0].points.iter().map(|p| p.count).sum();
+    assert_eq!(content_count, 2);
+    assert_eq!(analytics.presence.len(), 1);
+    assert!(analytics.presence_coverage > 0.0);
+    assert_eq!(ar.subjects[0].profiles.len(), 1);
+
+    env.cleanup().await;
+}
+
+/// analytics_no_selection tests:
+/// - Instrumentality serves an error response to requests to /analytics with no
+///   subjects or groups.
+#[tokio::test]
+async fn analytics_no_selection() {
+    use instrumentality::routes::response::ErrorResponse;
+
+    let mut env = Environment::default().await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/analytics")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let er: ErrorResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(er.response, "ERROR");
+
+    env.cleanup().await;
+}
+
+/// analytics_invisible_subjects tests:
+/// - Instrumentality serves a not found response to requests to /analytics for
+///   another user's subject or a group that doesn't exist, naming both.
+#[tokio::test]
+async fn analytics_invisible_subjects() {
+    use instrumentality::concepts::user::User;
+    use instrumentality::routes::response::ErrorResponse;
+
+    let mut env = Environment::default().await;
+
+    let uuid = create_subject(&mut env, "subject", "PLATFORM_1", &[]).await;
+    let (other_user, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other_user).await;
+
+    let uri = format!("/analytics?subjects={uuid}&groups=missing");
+    let (status, body) =
+        call(&mut env, Method::GET, &uri, &other_key, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let er: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert!(er.text.contains(&uuid));
+    assert!(er.text.contains("missing"));
+
+    env.cleanup().await;
+}
+
+/// analytics_too_many_buckets tests:
+/// - Instrumentality serves an error response to requests to /analytics for a
+///   range with more buckets than it will compute.
+#[tokio::test]
+async fn analytics_too_many_buckets() {
+    use chrono::{Duration, SecondsFormat, Utc};
+
+    let mut env = Environment::default().await;
+
+    let uuid = create_subject(&mut env, "subject", "PLATFORM_1", &[]).await;
+    let key = env.user_key.clone();
+    let from = (Utc::now() - Duration::days(365))
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+    let uri = format!("/analytics?subjects={uuid}&bucket=hour&from={from}");
+    let (status, _) = call(&mut env, Method::GET, &uri, &key, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let uri = format!("/analytics?subjects={uuid}&bucket=day&from={from}");
+    let (status, _) = call(&mut env, Method::GET, &uri, &key, None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    env.cleanup().await;
+}
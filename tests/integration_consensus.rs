@@ -0,0 +1,183 @@
+mod common;
+use axum::body::Body;
+use axum::http::Method;
+use axum::http::Request;
+use axum::http::StatusCode;
+use axum::Router;
+use common::{confirm_queue, take_job_as, Environment};
+use instrumentality::concepts::data::{Data, Datas};
+use tower::Service;
+
+use crate::common::create_mock_content;
+use crate::common::TEST_ENVIRONMENT_CONFIG;
+
+async fn submit(app: &mut Router, key: &str, queue_id: &str, data: &Data) {
+    let datas = Datas {
+        queue_id: Some(queue_id.to_string()),
+        data: vec![data.clone()],
+    };
+
+    let res = app
+        .call(
+            Request::builder()
+                .method(Method::POST)
+                .header("X-API-KEY", key)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    mime::APPLICATION_JSON.as_ref(),
+                )
+                .uri("/add")
+                .body(Body::from(serde_json::to_vec(&datas).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+}
+
+async fn content_count(env: &mut Environment, uuid: &str) -> usize {
+    use instrumentality::routes::response::ViewResponse;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri(format!("/view?subjects={}", uuid))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let vr: ViewResponse = serde_json::from_slice(&body).unwrap();
+    vr.view_data.subject_data[0].platforms[0].profiles[0]
+        .content
+        .len()
+}
+
+/// consensus tests:
+/// - With consensus enabled, data submitted for a queue job is held back until
+///   enough providers have done the job.
+/// - A provider is not given a job it has already done this round.
+/// - Data agreed on by a quorum of providers is committed.
+#[tokio::test]
+async fn consensus() {
+    use std::collections::HashMap;
+
+    use instrumentality::concepts::user::User;
+    use instrumentality::config;
+    use instrumentality::routes::response::LoginResponse;
+    use instrumentality::routes::subjects::create::CreateSubjectRequest;
+
+    const PLATFORM_NAME: &str = "PLATFORM_1";
+    const USERNAME: &str = "TEST_USER_1";
+
+    let mut config = config::open(TEST_ENVIRONMENT_CONFIG).unwrap();
+    config.consensus.enabled = true;
+    config.consensus.providers = 2;
+    config.consensus.quorum = 2;
+    let mut env = Environment::with_config(config).await;
+
+    let (other_user, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other_user).await;
+
+    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    profiles.insert(PLATFORM_NAME.to_string(), vec![USERNAME.to_string()]);
+    let new_subject = CreateSubjectRequest {
+        name: USERNAME.to_string(),
+        profiles,
+        description: None,
+    };
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/subjects/create")
+                .header("X-API-KEY", &env.user_key)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    mime::APPLICATION_JSON.as_ref(),
+                )
+                .body(Body::from(serde_json::to_vec(&new_subject).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let lr: LoginResponse = env.login().await;
+    let uuid = lr.subjects[0].uuid.clone();
+    let content = create_mock_content(USERNAME, PLATFORM_NAME);
+    let key = env.user_key.clone();
+
+    confirm_queue(&env).await;
+    let job = take_job_as(&mut env, &key).await.unwrap();
+    submit(&mut env.app, &key, &job.queue_id, &content).await;
+
+    assert_eq!(content_count(&mut env, &uuid).await, 0);
+    assert!(take_job_as(&mut env, &key).await.is_none());
+
+    let other_job = take_job_as(&mut env, &other_key).await.unwrap();
+    assert_eq!(other_job.queue_id, job.queue_id);
+    submit(&mut env.app, &other_key, &other_job.queue_id, &content).await;
+
+    assert_eq!(content_count(&mut env, &uuid).await, 1);
+
+    env.cleanup().await;
+}
+
+/// consensus_requires_queue_job tests:
+/// - With consensus enabled, data submitted without a queue job is refused
+///   unless it comes from an admin.
+#[tokio::test]
+async fn consensus_requires_queue_job() {
+    use instrumentality::concepts::user::User;
+    use instrumentality::config;
+
+    let mut config = config::open(TEST_ENVIRONMENT_CONFIG).unwrap();
+    config.consensus.enabled = true;
+    let mut env = Environment::with_config(config).await;
+
+    let (admin, admin_key) = User::new_admin("admin");
+    Environment::inject_account(&env.config, &admin).await;
+
+    let datas = Datas {
+        queue_id: None,
+        data: vec![create_mock_content("TEST_USER_1", "PLATFORM_1")],
+    };
+    let body = serde_json::to_vec(&datas).unwrap();
+    for (key, status) in [
+        (env.user_key.clone(), StatusCode::FORBIDDEN),
+        (admin_key, StatusCode::CREATED),
+    ] {
+        let res = env
+            .app
+            .call(
+                Request::builder()
+                    .method(Method::POST)
+                    .header("X-API-KEY", &key)
+                    .header(
+                        axum::http::header::CONTENT_TYPE,
+                        mime::APPLICATION_JSON.as_ref(),
+                    )
+                    .uri("/add")
+                    .body(Body::from(body.clone()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), status);
+    }
+
+    env.cleanup().await;
+}
@@ -0,0 +1,149 @@
+mod common;
+use axum::http::Method;
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use common::{create_subject, fetch, Environment};
+use instrumentality::concepts::data::{Data, Datas};
+use instrumentality::concepts::user::User;
+use instrumentality::routes::feeds::{
+    CreateFeedTokenRequest, RevokeFeedTokenRequest,
+};
+use instrumentality::routes::response::FeedTokenResponse;
+
+use crate::common::create_mock_content;
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+const USER_ID: &str = "123456789";
+
+fn meta(display_name: &str, mins_ago: i64) -> Data {
+    Data::Meta {
+        id: USER_ID.to_string(),
+        platform: PLATFORM_NAME.to_string(),
+        username: "TEST_USER_1".to_string(),
+        private: false,
+        suspended_or_banned: false,
+        retrieved_at: Utc::now() - Duration::minutes(mins_ago),
+        display_name: Some(display_name.to_string()),
+        profile_picture: None,
+        bio: None,
+        verified: None,
+        references: None,
+        link: None,
+        added_by: None,
+        added_at: None,
+    }
+}
+
+async fn create_token(
+    env: &mut Environment,
+    subject: &str,
+    key: &str,
+) -> (StatusCode, Option<String>) {
+    let req = CreateFeedTokenRequest {
+        subject: subject.to_string(),
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _, body) =
+        fetch(env, Method::POST, "/feeds/tokens", Some(key), Some(body)).await;
+    let token = serde_json::from_slice::<FeedTokenResponse>(&body)
+        .ok()
+        .map(|r| r.token);
+    (status, token)
+}
+
+/// feeds tests:
+/// - A user can create a feed token for a subject they can see, but not for one
+///   they can't.
+/// - The subject's feed is served as Atom and RSS with the token, with entries
+///   for its content and for changes to its profiles' metadata.
+/// - The feed can't be read without a valid token for the subject.
+/// - Revoking the token stops the feed working.
+#[tokio::test]
+async fn feeds() {
+    let mut env = Environment::default().await;
+    let key = env.user_key.clone();
+    let (other, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other).await;
+
+    let uuid =
+        create_subject(&mut env, "subject", PLATFORM_NAME, &[USER_ID]).await;
+
+    let mut content = create_mock_content(USER_ID, PLATFORM_NAME);
+    if let Data::Content {
+        body,
+        retrieved_from,
+        media,
+        ..
+    } = &mut content
+    {
+        *body = Some("A post.".to_string());
+        *retrieved_from = Some("https://example.com/post".to_string());
+        *media = Some(vec!["https://example.com/1.jpg".to_string()]);
+    }
+    let datas = Datas {
+        queue_id: None,
+        data: vec![meta("Old Name", 10), meta("New Name", 5), content],
+    };
+    let body = serde_json::to_vec(&datas).unwrap();
+    let (status, _, _) =
+        fetch(&mut env, Method::POST, "/add", Some(&key), Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, _) = create_token(&mut env, &uuid, &other_key).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, token) = create_token(&mut env, &uuid, &key).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let token = token.unwrap();
+
+    for (extension, content_type) in [
+        ("atom", "application/atom+xml"),
+        ("rss", "application/rss+xml"),
+    ] {
+        let uri = format!("/feeds/{uuid}.{extension}?token={token}");
+        let (status, ct, body) =
+            fetch(&mut env, Method::GET, &uri, None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(ct.starts_with(content_type));
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("A post."));
+        assert!(body.contains("https://example.com/post"));
+        assert!(body.contains("https://example.com/1.jpg"));
+        assert!(body.contains("display name: Old Name -&gt; New Name"));
+    }
+
+    let uri = format!("/feeds/{uuid}.atom");
+    let (status, _, _) = fetch(&mut env, Method::GET, &uri, None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let uri = format!("/feeds/{uuid}.atom?token=WRONG");
+    let (status, _, _) = fetch(&mut env, Method::GET, &uri, None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let req = RevokeFeedTokenRequest {
+        token: token.clone(),
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _, _) = fetch(
+        &mut env,
+        Method::DELETE,
+        "/feeds/tokens",
+        Some(&other_key),
+        Some(body.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, _) = fetch(
+        &mut env,
+        Method::DELETE,
+        "/feeds/tokens",
+        Some(&key),
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let uri = format!("/feeds/{uuid}.atom?token={token}");
+    let (status, _, _) = fetch(&mut env, Method::GET, &uri, None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    env.cleanup().await;
+}
@@ -0,0 +1,210 @@
+mod common;
+use std::collections::HashMap;
+
+use axum::body::Body;
+use axum::http::Method;
+use axum::http::Request;
+use axum::http::StatusCode;
+use chrono::Utc;
+use common::{create_subject, Environment};
+use instrumentality::concepts::data::Data;
+use instrumentality::concepts::data::Datas;
+use tower::Service;
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+const USERNAME_1: &str = "TEST_USER_1";
+const USERNAME_2: &str = "TEST_USER_2";
+
+async fn add_mention(
+    env: &mut Environment,
+    from: &str,
+    to: &str,
+    content_id: &str,
+) {
+    let mut references = HashMap::new();
+    references.insert("mention.0".to_string(), to.to_string());
+    let datas = Datas {
+        queue_id: None,
+        data: vec![Data::Content {
+            id: from.to_string(),
+            platform: PLATFORM_NAME.to_string(),
+            content_type: "post".to_string(),
+            retrieved_at: Utc::now(),
+            content_id: content_id.to_string(),
+            deleted: Some(false),
+            retrieved_from: None,
+            created_at: None,
+            body: None,
+            media: None,
+            references: Some(references),
+            added_by: None,
+            added_at: None,
+        }],
+    };
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::POST)
+                .header("X-API-KEY", &env.user_key)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    mime::APPLICATION_JSON.as_ref(),
+                )
+                .uri("/add")
+                .body(Body::from(serde_json::to_vec(&datas).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+}
+
+/// graph_neighbours tests:
+/// - Content with a reference creates an edge from its profile to the
+///   referenced profile.
+/// - References from different content increment the edge count, but submitting
+///   the same content again does not.
+/// - The edge is listed as outgoing for the referencing subject and incoming
+///   for the referenced profile.
+#[tokio::test]
+async fn graph_neighbours() {
+    use instrumentality::routes::response::NeighboursResponse;
+
+    let mut env = Environment::default().await;
+
+    let uuid =
+        create_subject(&mut env, USERNAME_1, PLATFORM_NAME, &[USERNAME_1])
+            .await;
+    create_subject(&mut env, USERNAME_2, PLATFORM_NAME, &[USERNAME_2]).await;
+    add_mention(&mut env, USERNAME_1, USERNAME_2, "1").await;
+    add_mention(&mut env, USERNAME_1, USERNAME_2, "2").await;
+    add_mention(&mut env, USERNAME_1, USERNAME_2, "1").await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri(format!("/graph/neighbours?subject={}", uuid))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let nr: NeighboursResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(nr.response, "OK");
+    assert_eq!(nr.outgoing.len(), 1);
+    assert_eq!(nr.outgoing[0].to.id, USERNAME_2);
+    assert_eq!(nr.outgoing[0].kind, "mention");
+    assert_eq!(nr.outgoing[0].count, 2);
+    assert!(nr.incoming.is_empty());
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri(format!(
+                    "/graph/neighbours?platform={}&id={}",
+                    PLATFORM_NAME, USERNAME_2
+                ))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let nr: NeighboursResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(nr.incoming.len(), 1);
+    assert_eq!(nr.incoming[0].from.id, USERNAME_1);
+
+    env.cleanup().await;
+}
+
+/// graph_path tests:
+/// - A path is found between two subjects connected by a reference.
+#[tokio::test]
+async fn graph_path() {
+    use instrumentality::routes::response::PathResponse;
+
+    let mut env = Environment::default().await;
+
+    let from =
+        create_subject(&mut env, USERNAME_1, PLATFORM_NAME, &[USERNAME_1])
+            .await;
+    let to = create_subject(&mut env, USERNAME_2, PLATFORM_NAME, &[USERNAME_2])
+        .await;
+    add_mention(&mut env, USERNAME_2, USERNAME_1, "1").await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri(format!("/graph/path?from={}&to={}", from, to))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let pr: PathResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(pr.response, "OK");
+    assert_eq!(pr.path.len(), 2);
+    assert_eq!(pr.path[0].id, USERNAME_1);
+    assert_eq!(pr.path[1].id, USERNAME_2);
+    assert_eq!(pr.edges.len(), 1);
+
+    env.cleanup().await;
+}
+
+/// graph_neighbours_no_arguments tests:
+/// - Instrumentality serves an error response to requests to /graph/neighbours
+///   with neither a subject nor a profile.
+#[tokio::test]
+async fn graph_neighbours_no_arguments() {
+    use instrumentality::routes::response::ErrorResponse;
+
+    let mut env = Environment::default().await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/graph/neighbours")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let er: ErrorResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(er.response, "ERROR");
+
+    env.cleanup().await;
+}
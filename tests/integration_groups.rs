@@ -3,7 +3,7 @@ use axum::body::Body;
 use axum::http::Method;
 use axum::http::Request;
 use axum::http::StatusCode;
-use common::Environment;
+use common::{call_json, Environment};
 use tower::Service;
 
 /// group_creation tests:
@@ -76,6 +76,7 @@ async fn group_creation() {
         name: "test".to_string(),
         subjects,
         description: None,
+        groups: Vec::new(),
     };
 
     let res = env
@@ -179,6 +180,7 @@ async fn group_bad_key_creation() {
         name: "test".to_string(),
         subjects,
         description: None,
+        groups: Vec::new(),
     };
 
     let res = env
@@ -285,6 +287,7 @@ async fn group_deletion() {
         name: "test".to_string(),
         subjects,
         description: None,
+        groups: Vec::new(),
     };
 
     let res = env
@@ -428,6 +431,7 @@ async fn group_subject_deletion() {
         name: "test".to_string(),
         subjects,
         description: None,
+        groups: Vec::new(),
     };
 
     let res = env
@@ -565,6 +569,7 @@ async fn group_update() {
         name: "testers".to_string(),
         subjects,
         description: None,
+        groups: Vec::new(),
     };
 
     let res = env
@@ -657,6 +662,8 @@ async fn group_update() {
                         name: "testers".to_string(),
                         subjects,
                         description: Some("The testers.".to_string()),
+                        groups: Vec::new(),
+                        version: None,
                     })
                     .unwrap(),
                 ))
@@ -747,6 +754,7 @@ async fn group_bad_uuid_creation() {
         name: "test".to_string(),
         subjects,
         description: None,
+        groups: Vec::new(),
     };
 
     let res = env
@@ -854,6 +862,7 @@ async fn group_bad_uuid_update() {
         name: "testers".to_string(),
         subjects,
         description: None,
+        groups: Vec::new(),
     };
 
     let res = env
@@ -946,6 +955,8 @@ async fn group_bad_uuid_update() {
                         name: "testers".to_string(),
                         subjects,
                         description: Some("The testers.".to_string()),
+                        groups: Vec::new(),
+                        version: None,
                     })
                     .unwrap(),
                 ))
@@ -966,3 +977,98 @@ async fn group_bad_uuid_update() {
 
     env.cleanup().await;
 }
+
+/// nested_groups tests:
+/// - Groups can be nested in other groups, and /view expands a group into the
+///   subjects of every group nested in it.
+/// - A group can't be nested in itself, directly or through other groups.
+/// - Deleting a subject removes it from groups at every level.
+/// - Deleting a group removes it from the groups it is nested in.
+#[tokio::test]
+async fn nested_groups() {
+    use std::collections::HashMap;
+
+    use instrumentality::routes::groups::create::CreateGroupRequest;
+    use instrumentality::routes::groups::delete::DeleteGroupRequest;
+    use instrumentality::routes::groups::update::UpdateGroupRequest;
+    use instrumentality::routes::response::CreateResponse;
+    use instrumentality::routes::response::ViewResponse;
+    use instrumentality::routes::subjects::create::CreateSubjectRequest;
+    use instrumentality::routes::subjects::delete::DeleteSubjectRequest;
+
+    let mut env: Environment = Environment::default().await;
+
+    let mut subjects = Vec::new();
+    for name in ["ceo", "engineer", "intern"] {
+        let req = CreateSubjectRequest {
+            name: name.to_string(),
+            profiles: HashMap::new(),
+            description: None,
+        };
+        let (status, body) =
+            call_json(&mut env, Method::POST, "/subjects/create", &req).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let cr: CreateResponse = serde_json::from_slice(&body).unwrap();
+        subjects.push(cr.uuid);
+    }
+
+    let mut groups: Vec<String> = Vec::new();
+    for (name, members) in [
+        ("team", vec![subjects[2].clone()]),
+        ("engineering", vec![subjects[1].clone()]),
+        ("company", vec![subjects[0].clone()]),
+    ] {
+        let req = CreateGroupRequest {
+            name: name.to_string(),
+            subjects: members,
+            description: None,
+            groups: groups.last().cloned().into_iter().collect(),
+        };
+        let (status, body) =
+            call_json(&mut env, Method::POST, "/groups/create", &req).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let cr: CreateResponse = serde_json::from_slice(&body).unwrap();
+        groups.push(cr.uuid);
+    }
+
+    let uri = format!("/view?groups={}", groups[2]);
+    let (status, body) = call_json(&mut env, Method::GET, &uri, &()).await;
+    assert_eq!(status, StatusCode::OK);
+    let vr: ViewResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(vr.view_data.subject_data.len(), 3);
+
+    for nested in [groups[2].clone(), groups[0].clone()] {
+        let req = UpdateGroupRequest {
+            uuid: groups[0].clone(),
+            name: "team".to_string(),
+            subjects: vec![subjects[2].clone()],
+            description: None,
+            groups: vec![nested],
+            version: None,
+        };
+        let (status, _) =
+            call_json(&mut env, Method::POST, "/groups/update", &req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    let req = DeleteSubjectRequest {
+        uuid: subjects[2].clone(),
+    };
+    let (status, _) =
+        call_json(&mut env, Method::DELETE, "/subjects/delete", &req).await;
+    assert_eq!(status, StatusCode::OK);
+    let req = DeleteGroupRequest {
+        uuid: groups[1].clone(),
+    };
+    let (status, _) =
+        call_json(&mut env, Method::DELETE, "/groups/delete", &req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let lr = env.login().await;
+    let team = lr.groups.iter().find(|g| g.uuid == groups[0]).unwrap();
+    assert!(team.subjects.is_empty());
+    let company = lr.groups.iter().find(|g| g.uuid == groups[2]).unwrap();
+    assert!(company.groups.is_empty());
+
+    env.cleanup().await;
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::Method;
+use axum::http::Request;
+use axum::http::StatusCode;
+use common::{confirm_queue, Environment};
+use instrumentality::routes::response::{ImportResponse, QueueResponse};
+use tower::Service;
+
+async fn import(
+    env: &mut Environment,
+    query: &str,
+    body: &str,
+) -> (StatusCode, ImportResponse) {
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::POST)
+                .header("X-API-KEY", &env.user_key)
+                .uri(format!("/subjects/import?{query}"))
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = res.status();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+const CSV: &str = "name,groups,PLATFORM_1,BADPLATFORM\n\
+                   Alice,Team,alice,\n\
+                   Bob,,bob,bob\n";
+
+/// import tests:
+/// - A transactional import with an invalid row imports nothing and reports the
+///   row.
+/// - A best effort import imports the valid rows and reports the rest.
+/// - Subjects are put in their named groups, which are created if missing.
+/// - The profiles of imported subjects are queued.
+#[tokio::test]
+async fn import_csv() {
+    let mut env = Environment::default().await;
+
+    let (status, ir) = import(&mut env, "format=csv", CSV).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(ir.created.is_empty());
+    assert_eq!(ir.errors.len(), 1);
+    assert_eq!(ir.errors[0].row, 2);
+    assert!(env.login().await.subjects.is_empty());
+
+    let (status, ir) =
+        import(&mut env, "format=csv&mode=best_effort", CSV).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ir.created.len(), 1);
+    assert_eq!(ir.created[0].name, "Alice");
+    assert_eq!(ir.errors.len(), 1);
+
+    let lr = env.login().await;
+    assert_eq!(lr.subjects.len(), 1);
+    assert_eq!(lr.groups.len(), 1);
+    assert_eq!(lr.groups[0].name, "Team");
+    assert_eq!(lr.groups[0].subjects, vec![ir.created[0].uuid.clone()]);
+
+    confirm_queue(&env).await;
+    let key = env.user_key.clone();
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &key)
+                .uri("/queue?platforms=PLATFORM_1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let qr: QueueResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(qr.platform_id, "alice");
+
+    env.cleanup().await;
+}
+
+/// JSON imports refuse names that already exist or repeat within the import.
+#[tokio::test]
+async fn import_json() {
+    let mut env = Environment::default().await;
+
+    let json = r#"[
+        {"name": "Alice", "profiles": {"PLATFORM_1": ["alice"]}},
+        {"name": "Alice", "profiles": {"PLATFORM_1": ["alice_alt"]}}
+    ]"#;
+    let (status, ir) =
+        import(&mut env, "format=json&mode=best_effort", json).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ir.created.len(), 1);
+    assert_eq!(ir.errors[0].row, 2);
+
+    let (status, ir) = import(&mut env, "format=json", json).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(ir.errors.len(), 2);
+
+    env.cleanup().await;
+}
@@ -0,0 +1,155 @@
+mod common;
+use std::time::Duration;
+
+use axum::body::{Body, BoxBody, HttpBody};
+use axum::http::Method;
+use axum::http::Request;
+use axum::http::StatusCode;
+use common::{add_content, call, create_subject, Environment};
+use tower::Service;
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+const USERNAME: &str = "TEST_USER_1";
+
+// Reads the body until a complete event has been received, returning the
+// event's ID and data.
+async fn next_event(body: &mut BoxBody) -> (String, String) {
+    let mut buffer = String::new();
+    loop {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), body.data())
+            .await
+            .expect("Timed out waiting for an event.")
+            .unwrap()
+            .unwrap();
+        buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        if buffer.contains("\n\n") && buffer.contains("data:") {
+            break;
+        }
+    }
+
+    let field = |name: &str| {
+        buffer
+            .lines()
+            .find_map(|l| l.strip_prefix(name))
+            .unwrap()
+            .trim()
+            .to_string()
+    };
+    (field("id:"), field("data:"))
+}
+
+/// view_live tests:
+/// - Data added for a subscribed subject is pushed to the subscriber.
+/// - A subscriber reconnecting with the last event ID it received is sent the
+///   data it missed.
+#[tokio::test]
+async fn view_live() {
+    let mut env = Environment::default().await;
+
+    let uuid =
+        create_subject(&mut env, USERNAME, PLATFORM_NAME, &[USERNAME]).await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri(format!("/view/live?subjects={}", uuid))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let mut body = res.into_body();
+
+    add_content(&mut env, USERNAME, PLATFORM_NAME).await;
+
+    let (first_id, data) = next_event(&mut body).await;
+    assert!(data.contains(USERNAME));
+    assert!(data.contains("story"));
+    drop(body);
+
+    add_content(&mut env, USERNAME, PLATFORM_NAME).await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .header("Last-Event-ID", &first_id)
+                .uri(format!("/view/live?subjects={}", uuid))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    let mut body = res.into_body();
+
+    let (second_id, data) = next_event(&mut body).await;
+    assert_ne!(first_id, second_id);
+    assert!(data.contains(USERNAME));
+
+    env.cleanup().await;
+}
+
+/// view_live_no_selection tests:
+/// - Instrumentality serves an error response to requests to /view/live with no
+///   subjects or groups.
+#[tokio::test]
+async fn view_live_no_selection() {
+    use instrumentality::routes::response::ErrorResponse;
+
+    let mut env = Environment::default().await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/view/live")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let er: ErrorResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(er.response, "ERROR");
+
+    env.cleanup().await;
+}
+
+/// view_live_invisible_subjects tests:
+/// - Instrumentality serves a not found response to requests to /view/live for
+///   another user's subject.
+#[tokio::test]
+async fn view_live_invisible_subjects() {
+    use instrumentality::concepts::user::User;
+    use instrumentality::routes::response::ErrorResponse;
+
+    let mut env = Environment::default().await;
+
+    let uuid = create_subject(&mut env, "subject", PLATFORM_NAME, &[]).await;
+    let (other_user, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other_user).await;
+
+    let uri = format!("/view/live?subjects={uuid}");
+    let (status, body) =
+        call(&mut env, Method::GET, &uri, &other_key, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let er: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert!(er.text.contains(&uuid));
+
+    env.cleanup().await;
+}
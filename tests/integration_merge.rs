@@ -0,0 +1,146 @@
+mod common;
+use std::collections::HashMap;
+
+use axum::http::Method;
+use axum::http::StatusCode;
+use common::{
+    call_json, call_json_as, create_group, create_subject, queued, Environment,
+};
+use instrumentality::concepts::acl::{Role, Visibility};
+use instrumentality::concepts::user::User;
+use instrumentality::routes::response::CreateResponse;
+use instrumentality::routes::subjects::delete::DeleteSubjectRequest;
+use instrumentality::routes::subjects::merge::MergeSubjectsRequest;
+use instrumentality::routes::subjects::share::{
+    ShareSubjectRequest, SubjectVisibilityRequest,
+};
+use instrumentality::routes::subjects::split::SplitSubjectRequest;
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+
+fn profiles(ids: &[&str]) -> HashMap<String, Vec<String>> {
+    let ids = ids.iter().map(|id| id.to_string()).collect();
+    HashMap::from([(PLATFORM_NAME.to_string(), ids)])
+}
+
+/// merge tests:
+/// - Merging a subject into another unions their profiles and deletes it.
+/// - Groups the merged subject was in contain the subject it was merged into.
+/// - Profiles the subjects had in common are referenced by the queue once, so
+///   deleting the merged subject leaves nothing queued.
+/// - A subject can't be merged into itself.
+/// - A subject listed twice is merged once.
+/// - Groups the subjects were in get a new version.
+#[tokio::test]
+async fn merge_subjects() {
+    let mut env = Environment::default().await;
+
+    let first =
+        create_subject(&mut env, "first", PLATFORM_NAME, &["ID_1", "ID_2"])
+            .await;
+    let second =
+        create_subject(&mut env, "second", PLATFORM_NAME, &["ID_2", "ID_3"])
+            .await;
+    create_group(&mut env, "group", &[&second], &[]).await;
+
+    let req = MergeSubjectsRequest {
+        uuid: first.clone(),
+        subjects: vec![first.clone()],
+        version: None,
+    };
+    let (status, _) =
+        call_json(&mut env, Method::POST, "/subjects/merge", &req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let req = MergeSubjectsRequest {
+        uuid: first.clone(),
+        subjects: vec![second.clone(), second.clone()],
+        version: Some(0),
+    };
+    let (status, _) =
+        call_json(&mut env, Method::POST, "/subjects/merge", &req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let lr = env.login().await;
+    assert_eq!(lr.subjects.len(), 1);
+    assert_eq!(lr.subjects[0].uuid, first);
+    assert_eq!(lr.subjects[0].profiles, profiles(&["ID_1", "ID_2", "ID_3"]));
+    assert_eq!(lr.groups[0].subjects, vec![first.clone()]);
+    assert!(lr.groups[0].version > 0);
+
+    let req = DeleteSubjectRequest { uuid: first };
+    let (status, _) =
+        call_json(&mut env, Method::DELETE, "/subjects/delete", &req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!queued(&mut env).await);
+
+    env.cleanup().await;
+}
+
+/// split tests:
+/// - Splitting moves the given profiles into a new subject.
+/// - Only profiles of the subject can be moved.
+/// - The new subject has the owner, sharing and visibility of the subject and
+///   is in the same groups, even when an editor splits it.
+#[tokio::test]
+async fn split_subject() {
+    let mut env = Environment::default().await;
+    let (editor, editor_key) = User::new("editor");
+    Environment::inject_account(&env.config, &editor).await;
+
+    let uuid =
+        create_subject(&mut env, "mixed", PLATFORM_NAME, &["ID_1", "ID_2"])
+            .await;
+    let req = ShareSubjectRequest {
+        uuid: uuid.clone(),
+        user: editor.uuid.clone(),
+        role: Role::Editor,
+    };
+    let (status, _) =
+        call_json(&mut env, Method::POST, "/subjects/share", &req).await;
+    assert_eq!(status, StatusCode::OK);
+    let req = SubjectVisibilityRequest {
+        uuid: uuid.clone(),
+        visibility: Visibility::Public,
+    };
+    let (status, _) =
+        call_json(&mut env, Method::POST, "/subjects/visibility", &req).await;
+    assert_eq!(status, StatusCode::OK);
+    create_group(&mut env, "group", &[&uuid], &[]).await;
+
+    let mut req = SplitSubjectRequest {
+        uuid: uuid.clone(),
+        profiles: profiles(&["ID_3"]),
+        name: "other".to_string(),
+        description: None,
+        version: None,
+    };
+    let (status, _) =
+        call_json(&mut env, Method::POST, "/subjects/split", &req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    req.profiles = profiles(&["ID_2"]);
+    let (status, body) = call_json_as(
+        &mut env,
+        Method::POST,
+        "/subjects/split",
+        &req,
+        &editor_key,
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let cr: CreateResponse = serde_json::from_slice(&body).unwrap();
+
+    let lr = env.login().await;
+    let mixed = lr.subjects.iter().find(|s| s.uuid == uuid).unwrap();
+    assert_eq!(mixed.profiles, profiles(&["ID_1"]));
+    let other = lr.subjects.iter().find(|s| s.uuid == cr.uuid).unwrap();
+    assert_eq!(other.name, "other");
+    assert_eq!(other.profiles, profiles(&["ID_2"]));
+    assert_eq!(other.created_by, env.user.uuid);
+    assert_eq!(other.editors, vec![editor.uuid.clone()]);
+    assert_eq!(other.visibility, Visibility::Public);
+    assert_eq!(lr.groups[0].subjects, vec![uuid, cr.uuid]);
+
+    env.cleanup().await;
+}
@@ -0,0 +1,218 @@
+mod common;
+use std::collections::HashMap;
+
+use axum::body::Body;
+use axum::http::Method;
+use axum::http::Request;
+use axum::http::StatusCode;
+use common::{call_json, create_subject, Environment};
+use instrumentality::routes::groups::create::CreateGroupRequest;
+use instrumentality::routes::groups::update::PatchGroupRequest;
+use instrumentality::routes::response::{CreateResponse, VersionResponse};
+use instrumentality::routes::subjects::update::{
+    PatchSubjectRequest, UpdateSubjectRequest,
+};
+use tower::Service;
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+
+fn profile(id: &str) -> HashMap<String, Vec<String>> {
+    HashMap::from([(PLATFORM_NAME.to_string(), vec![id.to_string()])])
+}
+
+// Sends the PATCHes at the same time and returns their statuses.
+async fn concurrent_patches<T: serde::Serialize>(
+    env: &Environment,
+    uri: &str,
+    reqs: &[T],
+) -> Vec<StatusCode> {
+    let calls = reqs.iter().map(|req| {
+        let mut app = env.app.clone();
+        let request = Request::builder()
+            .method(Method::PATCH)
+            .header("X-API-KEY", &env.user_key)
+            .header(
+                axum::http::header::CONTENT_TYPE,
+                mime::APPLICATION_JSON.as_ref(),
+            )
+            .uri(uri)
+            .body(Body::from(serde_json::to_vec(req).unwrap()))
+            .unwrap();
+        async move { app.call(request).await.unwrap().status() }
+    });
+    futures_util::future::join_all(calls).await
+}
+
+/// subject patch tests:
+/// - Profiles can be added and removed individually and the subject renamed
+///   without restating the rest of it.
+/// - Every change increments the version.
+/// - Changes made against an older version are refused with 409.
+/// - An empty description removes the description.
+#[tokio::test]
+async fn patch_subject() {
+    let mut env = Environment::default().await;
+    let uuid =
+        create_subject(&mut env, "subject", PLATFORM_NAME, &["ID_1"]).await;
+
+    let req = PatchSubjectRequest {
+        uuid: uuid.clone(),
+        version: Some(0),
+        description: Some("A subject.".to_string()),
+        add_profiles: profile("ID_2"),
+        ..Default::default()
+    };
+    let (status, body) =
+        call_json(&mut env, Method::PATCH, "/subjects/update", &req).await;
+    assert_eq!(status, StatusCode::OK);
+    let vr: VersionResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(vr.version, 1);
+
+    let (status, _) =
+        call_json(&mut env, Method::PATCH, "/subjects/update", &req).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let req = PatchSubjectRequest {
+        uuid: uuid.clone(),
+        version: Some(1),
+        name: Some("renamed".to_string()),
+        description: Some(String::new()),
+        remove_profiles: profile("ID_1"),
+        ..Default::default()
+    };
+    let (status, _) =
+        call_json(&mut env, Method::PATCH, "/subjects/update", &req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let lr = env.login().await;
+    let subject = &lr.subjects[0];
+    assert_eq!(subject.name, "renamed");
+    assert_eq!(subject.description, None);
+    assert_eq!(subject.profiles, profile("ID_2"));
+    assert_eq!(subject.version, 2);
+
+    let req = UpdateSubjectRequest {
+        uuid: uuid.clone(),
+        name: "stale".to_string(),
+        profiles: HashMap::new(),
+        description: None,
+        version: Some(1),
+    };
+    let (status, _) =
+        call_json(&mut env, Method::POST, "/subjects/update", &req).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    env.cleanup().await;
+}
+
+/// group patch tests:
+/// - Subjects can be added and removed individually.
+/// - Changes made against an older version are refused with 409.
+#[tokio::test]
+async fn patch_group() {
+    let mut env = Environment::default().await;
+    let first =
+        create_subject(&mut env, "first", PLATFORM_NAME, &["ID_1"]).await;
+    let second =
+        create_subject(&mut env, "second", PLATFORM_NAME, &["ID_1"]).await;
+
+    let req = CreateGroupRequest {
+        name: "group".to_string(),
+        subjects: vec![first.clone()],
+        description: None,
+        groups: Vec::new(),
+    };
+    let (status, body) =
+        call_json(&mut env, Method::POST, "/groups/create", &req).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let group = serde_json::from_slice::<CreateResponse>(&body)
+        .unwrap()
+        .uuid;
+
+    let req = PatchGroupRequest {
+        uuid: group.clone(),
+        version: Some(0),
+        add_subjects: vec![second.clone()],
+        remove_subjects: vec![first.clone()],
+        ..Default::default()
+    };
+    let (status, _) =
+        call_json(&mut env, Method::PATCH, "/groups/update", &req).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) =
+        call_json(&mut env, Method::PATCH, "/groups/update", &req).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let lr = env.login().await;
+    assert_eq!(lr.groups[0].subjects, vec![second]);
+    assert_eq!(lr.groups[0].name, "group");
+    assert_eq!(lr.groups[0].version, 1);
+
+    env.cleanup().await;
+}
+
+/// concurrent patch tests:
+/// - PATCHes to the same subject or group at the same time either succeed or
+///   are refused with 409, and every one that succeeds is kept.
+#[tokio::test]
+async fn concurrent_patch() {
+    let mut env = Environment::default().await;
+    let uuid =
+        create_subject(&mut env, "subject", PLATFORM_NAME, &["ID_0"]).await;
+    let ids: Vec<String> = (1..=5).map(|i| format!("ID_{i}")).collect();
+
+    let reqs: Vec<PatchSubjectRequest> = ids
+        .iter()
+        .map(|id| PatchSubjectRequest {
+            uuid: uuid.clone(),
+            add_profiles: profile(id),
+            ..Default::default()
+        })
+        .collect();
+    let statuses = concurrent_patches(&env, "/subjects/update", &reqs).await;
+    assert!(statuses
+        .iter()
+        .all(|s| *s == StatusCode::OK || *s == StatusCode::CONFLICT));
+    let added: Vec<&String> = ids
+        .iter()
+        .zip(&statuses)
+        .filter(|(_, s)| **s == StatusCode::OK)
+        .map(|(id, _)| id)
+        .collect();
+
+    let lr = env.login().await;
+    let subject = &lr.subjects[0];
+    assert_eq!(subject.version, added.len() as u64);
+    let profiles = &subject.profiles[PLATFORM_NAME];
+    assert!(added.iter().all(|id| profiles.contains(id)));
+    assert_eq!(profiles.len(), added.len() + 1);
+
+    let req = CreateGroupRequest {
+        name: "group".to_string(),
+        subjects: Vec::new(),
+        description: None,
+        groups: Vec::new(),
+    };
+    let (_, body) =
+        call_json(&mut env, Method::POST, "/groups/create", &req).await;
+    let group = serde_json::from_slice::<CreateResponse>(&body)
+        .unwrap()
+        .uuid;
+    let reqs: Vec<PatchGroupRequest> = (1..=5)
+        .map(|i| PatchGroupRequest {
+            uuid: group.clone(),
+            name: Some(format!("group {i}")),
+            ..Default::default()
+        })
+        .collect();
+    let statuses = concurrent_patches(&env, "/groups/update", &reqs).await;
+    assert!(statuses
+        .iter()
+        .all(|s| *s == StatusCode::OK || *s == StatusCode::CONFLICT));
+    let renamed = statuses.iter().filter(|s| **s == StatusCode::OK).count();
+
+    let lr = env.login().await;
+    assert_eq!(lr.groups[0].version, renamed as u64);
+
+    env.cleanup().await;
+}
@@ -0,0 +1,340 @@
+mod common;
+use std::collections::HashMap;
+
+use axum::http::Method;
+use axum::http::StatusCode;
+use common::{create_subject_with_content, fetch, Environment};
+use instrumentality::concepts::acl::{Role, Visibility};
+use instrumentality::concepts::data::Data;
+use instrumentality::concepts::user::User;
+use instrumentality::config;
+use instrumentality::routes::groups::create::CreateGroupRequest;
+use instrumentality::routes::groups::share::GroupVisibilityRequest;
+use instrumentality::routes::response::{
+    CreateResponse, LoginResponse, ViewResponse,
+};
+use instrumentality::routes::subjects::create::CreateSubjectRequest;
+use instrumentality::routes::subjects::share::{
+    ShareSubjectRequest, SubjectVisibilityRequest,
+};
+
+use crate::common::{create_mock_content, TEST_ENVIRONMENT_CONFIG};
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+
+// Content posted by the profile with a body and media.
+fn content_by(name: &str) -> Data {
+    let mut content = create_mock_content(name, PLATFORM_NAME);
+    if let Data::Content { body, media, .. } = &mut content {
+        *body = Some(format!("Posted by {name} & friends."));
+        *media = Some(vec!["https://example.com/1.jpg".to_string()]);
+    }
+    content
+}
+
+async fn make_public(env: &mut Environment, uuid: &str) {
+    let key = env.user_key.clone();
+    let req = SubjectVisibilityRequest {
+        uuid: uuid.to_string(),
+        visibility: Visibility::Public,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _, _) = fetch(
+        env,
+        Method::POST,
+        "/subjects/visibility",
+        Some(&key),
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+/// public_view tests:
+/// - Public subjects can be viewed without a key.
+/// - Asking for a subject that isn't public is an error naming it.
+/// - The subjects in a public group can be viewed through it if they are
+///   public, or if they belong to the group's owner and aren't private.
+#[tokio::test]
+async fn public_view() {
+    let mut env = Environment::default().await;
+    let key = env.user_key.clone();
+
+    let public = create_subject_with_content(
+        &mut env,
+        "public",
+        PLATFORM_NAME,
+        &["public"],
+        vec![content_by("public")],
+    )
+    .await;
+    let shared = create_subject_with_content(
+        &mut env,
+        "shared",
+        PLATFORM_NAME,
+        &["shared"],
+        vec![content_by("shared")],
+    )
+    .await;
+    make_public(&mut env, &public).await;
+
+    let uri = format!("/public/view?subjects={public}");
+    let (status, _, body) =
+        fetch(&mut env, Method::GET, &uri, None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let vr: ViewResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(vr.view_data.subject_data.len(), 1);
+    let profile = &vr.view_data.subject_data[0].platforms[0].profiles[0];
+    assert_eq!(profile.content.len(), 1);
+
+    let uri = format!("/public/view?subjects={public},{shared}");
+    let (status, _, body) =
+        fetch(&mut env, Method::GET, &uri, None, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(String::from_utf8_lossy(&body).contains(&shared));
+
+    let req = CreateGroupRequest {
+        name: "group".to_string(),
+        subjects: vec![public.clone(), shared.clone()],
+        description: None,
+        groups: Vec::new(),
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    fetch(
+        &mut env,
+        Method::POST,
+        "/groups/create",
+        Some(&key),
+        Some(body),
+    )
+    .await;
+    let (_, _, body) =
+        fetch(&mut env, Method::GET, "/user/login", Some(&key), None).await;
+    let lr: LoginResponse = serde_json::from_slice(&body).unwrap();
+    let group = lr.groups[0].uuid.clone();
+
+    let uri = format!("/public/view?groups={group}");
+    let (status, _, _) = fetch(&mut env, Method::GET, &uri, None, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let req = GroupVisibilityRequest {
+        uuid: group.clone(),
+        visibility: Visibility::Public,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _, _) = fetch(
+        &mut env,
+        Method::POST,
+        "/groups/visibility",
+        Some(&key),
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, body) =
+        fetch(&mut env, Method::GET, &uri, None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let vr: ViewResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(vr.view_data.subject_data.len(), 2);
+
+    let req = SubjectVisibilityRequest {
+        uuid: shared.clone(),
+        visibility: Visibility::Private,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    fetch(
+        &mut env,
+        Method::POST,
+        "/subjects/visibility",
+        Some(&key),
+        Some(body),
+    )
+    .await;
+    let (_, _, body) = fetch(&mut env, Method::GET, &uri, None, None).await;
+    let vr: ViewResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(vr.view_data.subject_data.len(), 1);
+    assert_eq!(vr.view_data.subject_data[0].subject.uuid, public);
+
+    env.cleanup().await;
+}
+
+/// A public group only serves the subjects of other users that are public.
+#[tokio::test]
+async fn public_group_of_others() {
+    let mut env = Environment::default().await;
+    let (other, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other).await;
+    let key = env.user_key.clone();
+
+    let req = CreateSubjectRequest {
+        name: "theirs".to_string(),
+        profiles: HashMap::new(),
+        description: None,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _, body) = fetch(
+        &mut env,
+        Method::POST,
+        "/subjects/create",
+        Some(&other_key),
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let theirs = serde_json::from_slice::<CreateResponse>(&body)
+        .unwrap()
+        .uuid;
+
+    let req = ShareSubjectRequest {
+        uuid: theirs.clone(),
+        user: env.user.uuid.clone(),
+        role: Role::Viewer,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _, _) = fetch(
+        &mut env,
+        Method::POST,
+        "/subjects/share",
+        Some(&other_key),
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let req = CreateGroupRequest {
+        name: "group".to_string(),
+        subjects: vec![theirs.clone()],
+        description: None,
+        groups: Vec::new(),
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _, body) = fetch(
+        &mut env,
+        Method::POST,
+        "/groups/create",
+        Some(&key),
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let group = serde_json::from_slice::<CreateResponse>(&body)
+        .unwrap()
+        .uuid;
+    let req = GroupVisibilityRequest {
+        uuid: group.clone(),
+        visibility: Visibility::Public,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    fetch(
+        &mut env,
+        Method::POST,
+        "/groups/visibility",
+        Some(&key),
+        Some(body),
+    )
+    .await;
+
+    let uri = format!("/public/view?groups={group}");
+    let (status, _, body) =
+        fetch(&mut env, Method::GET, &uri, None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let vr: ViewResponse = serde_json::from_slice(&body).unwrap();
+    assert!(vr.view_data.subject_data.is_empty());
+
+    let req = SubjectVisibilityRequest {
+        uuid: theirs.clone(),
+        visibility: Visibility::Public,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    fetch(
+        &mut env,
+        Method::POST,
+        "/subjects/visibility",
+        Some(&other_key),
+        Some(body),
+    )
+    .await;
+    let (_, _, body) = fetch(&mut env, Method::GET, &uri, None, None).await;
+    let vr: ViewResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(vr.view_data.subject_data.len(), 1);
+    assert_eq!(vr.view_data.subject_data[0].subject.uuid, theirs);
+
+    env.cleanup().await;
+}
+
+/// public_feed tests:
+/// - The content of public subjects is served as Atom, RSS and JSON Feed
+///   documents with the right content types.
+/// - A feed must be asked for in a format.
+#[tokio::test]
+async fn public_feed() {
+    let mut env = Environment::default().await;
+    let public = create_subject_with_content(
+        &mut env,
+        "public",
+        PLATFORM_NAME,
+        &["public"],
+        vec![content_by("public")],
+    )
+    .await;
+    make_public(&mut env, &public).await;
+
+    for (format, content_type) in [
+        ("atom", "application/atom+xml"),
+        ("rss", "application/rss+xml"),
+        ("json", "application/feed+json"),
+    ] {
+        let uri = format!("/public/feed?subjects={public}&format={format}");
+        let (status, ct, body) =
+            fetch(&mut env, Method::GET, &uri, None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(ct.starts_with(content_type));
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("https://example.com/1.jpg"));
+        if format == "json" {
+            let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(
+                json["items"][0]["content_text"],
+                "Posted by public & friends."
+            );
+        } else {
+            assert!(body.contains("Posted by public &amp; friends."));
+        }
+    }
+
+    let uri = format!("/public/feed?subjects={public}");
+    let (status, _, _) = fetch(&mut env, Method::GET, &uri, None, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    env.cleanup().await;
+}
+
+/// public_rate_limit tests:
+/// - Clients are limited to the configured number of public requests a minute.
+#[tokio::test]
+async fn public_rate_limit() {
+    let mut config = config::open(TEST_ENVIRONMENT_CONFIG).unwrap();
+    config.public.requests_per_min = 2;
+    let mut env = Environment::with_config(config).await;
+    let public = create_subject_with_content(
+        &mut env,
+        "public",
+        PLATFORM_NAME,
+        &["public"],
+        vec![content_by("public")],
+    )
+    .await;
+    make_public(&mut env, &public).await;
+
+    let uri = format!("/public/view?subjects={public}");
+    for _ in 0..2 {
+        let (status, _, _) =
+            fetch(&mut env, Method::GET, &uri, None, None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _, _) = fetch(&mut env, Method::GET, &uri, None, None).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    env.cleanup().await;
+}
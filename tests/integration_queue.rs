@@ -4,7 +4,11 @@ use axum::http::Method;
 use axum::http::Request;
 use axum::http::StatusCode;
 use chrono::Utc;
-use common::Environment;
+use common::{
+    call_json, confirm_queue, create_subject, take_job, take_job_as,
+    Environment,
+};
+use instrumentality::routes::response::QueueResponse;
 use tower::Service;
 
 use crate::common::create_mock_content;
@@ -13,12 +17,14 @@ use crate::common::create_mock_presence;
 /// queue_entry_created tests:
 /// - Subject is created upon post request.
 /// - Queue entry is created upon subject creation with profiles.
+/// - The queue entry isn't handed out until its platform ID is confirmed.
 /// - Fetching from the queue with square bracket syntax
 ///   (/queue?platforms=[PLATFORM_1]) yields queue item.
 #[tokio::test]
 async fn queue_entry_created() {
     use std::collections::HashMap;
 
+    use instrumentality::routes::response::ErrorResponse;
     use instrumentality::routes::response::OkResponse;
     use instrumentality::routes::response::QueueResponse;
     use instrumentality::routes::subjects::create::CreateSubjectRequest;
@@ -75,6 +81,28 @@ async fn queue_entry_created() {
 
     assert_eq!(res.status(), StatusCode::OK);
 
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let er: ErrorResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(er.response, "ERROR".to_string());
+
+    confirm_queue(&env).await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/queue?platforms=[PLATFORM_1]")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
     let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
     let qr: QueueResponse = serde_json::from_slice(&body).unwrap();
 
@@ -135,6 +163,8 @@ async fn queue_entry_created_alternate_syntax() {
 
     assert_eq!(okr.response, "OK".to_string());
 
+    confirm_queue(&env).await;
+
     let res = env
         .app
         .call(
@@ -213,6 +243,8 @@ async fn queue_locks_entry() {
 
     assert_eq!(okr.response, "OK".to_string());
 
+    confirm_queue(&env).await;
+
     let res = env
         .app
         .call(
@@ -317,6 +349,8 @@ async fn queue_add_unlocks_entry() {
 
     assert_eq!(okr.response, "OK".to_string());
 
+    confirm_queue(&env).await;
+
     let res = env
         .app
         .call(
@@ -400,13 +434,13 @@ async fn queue_add_unlocks_entry() {
 /// queue_add_meta_data_modifies_platform_id tests:
 /// - Subject is created upon post request.
 /// - Queue entry is created upon subject creation with profiles.
-/// - Fetching from the queue with square bracket syntax
-///   (/queue?platforms=[PLATFORM_1]) yields queue item.
+/// - Fetching from the resolution queue (/queue/resolve?platforms=PLATFORM_1)
+///   yields the unconfirmed queue item.
 /// - Adding the item by the given queue item's ID succeeds.
+/// - Providing meta data for a user updates their queue entry to include a
+///   unique platform ID separate from a username and confirms it.
 /// - The queue item is immediately available in the queue again after being
 ///   added.
-/// - Providing meta data for a user updates their queue entry to include a
-///   unique platform ID separate from a username.
 #[tokio::test]
 async fn queue_add_meta_data_modifies_platform_id() {
     use std::collections::HashMap;
@@ -461,7 +495,7 @@ async fn queue_add_meta_data_modifies_platform_id() {
             Request::builder()
                 .method(Method::GET)
                 .header("X-API-KEY", &env.user_key)
-                .uri(format!("/queue?platforms={}", PLATFORM_NAME))
+                .uri(format!("/queue/resolve?platforms={}", PLATFORM_NAME))
                 .body(Body::empty())
                 .unwrap(),
         )
@@ -608,6 +642,8 @@ async fn queue_lock_times_out() {
 
     assert_eq!(okr.response, "OK".to_string());
 
+    confirm_queue(&env).await;
+
     let res = env
         .app
         .call(
@@ -663,3 +699,444 @@ async fn queue_lock_times_out() {
 
     env.cleanup().await;
 }
+
+async fn create_subject_and_take_job(env: &mut Environment) -> QueueResponse {
+    create_subject(env, "test", "PLATFORM_1", &["TEST_USER_1"]).await;
+    confirm_queue(env).await;
+    take_job(env).await.unwrap()
+}
+
+/// queue_heartbeat_extends_lock tests:
+/// - A lock holder can send a heartbeat for its job.
+/// - The job is still locked after the lock timeout period if heartbeats are
+///   sent.
+/// - A heartbeat for a job that isn't held is rejected.
+#[tokio::test]
+async fn queue_heartbeat_extends_lock() {
+    use instrumentality::routes::queue::HeartbeatRequest;
+
+    let mut env = Environment::default().await;
+
+    let qr = create_subject_and_take_job(&mut env).await;
+    let heartbeat = HeartbeatRequest {
+        queue_id: qr.queue_id.clone(),
+    };
+
+    let timeout = std::time::Duration::from_secs(
+        env.config.settings.queue_timeout_secs.try_into().unwrap(),
+    );
+    for _ in 0..4 {
+        tokio::time::sleep(timeout / 2).await;
+        let status =
+            call_json(&mut env, Method::POST, "/queue/heartbeat", &heartbeat)
+                .await
+                .0;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    assert!(take_job(&mut env).await.is_none());
+
+    let heartbeat = HeartbeatRequest {
+        queue_id: "not a queue ID".to_string(),
+    };
+    let status =
+        call_json(&mut env, Method::POST, "/queue/heartbeat", &heartbeat)
+            .await
+            .0;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    env.cleanup().await;
+}
+
+/// queue_release tests:
+/// - A job released without a reason is immediately available again.
+/// - A job released with a reason enough times in a row is backed off and not
+///   handed out again.
+#[tokio::test]
+async fn queue_release() {
+    use instrumentality::routes::queue::ReleaseRequest;
+
+    let mut env = Environment::default().await;
+
+    let qr = create_subject_and_take_job(&mut env).await;
+    let release = ReleaseRequest {
+        queue_id: qr.queue_id.clone(),
+        reason: None,
+    };
+    let status = call_json(&mut env, Method::POST, "/queue/release", &release)
+        .await
+        .0;
+    assert_eq!(status, StatusCode::OK);
+
+    for _ in 0..env.config.settings.queue_backoff_after {
+        let qr = take_job(&mut env).await.unwrap();
+        let release = ReleaseRequest {
+            queue_id: qr.queue_id,
+            reason: Some("rate limited".to_string()),
+        };
+        let status =
+            call_json(&mut env, Method::POST, "/queue/release", &release)
+                .await
+                .0;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    assert!(take_job(&mut env).await.is_none());
+
+    env.cleanup().await;
+}
+
+/// queue_batch tests:
+/// - Several jobs can be leased at once with /queue?count=N.
+/// - Leasing more than the maximum number of jobs at once is rejected.
+/// - Data for every leased job can be submitted to /add in one request.
+/// - A batch submitting the same job twice is rejected.
+#[tokio::test]
+async fn queue_batch() {
+    use std::collections::HashMap;
+
+    use instrumentality::concepts::data::Datas;
+    use instrumentality::routes::add::AddRequest;
+    use instrumentality::routes::response::QueueBatchResponse;
+    use instrumentality::routes::subjects::create::CreateSubjectRequest;
+
+    let mut env = Environment::default().await;
+
+    let usernames = vec!["TEST_USER_1".to_string(), "TEST_USER_2".to_string()];
+    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), usernames.clone());
+    let new_subject = CreateSubjectRequest {
+        name: "test".to_string(),
+        profiles,
+        description: None,
+    };
+    let status =
+        call_json(&mut env, Method::POST, "/subjects/create", &new_subject)
+            .await
+            .0;
+    assert_eq!(status, StatusCode::CREATED);
+    confirm_queue(&env).await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/queue?platforms=PLATFORM_1&count=1000")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/queue?platforms=PLATFORM_1&count=5")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let qbr: QueueBatchResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(qbr.response, "OK");
+    assert_eq!(qbr.jobs.len(), 2);
+    for job in &qbr.jobs {
+        assert!(usernames.contains(&job.platform_id));
+    }
+
+    let jobs: Vec<Datas> = qbr
+        .jobs
+        .iter()
+        .map(|job| Datas {
+            queue_id: Some(job.queue_id.clone()),
+            data: vec![create_mock_content(&job.platform_id, "PLATFORM_1")],
+        })
+        .collect();
+
+    let duplicated = AddRequest::Batch {
+        jobs: vec![jobs[0].clone(), jobs[0].clone()],
+    };
+    let status = call_json(&mut env, Method::POST, "/add", &duplicated)
+        .await
+        .0;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let batch = AddRequest::Batch { jobs };
+    let status = call_json(&mut env, Method::POST, "/add", &batch).await.0;
+    assert_eq!(status, StatusCode::CREATED);
+
+    env.cleanup().await;
+}
+
+/// queue_refresh_policy tests:
+/// - No more jobs are leased for a platform than its lease cap.
+/// - A profile that has just been refreshed is not handed out again within the
+///   platform's minimum interval.
+#[tokio::test]
+async fn queue_refresh_policy() {
+    use std::collections::HashMap;
+
+    use instrumentality::concepts::data::Datas;
+    use instrumentality::config;
+    use instrumentality::config::PlatformPolicy;
+    use instrumentality::routes::subjects::create::CreateSubjectRequest;
+
+    use crate::common::TEST_ENVIRONMENT_CONFIG;
+
+    let mut config = config::open(TEST_ENVIRONMENT_CONFIG).unwrap();
+    config.queue.platforms.insert(
+        "PLATFORM_1".to_string(),
+        PlatformPolicy {
+            min_interval_secs: 3600,
+            max_leases: Some(1),
+            ..Default::default()
+        },
+    );
+    let mut env = Environment::with_config(config).await;
+
+    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    profiles.insert(
+        "PLATFORM_1".to_string(),
+        vec!["TEST_USER_1".to_string(), "TEST_USER_2".to_string()],
+    );
+    let new_subject = CreateSubjectRequest {
+        name: "test".to_string(),
+        profiles,
+        description: None,
+    };
+    let status =
+        call_json(&mut env, Method::POST, "/subjects/create", &new_subject)
+            .await
+            .0;
+    assert_eq!(status, StatusCode::CREATED);
+    confirm_queue(&env).await;
+
+    for _ in 0..2 {
+        let qr = take_job(&mut env).await.unwrap();
+        assert!(take_job(&mut env).await.is_none());
+
+        let datas = Datas {
+            queue_id: Some(qr.queue_id),
+            data: vec![create_mock_content(&qr.platform_id, "PLATFORM_1")],
+        };
+        let status = call_json(&mut env, Method::POST, "/add", &datas).await.0;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    assert!(take_job(&mut env).await.is_none());
+
+    env.cleanup().await;
+}
+
+/// queue_username_history tests:
+/// - The username hint for a job is the username in the most recent metadata,
+///   even when content was added more recently.
+/// - Every username seen for the profile is given with the job, most recently
+///   seen first.
+#[tokio::test]
+async fn queue_username_history() {
+    use std::collections::HashMap;
+
+    use instrumentality::concepts::data::Data;
+    use instrumentality::concepts::data::Datas;
+    use instrumentality::routes::subjects::create::CreateSubjectRequest;
+
+    const USER_PLATFORM_ID: &str = "123456789";
+
+    let mut env = Environment::default().await;
+
+    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    profiles
+        .insert("PLATFORM_1".to_string(), vec![USER_PLATFORM_ID.to_string()]);
+    let new_subject = CreateSubjectRequest {
+        name: "test".to_string(),
+        profiles,
+        description: None,
+    };
+    let status =
+        call_json(&mut env, Method::POST, "/subjects/create", &new_subject)
+            .await
+            .0;
+    assert_eq!(status, StatusCode::CREATED);
+    confirm_queue(&env).await;
+
+    let meta = |username: &str, retrieved_at| Data::Meta {
+        id: USER_PLATFORM_ID.to_string(),
+        platform: "PLATFORM_1".to_string(),
+        username: username.to_string(),
+        private: false,
+        suspended_or_banned: false,
+        retrieved_at,
+        display_name: None,
+        profile_picture: None,
+        bio: None,
+        verified: None,
+        references: None,
+        link: None,
+        added_by: None,
+        added_at: None,
+    };
+    let datas = Datas {
+        queue_id: None,
+        data: vec![
+            meta("OLD_NAME", Utc::now() - chrono::Duration::days(2)),
+            meta("NEW_NAME", Utc::now() - chrono::Duration::days(1)),
+            create_mock_content(USER_PLATFORM_ID, "PLATFORM_1"),
+        ],
+    };
+    let status = call_json(&mut env, Method::POST, "/add", &datas).await.0;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let qr = take_job(&mut env).await.unwrap();
+    assert_eq!(qr.platform_id, USER_PLATFORM_ID);
+    assert_eq!(qr.platform_username_hint, "NEW_NAME");
+    let history: Vec<&str> = qr
+        .platform_username_history
+        .iter()
+        .map(|p| p.username.as_str())
+        .collect();
+    assert_eq!(history, vec!["NEW_NAME", "OLD_NAME"]);
+
+    env.cleanup().await;
+}
+
+async fn fairness_environment(
+    config: instrumentality::config::IConfig,
+) -> Environment {
+    use std::collections::HashMap;
+
+    use instrumentality::routes::subjects::create::CreateSubjectRequest;
+
+    let mut env = Environment::with_config(config).await;
+
+    let usernames = (1..=4).map(|i| format!("TEST_USER_{i}")).collect();
+    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    profiles.insert("PLATFORM_1".to_string(), usernames);
+    let new_subject = CreateSubjectRequest {
+        name: "test".to_string(),
+        profiles,
+        description: None,
+    };
+    let status =
+        call_json(&mut env, Method::POST, "/subjects/create", &new_subject)
+            .await
+            .0;
+    assert_eq!(status, StatusCode::CREATED);
+    confirm_queue(&env).await;
+    env
+}
+
+/// queue_provider_lease_cap tests:
+/// - A provider holding as many jobs as the per-provider cap is not given
+///   another, even when more are available.
+/// - Other providers are still given jobs.
+#[tokio::test]
+async fn queue_provider_lease_cap() {
+    use instrumentality::concepts::user::User;
+    use instrumentality::config;
+
+    use crate::common::TEST_ENVIRONMENT_CONFIG;
+
+    let mut config = config::open(TEST_ENVIRONMENT_CONFIG).unwrap();
+    config.queue.max_leases_per_provider = Some(2);
+    let mut env = fairness_environment(config).await;
+
+    let (other, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other).await;
+
+    assert!(take_job(&mut env).await.is_some());
+    assert!(take_job(&mut env).await.is_some());
+    assert!(take_job(&mut env).await.is_none());
+    assert!(take_job_as(&mut env, &other_key).await.is_some());
+
+    env.cleanup().await;
+}
+
+/// queue_round_robin tests:
+/// - A provider is given jobs while no other provider is asking for them.
+/// - Once another provider asks for jobs on the same platform, the two take
+///   turns.
+#[tokio::test]
+async fn queue_round_robin() {
+    use instrumentality::concepts::user::User;
+    use instrumentality::config;
+
+    use crate::common::TEST_ENVIRONMENT_CONFIG;
+
+    let mut config = config::open(TEST_ENVIRONMENT_CONFIG).unwrap();
+    config.queue.round_robin = true;
+    let mut env = fairness_environment(config).await;
+
+    let (other, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other).await;
+
+    assert!(take_job(&mut env).await.is_some());
+    assert!(take_job_as(&mut env, &other_key).await.is_some());
+    assert!(take_job(&mut env).await.is_some());
+    assert!(take_job(&mut env).await.is_none());
+    assert!(take_job_as(&mut env, &other_key).await.is_some());
+
+    env.cleanup().await;
+}
+
+/// queue_round_robin_turns tests:
+/// - A provider leasing several jobs at once is given all of them in its turn.
+/// - A provider that was given nothing on its turn doesn't hold up the others.
+#[tokio::test]
+async fn queue_round_robin_turns() {
+    use instrumentality::concepts::user::User;
+    use instrumentality::config;
+    use instrumentality::routes::queue::ReleaseRequest;
+    use instrumentality::routes::response::QueueBatchResponse;
+
+    use crate::common::TEST_ENVIRONMENT_CONFIG;
+
+    let mut config = config::open(TEST_ENVIRONMENT_CONFIG).unwrap();
+    config.queue.round_robin = true;
+    let mut env = fairness_environment(config).await;
+
+    let (other, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other).await;
+
+    assert!(take_job_as(&mut env, &other_key).await.is_some());
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/queue?platforms=PLATFORM_1&count=3")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let qbr: QueueBatchResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(qbr.jobs.len(), 3);
+
+    assert!(take_job_as(&mut env, &other_key).await.is_none());
+
+    let release = ReleaseRequest {
+        queue_id: qbr.jobs[0].queue_id.clone(),
+        reason: None,
+    };
+    let status = call_json(&mut env, Method::POST, "/queue/release", &release)
+        .await
+        .0;
+    assert_eq!(status, StatusCode::OK);
+    assert!(take_job(&mut env).await.is_some());
+
+    env.cleanup().await;
+}
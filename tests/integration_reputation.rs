@@ -0,0 +1,156 @@
+mod common;
+use axum::body::Body;
+use axum::http::Method;
+use axum::http::Request;
+use axum::http::StatusCode;
+use axum::Router;
+use chrono::Utc;
+use common::Environment;
+use instrumentality::concepts::data::{Data, Datas};
+use instrumentality::concepts::reputation::Reputation;
+use instrumentality::concepts::user::User;
+use instrumentality::routes::response::ReputationResponse;
+use tower::Service;
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+const USERNAME: &str = "TEST_USER_1";
+
+fn meta(bio: Option<&str>) -> Data {
+    Data::Meta {
+        id: USERNAME.to_string(),
+        platform: PLATFORM_NAME.to_string(),
+        username: USERNAME.to_string(),
+        private: false,
+        suspended_or_banned: false,
+        retrieved_at: Utc::now(),
+        display_name: Some(USERNAME.to_string()),
+        profile_picture: None,
+        bio: bio.map(|b| b.to_string()),
+        verified: None,
+        references: None,
+        link: None,
+        added_by: None,
+        added_at: None,
+    }
+}
+
+async fn add(app: &mut Router, key: &str, data: Data) {
+    let datas = Datas {
+        queue_id: None,
+        data: vec![data],
+    };
+
+    let res = app
+        .call(
+            Request::builder()
+                .method(Method::POST)
+                .header("X-API-KEY", key)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    mime::APPLICATION_JSON.as_ref(),
+                )
+                .uri("/add")
+                .body(Body::from(serde_json::to_vec(&datas).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+}
+
+async fn reputations(app: &mut Router, admin_key: &str) -> Vec<Reputation> {
+    let res = app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", admin_key)
+                .uri("/reputation")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let rr: ReputationResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(rr.response, "OK");
+    rr.reputations
+}
+
+/// reputation tests:
+/// - A provider omitting a field that two other providers filled in for the
+///   same profile is recorded as having omitted it.
+/// - A field filled in by only one other provider doesn't count against a
+///   provider.
+/// - Submitting the same data again doesn't count against anyone again.
+/// - The providers that filled in the field pass the checks they were judged
+///   on.
+/// - Administrators can review reputations.
+#[tokio::test]
+async fn reputation() {
+    let mut env = Environment::default().await;
+
+    let (lazy_user, lazy_key) = User::new("lazy");
+    Environment::inject_account(&env.config, &lazy_user).await;
+    let (second_user, second_key) = User::new("second");
+    Environment::inject_account(&env.config, &second_user).await;
+    let (admin, admin_key) = User::new_admin("admin");
+    Environment::inject_account(&env.config, &admin).await;
+
+    let key = env.user_key.clone();
+    let lazy_meta = meta(None);
+    add(&mut env.app, &key, meta(Some("Hello."))).await;
+    add(&mut env.app, &lazy_key, lazy_meta.clone()).await;
+
+    let rs = reputations(&mut env.app, &admin_key).await;
+    assert!(rs.iter().all(|r| r.omitted_fields == 0));
+
+    add(&mut env.app, &second_key, meta(Some("Hello."))).await;
+    add(&mut env.app, &lazy_key, lazy_meta).await;
+
+    let rs = reputations(&mut env.app, &admin_key).await;
+    let lazy = rs.iter().find(|r| r.user == lazy_user.uuid).unwrap();
+    assert_eq!(lazy.checks, 2);
+    assert_eq!(lazy.omitted_fields, 1);
+    let thorough = rs.iter().find(|r| r.user == env.user.uuid).unwrap();
+    assert_eq!(thorough.omitted_fields, 0);
+    assert!(thorough.score > lazy.score);
+
+    env.cleanup().await;
+}
+
+/// reputation_unauthorised tests:
+/// - Instrumentality serves an error response to non-administrators requesting
+///   /reputation.
+#[tokio::test]
+async fn reputation_unauthorised() {
+    use instrumentality::routes::response::ErrorResponse;
+
+    let mut env = Environment::default().await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/reputation")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let er: ErrorResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(er.response, "ERROR");
+
+    env.cleanup().await;
+}
@@ -0,0 +1,165 @@
+mod common;
+use axum::http::Method;
+use axum::http::StatusCode;
+use common::{call, create_subject, Environment};
+use instrumentality::concepts::resolution::MappingStatus;
+use instrumentality::concepts::user::User;
+use instrumentality::routes::admin::resolutions::ClearConflictRequest;
+use instrumentality::routes::queue::ResolveRequest;
+use instrumentality::routes::response::{
+    QueueResponse, ResolutionResponse, UsernameMappingsResponse,
+};
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+const USERNAME: &str = "TEST_USER_1";
+const USER_PLATFORM_ID: &str = "123456789";
+
+// Leases the unconfirmed item for the username and reports a platform ID for
+// it.
+async fn resolve(env: &mut Environment, platform_id: &str) -> MappingStatus {
+    let key = env.user_key.clone();
+    let uri = format!("/queue/resolve?platforms={PLATFORM_NAME}");
+    let (status, body) = call(env, Method::GET, &uri, &key, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let qr: QueueResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(qr.platform_id, USERNAME);
+
+    let req = ResolveRequest {
+        queue_id: qr.queue_id,
+        platform_id: platform_id.to_string(),
+        evidence: "https://platform.example/TEST_USER_1".to_string(),
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, body) =
+        call(env, Method::POST, "/queue/resolve", &key, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let rr: ResolutionResponse = serde_json::from_slice(&body).unwrap();
+    rr.status
+}
+
+/// resolution tests:
+/// - Unconfirmed queue items are not handed out by /queue.
+/// - Unconfirmed queue items can be leased through /queue/resolve.
+/// - Reporting a platform ID for the username rewrites every subject and queue
+///   item that refers to it and records the mapping.
+/// - Reporting a different platform ID for the same username later is flagged
+///   as a conflict and not applied.
+/// - Administrators can list mappings, filtered by status.
+/// - Administrators can clear a conflict by choosing the platform ID, which is
+///   applied to the subjects still referring to the username.
+#[tokio::test]
+async fn resolution() {
+    let mut env = Environment::default().await;
+    let (admin, admin_key) = User::new_admin("admin");
+    Environment::inject_account(&env.config, &admin).await;
+
+    create_subject(&mut env, "first", PLATFORM_NAME, &[USERNAME]).await;
+    create_subject(&mut env, "second", PLATFORM_NAME, &[USERNAME]).await;
+
+    let key = env.user_key.clone();
+    let uri = format!("/queue?platforms={PLATFORM_NAME}");
+    let (_, body) = call(&mut env, Method::GET, &uri, &key, None).await;
+    assert!(serde_json::from_slice::<QueueResponse>(&body).is_err());
+
+    let status = resolve(&mut env, USER_PLATFORM_ID).await;
+    assert_eq!(status, MappingStatus::Applied);
+
+    let (_, body) = call(&mut env, Method::GET, &uri, &key, None).await;
+    let qr: QueueResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(qr.platform_id, USER_PLATFORM_ID);
+
+    let (_, body) = call(
+        &mut env,
+        Method::GET,
+        "/admin/resolutions",
+        &admin_key,
+        None,
+    )
+    .await;
+    let umr: UsernameMappingsResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(umr.mappings.len(), 1);
+    assert_eq!(umr.mappings[0].platform_id, USER_PLATFORM_ID);
+    assert_eq!(umr.mappings[0].subjects.len(), 2);
+
+    create_subject(&mut env, "third", PLATFORM_NAME, &[USERNAME]).await;
+    let status = resolve(&mut env, "987654321").await;
+    assert_eq!(status, MappingStatus::Conflict);
+
+    let (status, body) = call(
+        &mut env,
+        Method::GET,
+        "/admin/resolutions?status=conflict",
+        &admin_key,
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let umr: UsernameMappingsResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(umr.mappings.len(), 1);
+    assert_eq!(umr.mappings[0].platform_id, "987654321");
+
+    let (status, _) =
+        call(&mut env, Method::GET, "/admin/resolutions", &key, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let req = ClearConflictRequest {
+        platform: PLATFORM_NAME.to_string(),
+        username: USERNAME.to_string(),
+        platform_id: "987654321".to_string(),
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let uri = "/admin/resolutions/clear";
+    let (status, _) =
+        call(&mut env, Method::POST, uri, &key, Some(body.clone())).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, res) =
+        call(&mut env, Method::POST, uri, &admin_key, Some(body.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    let rr: ResolutionResponse = serde_json::from_slice(&res).unwrap();
+    assert_eq!(rr.status, MappingStatus::Applied);
+    let (status, _) =
+        call(&mut env, Method::POST, uri, &admin_key, Some(body)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, body) = call(
+        &mut env,
+        Method::GET,
+        "/admin/resolutions?status=conflict",
+        &admin_key,
+        None,
+    )
+    .await;
+    let umr: UsernameMappingsResponse = serde_json::from_slice(&body).unwrap();
+    assert!(umr.mappings.is_empty());
+
+    let lr = env.login().await;
+    let third = lr.subjects.iter().find(|s| s.name == "third").unwrap();
+    assert_eq!(third.profiles[PLATFORM_NAME], vec!["987654321".to_string()]);
+
+    env.cleanup().await;
+}
+
+/// resolution_to_own_id tests:
+/// - Reporting the username itself as the platform ID confirms the queue item
+///   without removing the profile from the subject.
+#[tokio::test]
+async fn resolution_to_own_id() {
+    let mut env = Environment::default().await;
+
+    create_subject(&mut env, "first", PLATFORM_NAME, &[USERNAME]).await;
+
+    let status = resolve(&mut env, USERNAME).await;
+    assert_eq!(status, MappingStatus::Applied);
+
+    let key = env.user_key.clone();
+    let uri = format!("/queue?platforms={PLATFORM_NAME}");
+    let (_, body) = call(&mut env, Method::GET, &uri, &key, None).await;
+    let qr: QueueResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(qr.platform_id, USERNAME);
+
+    let lr = env.login().await;
+    let first = lr.subjects.iter().find(|s| s.name == "first").unwrap();
+    assert_eq!(first.profiles[PLATFORM_NAME], vec![USERNAME.to_string()]);
+
+    env.cleanup().await;
+}
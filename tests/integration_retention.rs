@@ -0,0 +1,116 @@
+mod common;
+use axum::body::Body;
+use axum::http::Method;
+use axum::http::Request;
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use common::Environment;
+use instrumentality::concepts::data::{Data, Datas};
+use instrumentality::concepts::user::User;
+use instrumentality::config;
+use instrumentality::config::RetentionRule;
+use instrumentality::routes::response::RetentionResponse;
+use tower::Service;
+
+use crate::common::create_mock_presence;
+use crate::common::TEST_ENVIRONMENT_CONFIG;
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+const USERNAME: &str = "TEST_USER_1";
+
+fn old_presence(days: i64, mins: i64) -> Data {
+    Data::Presence {
+        id: USERNAME.to_string(),
+        platform: PLATFORM_NAME.to_string(),
+        presence_type: "live".to_string(),
+        retrieved_at: Utc::now() - Duration::days(days)
+            + Duration::minutes(mins),
+        added_by: None,
+        added_at: None,
+    }
+}
+
+/// retention_dry_run tests:
+/// - The dry run counts the presence older than a rule's retention period.
+/// - The dry run counts the intervals the expired presence would be aggregated
+///   into.
+/// - Presence within the retention period is not counted.
+/// - Non-administrators cannot review retention.
+#[tokio::test]
+async fn retention_dry_run() {
+    let mut config = config::open(TEST_ENVIRONMENT_CONFIG).unwrap();
+    config.retention.rules.push(RetentionRule {
+        platform: PLATFORM_NAME.to_string(),
+        presence_type: Some("live".to_string()),
+        content_type: None,
+        keep_days: 30,
+        aggregate: true,
+        interval_gap_secs: 600,
+    });
+    let mut env = Environment::with_config(config).await;
+    let (admin, admin_key) = User::new_admin("admin");
+    Environment::inject_account(&env.config, &admin).await;
+
+    let datas = Datas {
+        queue_id: None,
+        data: vec![
+            old_presence(40, 0),
+            old_presence(40, 5),
+            old_presence(40, 60),
+            create_mock_presence(USERNAME, PLATFORM_NAME),
+        ],
+    };
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::POST)
+                .header("X-API-KEY", &env.user_key)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    mime::APPLICATION_JSON.as_ref(),
+                )
+                .uri("/add")
+                .body(Body::from(serde_json::to_vec(&datas).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/admin/retention")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &admin_key)
+                .uri("/admin/retention")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let rr: RetentionResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(rr.reports.len(), 1);
+    assert_eq!(rr.reports[0].expired, 3);
+    assert_eq!(rr.reports[0].intervals, 2);
+
+    env.cleanup().await;
+}
@@ -0,0 +1,283 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::Method;
+use axum::http::Request;
+use axum::http::StatusCode;
+use chrono::Utc;
+use common::{
+    call, call_json, create_subject, create_subject_with_content, Environment,
+};
+use instrumentality::concepts::data::Data;
+use tower::Service;
+use uuid::Uuid;
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+const USERNAME: &str = "TEST_USER_1";
+
+fn create_content_with_body(body: &str) -> Data {
+    Data::Content {
+        id: USERNAME.to_string(),
+        platform: PLATFORM_NAME.to_string(),
+        content_type: "post".to_string(),
+        retrieved_at: Utc::now(),
+        content_id: Uuid::new_v4().to_string(),
+        deleted: Some(false),
+        retrieved_from: None,
+        created_at: None,
+        body: Some(body.to_string()),
+        media: None,
+        references: None,
+        added_by: None,
+        added_at: None,
+    }
+}
+
+/// search tests:
+/// - Content added for a profile of one of the user's subjects is found by
+///   searching for a word in its body.
+/// - Content that does not contain the word is not returned.
+/// - Pages past the last result are empty, however large.
+#[tokio::test]
+async fn search() {
+    use instrumentality::routes::response::SearchResponse;
+
+    let mut env = Environment::default().await;
+
+    create_subject_with_content(
+        &mut env,
+        USERNAME,
+        PLATFORM_NAME,
+        &[USERNAME],
+        ["The election is next week.", "Nothing to see here."]
+            .iter()
+            .map(|b| create_content_with_body(b))
+            .collect(),
+    )
+    .await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/search?q=election")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let sr: SearchResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(sr.response, "OK");
+    assert_eq!(sr.results.len(), 1);
+    match &sr.results[0].data {
+        Data::Content { body, .. } => {
+            assert_eq!(body.as_deref(), Some("The election is next week."))
+        }
+        _ => panic!("Expected Data::Content."),
+    }
+
+    let uri = format!("/search?q=election&page={}", u64::MAX);
+    let (status, body) = call_json(&mut env, Method::GET, &uri, &()).await;
+    assert_eq!(status, StatusCode::OK);
+    let sr: SearchResponse = serde_json::from_slice(&body).unwrap();
+    assert!(sr.results.is_empty());
+
+    env.cleanup().await;
+}
+
+/// search_phrase tests:
+/// - A quoted phrase only matches content containing the exact phrase.
+#[tokio::test]
+async fn search_phrase() {
+    use instrumentality::routes::response::SearchResponse;
+
+    let mut env = Environment::default().await;
+
+    create_subject_with_content(
+        &mut env,
+        USERNAME,
+        PLATFORM_NAME,
+        &[USERNAME],
+        ["Polling day is here.", "Here is the day of polling."]
+            .iter()
+            .map(|b| create_content_with_body(b))
+            .collect(),
+    )
+    .await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/search?q=%22polling%20day%22")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let sr: SearchResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(sr.response, "OK");
+    assert_eq!(sr.results.len(), 1);
+
+    env.cleanup().await;
+}
+
+/// search_other_users_subjects tests:
+/// - Content belonging only to another user's subjects is not returned.
+#[tokio::test]
+async fn search_other_users_subjects() {
+    use instrumentality::concepts::user::User;
+    use instrumentality::routes::response::SearchResponse;
+
+    let mut env = Environment::default().await;
+
+    create_subject_with_content(
+        &mut env,
+        USERNAME,
+        PLATFORM_NAME,
+        &[USERNAME],
+        ["The election is next week."]
+            .iter()
+            .map(|b| create_content_with_body(b))
+            .collect(),
+    )
+    .await;
+
+    let (other_user, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other_user).await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &other_key)
+                .uri("/search?q=election")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let sr: SearchResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(sr.response, "OK");
+    assert!(sr.results.is_empty());
+
+    env.cleanup().await;
+}
+
+/// search_no_query tests:
+/// - Instrumentality serves an error response to requests to /search with no
+///   query.
+#[tokio::test]
+async fn search_no_query() {
+    use instrumentality::routes::response::ErrorResponse;
+
+    let mut env = Environment::default().await;
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri("/search")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let er: ErrorResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(er.response, "ERROR");
+
+    env.cleanup().await;
+}
+
+/// search_invisible_subjects tests:
+/// - Instrumentality serves a not found response to requests to /search for
+///   another user's subject.
+#[tokio::test]
+async fn search_invisible_subjects() {
+    use instrumentality::concepts::user::User;
+    use instrumentality::routes::response::ErrorResponse;
+
+    let mut env = Environment::default().await;
+
+    let uuid = create_subject(&mut env, "subject", PLATFORM_NAME, &[]).await;
+    let (other_user, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other_user).await;
+
+    let uri = format!("/search?q=election&subjects={uuid}");
+    let (status, body) =
+        call(&mut env, Method::GET, &uri, &other_key, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let er: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert!(er.text.contains(&uuid));
+
+    env.cleanup().await;
+}
+
+/// search_range tests:
+/// - The range applies to when content was created, falling back to when it was
+///   retrieved.
+#[tokio::test]
+async fn search_range() {
+    use chrono::{Duration, SecondsFormat};
+    use instrumentality::routes::response::SearchResponse;
+
+    let mut env = Environment::default().await;
+
+    let mut old = create_content_with_body("The election was last month.");
+    if let Data::Content { created_at, .. } = &mut old {
+        *created_at = Some(Utc::now() - Duration::days(30));
+    }
+    let new = create_content_with_body("The election is next week.");
+    create_subject_with_content(
+        &mut env,
+        USERNAME,
+        PLATFORM_NAME,
+        &[USERNAME],
+        vec![old, new],
+    )
+    .await;
+
+    let key = env.user_key.clone();
+    let from = (Utc::now() - Duration::days(1))
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+    let uri = format!("/search?q=election&from={from}");
+    let (status, body) = call(&mut env, Method::GET, &uri, &key, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let sr: SearchResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(sr.results.len(), 1);
+    match &sr.results[0].data {
+        Data::Content { body, .. } => {
+            assert_eq!(body.as_deref(), Some("The election is next week."))
+        }
+        _ => panic!("Expected Data::Content."),
+    }
+
+    env.cleanup().await;
+}
@@ -0,0 +1,378 @@
+mod common;
+use std::collections::HashMap;
+
+use axum::http::Method;
+use axum::http::StatusCode;
+use common::{
+    call, create_group, create_group_as, create_subject, Environment,
+};
+use instrumentality::concepts::acl::{Role, Visibility};
+use instrumentality::concepts::user::User;
+use instrumentality::routes::groups::update::PatchGroupRequest;
+use instrumentality::routes::response::{
+    ErrorResponse, LoginResponse, ViewResponse,
+};
+use instrumentality::routes::subjects::share::{
+    ShareSubjectRequest, SubjectVisibilityRequest, UnshareSubjectRequest,
+};
+use instrumentality::routes::subjects::update::UpdateSubjectRequest;
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+
+async fn login(env: &mut Environment, key: &str) -> LoginResponse {
+    let (status, body) = call(env, Method::GET, "/user/login", key, None).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn share(
+    env: &mut Environment,
+    uuid: &str,
+    user: &str,
+    role: Role,
+) -> StatusCode {
+    let key = env.user_key.clone();
+    let req = ShareSubjectRequest {
+        uuid: uuid.to_string(),
+        user: user.to_string(),
+        role,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    call(env, Method::POST, "/subjects/share", &key, Some(body))
+        .await
+        .0
+}
+
+async fn update(env: &mut Environment, uuid: &str, key: &str) -> StatusCode {
+    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    profiles.insert(PLATFORM_NAME.to_string(), vec!["123".to_string()]);
+    let req = UpdateSubjectRequest {
+        uuid: uuid.to_string(),
+        name: "renamed".to_string(),
+        profiles,
+        description: None,
+        version: None,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    call(env, Method::POST, "/subjects/update", key, Some(body))
+        .await
+        .0
+}
+
+async fn view(env: &mut Environment, uuid: &str, key: &str) -> StatusCode {
+    let uri = format!("/view?subjects={uuid}");
+    let (status, body) = call(env, Method::GET, &uri, key, None).await;
+    if status == StatusCode::OK {
+        let vr: ViewResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(vr.view_data.subject_data[0].subject.uuid, uuid);
+    }
+    status
+}
+
+async fn set_visibility(
+    env: &mut Environment,
+    uuid: &str,
+    visibility: Visibility,
+) -> StatusCode {
+    let key = env.user_key.clone();
+    let req = SubjectVisibilityRequest {
+        uuid: uuid.to_string(),
+        visibility,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    call(env, Method::POST, "/subjects/visibility", &key, Some(body))
+        .await
+        .0
+}
+
+/// share tests:
+/// - Subjects and groups shared with a user are listed separately by
+///   /user/login.
+/// - Editors can update a subject, viewers can only view it and other users can
+///   do neither.
+/// - Only the owner can share a subject.
+/// - Unsharing a subject removes access to it.
+#[tokio::test]
+async fn share_subject() {
+    let mut env = Environment::default().await;
+    let (other, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other).await;
+    let key = env.user_key.clone();
+
+    let uuid = create_subject(&mut env, "shared", PLATFORM_NAME, &[]).await;
+
+    assert_eq!(
+        view(&mut env, &uuid, &other_key).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        update(&mut env, &uuid, &other_key).await,
+        StatusCode::BAD_REQUEST
+    );
+
+    let status = share(&mut env, &uuid, &other.uuid, Role::Viewer).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(view(&mut env, &uuid, &other_key).await, StatusCode::OK);
+    assert_eq!(
+        update(&mut env, &uuid, &other_key).await,
+        StatusCode::BAD_REQUEST
+    );
+
+    let status = share(&mut env, &uuid, &other.uuid, Role::Editor).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(update(&mut env, &uuid, &other_key).await, StatusCode::OK);
+
+    let lr = login(&mut env, &other_key).await;
+    assert!(lr.subjects.is_empty());
+    assert_eq!(lr.shared_subjects.len(), 1);
+    assert_eq!(lr.shared_subjects[0].name, "renamed");
+
+    let req = ShareSubjectRequest {
+        uuid: uuid.clone(),
+        user: env.user.uuid.clone(),
+        role: Role::Editor,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _) = call(
+        &mut env,
+        Method::POST,
+        "/subjects/share",
+        &other_key,
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let req = UnshareSubjectRequest {
+        uuid: uuid.clone(),
+        user: other.uuid.clone(),
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _) = call(
+        &mut env,
+        Method::POST,
+        "/subjects/unshare",
+        &key,
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        view(&mut env, &uuid, &other_key).await,
+        StatusCode::NOT_FOUND
+    );
+    assert!(login(&mut env, &other_key).await.shared_subjects.is_empty());
+
+    env.cleanup().await;
+}
+
+/// Groups shared with a user are listed by /user/login without their subjects.
+#[tokio::test]
+async fn share_group() {
+    let mut env = Environment::default().await;
+    let (other, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other).await;
+    let key = env.user_key.clone();
+
+    let subject = create_subject(&mut env, "member", PLATFORM_NAME, &[]).await;
+
+    let group = create_group(&mut env, "team", &[&subject], &[]).await;
+
+    let req = serde_json::json!({
+        "uuid": group,
+        "user": other.uuid,
+        "role": "viewer"
+    });
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _) =
+        call(&mut env, Method::POST, "/groups/share", &key, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let lr = login(&mut env, &other_key).await;
+    assert_eq!(lr.shared_groups.len(), 1);
+    assert!(lr.shared_subjects.is_empty());
+
+    env.cleanup().await;
+}
+
+// The UUIDs of the subjects the user with the key sees through /view.
+async fn view_uuids(
+    env: &mut Environment,
+    uri: &str,
+    key: &str,
+) -> Vec<String> {
+    let (status, body) = call(env, Method::GET, uri, key, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let vr: ViewResponse = serde_json::from_slice(&body).unwrap();
+    vr.view_data
+        .subject_data
+        .into_iter()
+        .map(|sd| sd.subject.uuid)
+        .collect()
+}
+
+/// group_access tests:
+/// - A user can't put a subject they can't read in a group, whether creating or
+///   updating it.
+/// - Reading a group, or a group nested in it, only selects the subjects in it
+///   that the reader can read themselves.
+/// - An editor of a group can update it even if it has a nested group they
+///   can't read.
+#[tokio::test]
+async fn group_access() {
+    let mut env = Environment::default().await;
+    let (other, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other).await;
+    let key = env.user_key.clone();
+
+    let hidden = create_subject(&mut env, "hidden", PLATFORM_NAME, &[]).await;
+    let shared = create_subject(&mut env, "shared", PLATFORM_NAME, &[]).await;
+    share(&mut env, &shared, &other.uuid, Role::Viewer).await;
+
+    let (status, _) =
+        create_group_as(&mut env, "stolen", &[&hidden], &[], &other_key).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, own) =
+        create_group_as(&mut env, "own", &[&shared], &[], &other_key).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let own = own.unwrap();
+
+    let req = PatchGroupRequest {
+        uuid: own.clone(),
+        add_subjects: vec![hidden.clone()],
+        ..Default::default()
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _) = call(
+        &mut env,
+        Method::PATCH,
+        "/groups/update",
+        &other_key,
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        view(&mut env, &hidden, &other_key).await,
+        StatusCode::NOT_FOUND
+    );
+
+    let (_, nested) =
+        create_group_as(&mut env, "nested", &[&hidden], &[], &key).await;
+    let nested = nested.unwrap();
+    let (_, team) =
+        create_group_as(&mut env, "team", &[&shared], &[&nested], &key).await;
+    let team = team.unwrap();
+    let req = serde_json::json!({
+        "uuid": team,
+        "user": other.uuid,
+        "role": "viewer"
+    });
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _) =
+        call(&mut env, Method::POST, "/groups/share", &key, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let uri = format!("/view?groups={team}");
+    assert_eq!(
+        view_uuids(&mut env, &uri, &other_key).await,
+        vec![shared.clone()]
+    );
+    assert_eq!(view_uuids(&mut env, &uri, &key).await.len(), 2);
+
+    let (status, outer) =
+        create_group_as(&mut env, "outer", &[], &[&team], &other_key).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let uri = format!("/view?groups={}", outer.unwrap());
+    assert_eq!(view_uuids(&mut env, &uri, &other_key).await, vec![shared]);
+
+    let req = serde_json::json!({
+        "uuid": team,
+        "user": other.uuid,
+        "role": "editor"
+    });
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _) =
+        call(&mut env, Method::POST, "/groups/share", &key, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let req = PatchGroupRequest {
+        uuid: team,
+        name: Some("renamed".to_string()),
+        ..Default::default()
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _) = call(
+        &mut env,
+        Method::PATCH,
+        "/groups/update",
+        &other_key,
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    env.cleanup().await;
+}
+
+/// visibility tests:
+/// - Asking /view for a subject that can't be seen is an error naming it, even
+///   if other subjects asked for are visible.
+/// - Public subjects can be seen by every user.
+/// - Private subjects can only be seen by their owner, even if shared.
+/// - Only the owner can change the visibility of a subject.
+#[tokio::test]
+async fn visibility() {
+    let mut env = Environment::default().await;
+    let (other, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other).await;
+    let key = env.user_key.clone();
+
+    let public = create_subject(&mut env, "public", PLATFORM_NAME, &[]).await;
+    let private = create_subject(&mut env, "private", PLATFORM_NAME, &[]).await;
+
+    let uri = format!("/view?subjects={public},{private}");
+    let (status, body) =
+        call(&mut env, Method::GET, &uri, &other_key, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let er: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert!(er.text.contains(&public));
+    assert!(er.text.contains(&private));
+
+    let status = set_visibility(&mut env, &public, Visibility::Public).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(view(&mut env, &public, &other_key).await, StatusCode::OK);
+
+    let (status, body) =
+        call(&mut env, Method::GET, &uri, &other_key, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let er: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert!(!er.text.contains(&public));
+    assert!(er.text.contains(&private));
+
+    share(&mut env, &private, &other.uuid, Role::Viewer).await;
+    assert_eq!(view(&mut env, &private, &other_key).await, StatusCode::OK);
+    set_visibility(&mut env, &private, Visibility::Private).await;
+    assert_eq!(
+        view(&mut env, &private, &other_key).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(view(&mut env, &private, &key).await, StatusCode::OK);
+    assert!(login(&mut env, &other_key).await.shared_subjects.is_empty());
+
+    let req = SubjectVisibilityRequest {
+        uuid: private.clone(),
+        visibility: Visibility::Public,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _) = call(
+        &mut env,
+        Method::POST,
+        "/subjects/visibility",
+        &other_key,
+        Some(body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    env.cleanup().await;
+}
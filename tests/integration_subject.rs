@@ -301,6 +301,7 @@ async fn subject_update() {
         name: USERNAME_PLATFORM_1.to_string(),
         profiles,
         description: None,
+        version: None,
     };
 
     let res = env
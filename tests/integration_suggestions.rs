@@ -0,0 +1,189 @@
+mod common;
+use axum::body::Body;
+use axum::http::Method;
+use axum::http::Request;
+use axum::http::StatusCode;
+use chrono::Utc;
+use common::{confirm_queue, Environment};
+use tower::Service;
+
+/// subject_suggestions tests:
+/// - A link reference in a profile's metadata is suggested as a profile for the
+///   subject.
+/// - Accepting the suggestion adds the profile to the subject.
+/// - Accepting the suggestion creates a queue item for the profile.
+#[tokio::test]
+async fn subject_suggestions() {
+    use std::collections::HashMap;
+
+    use instrumentality::concepts::data::Data;
+    use instrumentality::concepts::data::Datas;
+    use instrumentality::routes::response::CreateResponse;
+    use instrumentality::routes::response::LoginResponse;
+    use instrumentality::routes::response::QueueResponse;
+    use instrumentality::routes::response::SuggestionsResponse;
+    use instrumentality::routes::subjects::create::CreateSubjectRequest;
+    use instrumentality::routes::subjects::suggestions::AcceptSuggestionRequest;
+
+    const PLATFORM_NAME: &str = "PLATFORM_1";
+    const OTHER_PLATFORM_NAME: &str = "PLATFORM_2";
+    const USERNAME: &str = "TEST_USER_1";
+    const OTHER_USERNAME: &str = "TEST_USER_2";
+
+    let mut env = Environment::default().await;
+
+    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
+    profiles.insert(PLATFORM_NAME.to_string(), vec![USERNAME.to_string()]);
+    let new_subject = CreateSubjectRequest {
+        name: USERNAME.to_string(),
+        profiles,
+        description: None,
+    };
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/subjects/create")
+                .header("X-API-KEY", &env.user_key)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    mime::APPLICATION_JSON.as_ref(),
+                )
+                .body(Body::from(serde_json::to_vec(&new_subject).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let cr: CreateResponse = serde_json::from_slice(&body).unwrap();
+    let uuid = cr.uuid;
+
+    let mut references = HashMap::new();
+    references.insert(
+        "link".to_string(),
+        format!("{}:{}", OTHER_PLATFORM_NAME, OTHER_USERNAME),
+    );
+    let datas = Datas {
+        queue_id: None,
+        data: vec![Data::Meta {
+            id: USERNAME.to_string(),
+            platform: PLATFORM_NAME.to_string(),
+            username: USERNAME.to_string(),
+            private: false,
+            suspended_or_banned: false,
+            retrieved_at: Utc::now(),
+            display_name: None,
+            profile_picture: None,
+            bio: None,
+            verified: None,
+            references: Some(references),
+            link: None,
+            added_by: None,
+            added_at: None,
+        }],
+    };
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::POST)
+                .header("X-API-KEY", &env.user_key)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    mime::APPLICATION_JSON.as_ref(),
+                )
+                .uri("/add")
+                .body(Body::from(serde_json::to_vec(&datas).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri(format!("/subjects/suggestions?subject={}", uuid))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let sr: SuggestionsResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(sr.response, "OK");
+    assert_eq!(sr.suggestions.len(), 1);
+    assert_eq!(sr.suggestions[0].platform, OTHER_PLATFORM_NAME);
+    assert_eq!(sr.suggestions[0].id, OTHER_USERNAME);
+    assert!(sr.suggestions[0].confidence > 0.0);
+    assert!(!sr.suggestions[0].evidence.is_empty());
+
+    let accept = AcceptSuggestionRequest {
+        subject: uuid,
+        platform: OTHER_PLATFORM_NAME.to_string(),
+        id: OTHER_USERNAME.to_string(),
+    };
+
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/subjects/suggestions/accept")
+                .header("X-API-KEY", &env.user_key)
+                .header(
+                    axum::http::header::CONTENT_TYPE,
+                    mime::APPLICATION_JSON.as_ref(),
+                )
+                .body(Body::from(serde_json::to_vec(&accept).unwrap()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+
+    let lr: LoginResponse = env.login().await;
+    assert_eq!(
+        lr.subjects[0].profiles.get(OTHER_PLATFORM_NAME),
+        Some(&vec![OTHER_USERNAME.to_string()])
+    );
+
+    confirm_queue(&env).await;
+    let res = env
+        .app
+        .call(
+            Request::builder()
+                .method(Method::GET)
+                .header("X-API-KEY", &env.user_key)
+                .uri(format!("/queue?platforms={}", OTHER_PLATFORM_NAME))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let qr: QueueResponse = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(qr.platform, OTHER_PLATFORM_NAME);
+    assert_eq!(qr.platform_id, OTHER_USERNAME);
+
+    env.cleanup().await;
+}
@@ -0,0 +1,216 @@
+mod common;
+use axum::http::Method;
+use axum::http::StatusCode;
+use common::{add_content, call, create_subject, Environment};
+use instrumentality::concepts::acl::Role;
+use instrumentality::concepts::user::User;
+use instrumentality::routes::response::{
+    SearchResponse, TagsResponse, ViewResponse,
+};
+use instrumentality::routes::subjects::share::ShareSubjectRequest;
+use instrumentality::routes::tags::{ContentTagsRequest, SubjectTagsRequest};
+use instrumentality::routes::view::ProfileData;
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+
+async fn tag_subject(
+    env: &mut Environment,
+    method: Method,
+    uuid: &str,
+    tags: &[&str],
+) -> StatusCode {
+    let key = env.user_key.clone();
+    let req = SubjectTagsRequest {
+        uuid: uuid.to_string(),
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    call(env, method, "/tags/subjects", &key, Some(body))
+        .await
+        .0
+}
+
+async fn tag_content(
+    env: &mut Environment,
+    content_id: &str,
+    tags: &[&str],
+) -> StatusCode {
+    let key = env.user_key.clone();
+    tag_content_as(env, Method::POST, content_id, tags, &key).await
+}
+
+async fn tag_content_as(
+    env: &mut Environment,
+    method: Method,
+    content_id: &str,
+    tags: &[&str],
+    key: &str,
+) -> StatusCode {
+    let req = ContentTagsRequest {
+        platform: PLATFORM_NAME.to_string(),
+        content_id: content_id.to_string(),
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    call(env, method, "/tags/content", key, Some(body)).await.0
+}
+
+async fn view(env: &mut Environment, query: &str) -> ViewResponse {
+    let key = env.user_key.clone();
+    let uri = format!("/view?{query}");
+    let (status, body) = call(env, Method::GET, &uri, &key, None).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).unwrap()
+}
+
+fn profile(vr: &ViewResponse) -> &ProfileData {
+    &vr.view_data.subject_data[0].platforms[0].profiles[0]
+}
+
+/// subject tag tests:
+/// - Tags are normalised and added to the subject.
+/// - /view can select subjects by tag alone.
+/// - Removing a tag stops the subject being selected by it.
+/// - Empty tags are rejected.
+#[tokio::test]
+async fn subject_tags() {
+    let mut env = Environment::default().await;
+
+    let election =
+        create_subject(&mut env, "election", PLATFORM_NAME, &["ID_1"]).await;
+    create_subject(&mut env, "other", PLATFORM_NAME, &["ID_2"]).await;
+
+    let status =
+        tag_subject(&mut env, Method::POST, &election, &[" Election "]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let vr = view(&mut env, "tags=election").await;
+    assert_eq!(vr.view_data.subject_data.len(), 1);
+    assert_eq!(vr.view_data.subject_data[0].subject.uuid, election);
+    assert_eq!(vr.view_data.subject_data[0].subject.tags, vec!["election"]);
+
+    let key = env.user_key.clone();
+    let (status, body) = call(&mut env, Method::GET, "/tags", &key, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let tr: TagsResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(tr.tags.len(), 1);
+    assert_eq!(tr.tags[0].subjects, 1);
+
+    let status =
+        tag_subject(&mut env, Method::DELETE, &election, &["election"]).await;
+    assert_eq!(status, StatusCode::OK);
+    let vr = view(&mut env, "tags=election").await;
+    assert!(vr.view_data.subject_data.is_empty());
+
+    let status = tag_subject(&mut env, Method::POST, &election, &[""]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    env.cleanup().await;
+}
+
+/// content tag tests:
+/// - Tags on content are returned by /view and /search.
+/// - /view can be narrowed to content with a tag.
+/// - Content that doesn't exist can't be tagged.
+#[tokio::test]
+async fn content_tags() {
+    let mut env = Environment::default().await;
+
+    let subject =
+        create_subject(&mut env, "subject", PLATFORM_NAME, &["ID_1"]).await;
+    let tagged = add_content(&mut env, "ID_1", PLATFORM_NAME).await;
+    add_content(&mut env, "ID_1", PLATFORM_NAME).await;
+
+    let status = tag_content(&mut env, &tagged, &["needs review"]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let vr = view(&mut env, &format!("subjects={subject}")).await;
+    assert_eq!(profile(&vr).content.len(), 2);
+    assert_eq!(profile(&vr).content_tags[&tagged], vec!["needs review"]);
+
+    let query = format!("subjects={subject}&content_tags=needs%20review");
+    let vr = view(&mut env, &query).await;
+    assert_eq!(profile(&vr).content.len(), 1);
+
+    let status = tag_content(&mut env, "missing", &["needs review"]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let key = env.user_key.clone();
+    let uri = "/search?q=story&content_tags=needs%20review";
+    let (status, body) = call(&mut env, Method::GET, uri, &key, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let sr: SearchResponse = serde_json::from_slice(&body).unwrap();
+    assert!(sr.results.iter().all(|r| r.tags == vec!["needs review"]));
+
+    env.cleanup().await;
+}
+
+/// content tag access tests:
+/// - Content about profiles of subjects a user can't see can't be tagged by
+///   them, and its tags aren't counted by /tags for them.
+/// - Anyone who can see the content can remove their own tags from it, but only
+///   editors can remove the tags of others.
+#[tokio::test]
+async fn content_tag_access() {
+    let mut env = Environment::default().await;
+    let (other, other_key) = User::new("other");
+    Environment::inject_account(&env.config, &other).await;
+    let key = env.user_key.clone();
+
+    let subject =
+        create_subject(&mut env, "subject", PLATFORM_NAME, &["ID_1"]).await;
+    let content = add_content(&mut env, "ID_1", PLATFORM_NAME).await;
+    let status = tag_content(&mut env, &content, &["owner"]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let status =
+        tag_content_as(&mut env, Method::POST, &content, &["x"], &other_key)
+            .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, body) =
+        call(&mut env, Method::GET, "/tags", &other_key, None).await;
+    let tr: TagsResponse = serde_json::from_slice(&body).unwrap();
+    assert!(tr.tags.is_empty());
+
+    let req = ShareSubjectRequest {
+        uuid: subject.clone(),
+        user: other.uuid.clone(),
+        role: Role::Viewer,
+    };
+    let body = serde_json::to_vec(&req).unwrap();
+    let (status, _) =
+        call(&mut env, Method::POST, "/subjects/share", &key, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let status = tag_content_as(
+        &mut env,
+        Method::POST,
+        &content,
+        &["viewer"],
+        &other_key,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) =
+        call(&mut env, Method::GET, "/tags", &other_key, None).await;
+    let tr: TagsResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(tr.tags.len(), 2);
+
+    let tags = &["owner", "viewer"];
+    let status =
+        tag_content_as(&mut env, Method::DELETE, &content, tags, &other_key)
+            .await;
+    assert_eq!(status, StatusCode::OK);
+    let vr = view(&mut env, &format!("subjects={subject}")).await;
+    assert_eq!(profile(&vr).content_tags[&content], vec!["owner"]);
+
+    tag_content_as(&mut env, Method::POST, &content, &["viewer"], &other_key)
+        .await;
+    let status =
+        tag_content_as(&mut env, Method::DELETE, &content, tags, &key).await;
+    assert_eq!(status, StatusCode::OK);
+    let vr = view(&mut env, &format!("subjects={subject}")).await;
+    assert!(!profile(&vr).content_tags.contains_key(&content));
+
+    env.cleanup().await;
+}
@@ -0,0 +1,150 @@
+mod common;
+use axum::http::Method;
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use common::{call_json, create_group, create_subject, queued, Environment};
+use instrumentality::concepts::trash::TrashKind;
+use instrumentality::database;
+use instrumentality::routes::groups::delete::DeleteGroupRequest;
+use instrumentality::routes::groups::restore::RestoreGroupRequest;
+use instrumentality::routes::response::TrashResponse;
+use instrumentality::routes::subjects::delete::DeleteSubjectRequest;
+use instrumentality::routes::subjects::restore::RestoreSubjectRequest;
+use mongodb::bson::{self, doc, Document};
+
+const PLATFORM_NAME: &str = "PLATFORM_1";
+const USERNAME: &str = "TEST_USER_1";
+
+async fn trash(env: &mut Environment) -> TrashResponse {
+    let (status, body) = call_json(env, Method::GET, "/trash", &()).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).unwrap()
+}
+
+/// subject trash tests:
+/// - A deleted subject is listed in the trash and removed from its groups and
+///   the queue.
+/// - Restoring it puts it back in its groups and queues its profiles again.
+/// - A subject can't be restored twice.
+#[tokio::test]
+async fn restore_subject() {
+    let mut env = Environment::default().await;
+
+    let subject =
+        create_subject(&mut env, "subject", PLATFORM_NAME, &[USERNAME]).await;
+    let group = create_group(&mut env, "group", &[&subject], &[]).await;
+
+    let req = DeleteSubjectRequest {
+        uuid: subject.clone(),
+    };
+    let (status, _) =
+        call_json(&mut env, Method::DELETE, "/subjects/delete", &req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let tr = trash(&mut env).await;
+    assert_eq!(tr.trash.len(), 1);
+    assert_eq!(tr.trash[0].uuid, subject);
+    assert_eq!(tr.trash[0].kind, TrashKind::Subject);
+    assert!(tr.trash[0].purge_at > tr.trash[0].deleted_at);
+
+    let lr = env.login().await;
+    assert!(lr.subjects.is_empty());
+    assert!(lr.groups[0].subjects.is_empty());
+    assert!(!queued(&mut env).await);
+
+    let req = RestoreSubjectRequest {
+        uuid: subject.clone(),
+    };
+    let (status, _) =
+        call_json(&mut env, Method::POST, "/subjects/restore", &req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let lr = env.login().await;
+    assert_eq!(lr.subjects[0].uuid, subject);
+    let g = lr.groups.iter().find(|g| g.uuid == group).unwrap();
+    assert_eq!(g.subjects, vec![subject.clone()]);
+    assert!(trash(&mut env).await.trash.is_empty());
+    assert!(queued(&mut env).await);
+
+    let (status, _) =
+        call_json(&mut env, Method::POST, "/subjects/restore", &req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    env.cleanup().await;
+}
+
+/// group trash tests:
+/// - A deleted group is removed from the groups it was nested in.
+/// - Restoring it nests it in them again.
+#[tokio::test]
+async fn restore_group() {
+    let mut env = Environment::default().await;
+
+    let child = create_group(&mut env, "child", &[], &[]).await;
+    let parent = create_group(&mut env, "parent", &[], &[&child]).await;
+
+    let req = DeleteGroupRequest {
+        uuid: child.clone(),
+    };
+    let (status, _) =
+        call_json(&mut env, Method::DELETE, "/groups/delete", &req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let lr = env.login().await;
+    assert_eq!(lr.groups.len(), 1);
+    assert!(lr.groups[0].groups.is_empty());
+
+    let req = RestoreGroupRequest {
+        uuid: child.clone(),
+    };
+    let (status, _) =
+        call_json(&mut env, Method::POST, "/groups/restore", &req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let lr = env.login().await;
+    assert_eq!(lr.groups.len(), 2);
+    let p = lr.groups.iter().find(|g| g.uuid == parent).unwrap();
+    assert_eq!(p.groups, vec![child]);
+
+    env.cleanup().await;
+}
+
+/// expired trash tests:
+/// - A subject that has been in the trash for longer than the purge window is
+///   not listed and can't be restored, even before it is purged.
+#[tokio::test]
+async fn restore_expired() {
+    let mut env = Environment::default().await;
+
+    let subject = create_subject(&mut env, "subject", PLATFORM_NAME, &[]).await;
+    let req = DeleteSubjectRequest {
+        uuid: subject.clone(),
+    };
+    let (status, _) =
+        call_json(&mut env, Method::DELETE, "/subjects/delete", &req).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let days = env.config.trash.purge_after_days + 1;
+    let deleted_at = Utc::now() - Duration::days(days);
+    database::open(&env.config)
+        .await
+        .unwrap()
+        .handle()
+        .await
+        .collection::<Document>("trash")
+        .update_one(
+            doc! {"uuid": &subject},
+            doc! {"$set": {"deleted_at": bson::to_bson(&deleted_at).unwrap()}},
+            None,
+        )
+        .await
+        .unwrap();
+
+    assert!(trash(&mut env).await.trash.is_empty());
+    let req = RestoreSubjectRequest { uuid: subject };
+    let (status, _) =
+        call_json(&mut env, Method::POST, "/subjects/restore", &req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    env.cleanup().await;
+}